@@ -0,0 +1,177 @@
+-- Parameters may now be shared by every part of a model, in which case they are owned by
+-- the model rather than a single part. SQLite cannot relax a NOT NULL constraint in place,
+-- so each parameter table is rebuilt.
+
+CREATE TABLE NewIntRangeParameters (
+    parameter_id INTEGER NOT NULL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    default_value INTEGER NOT NULL,
+    lower INTEGER NOT NULL,
+    upper INTEGER NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES Parts (part_id),
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id),
+    CHECK ((part_id IS NULL) != (model_id IS NULL))
+);
+
+INSERT INTO NewIntRangeParameters (parameter_id, name, default_value, lower, upper, part_id)
+    SELECT parameter_id, name, default_value, lower, upper, part_id FROM IntRangeParameters;
+DROP TABLE IntRangeParameters;
+ALTER TABLE NewIntRangeParameters RENAME TO IntRangeParameters;
+
+CREATE TABLE NewFloatRangeParameters (
+    parameter_id INTEGER NOT NULL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    default_value REAL NOT NULL,
+    lower REAL NOT NULL,
+    upper REAL NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES Parts (part_id),
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id),
+    CHECK ((part_id IS NULL) != (model_id IS NULL))
+);
+
+INSERT INTO NewFloatRangeParameters (parameter_id, name, default_value, lower, upper, part_id)
+    SELECT parameter_id, name, default_value, lower, upper, part_id FROM FloatRangeParameters;
+DROP TABLE FloatRangeParameters;
+ALTER TABLE NewFloatRangeParameters RENAME TO FloatRangeParameters;
+
+CREATE TABLE NewStringLengthParameters (
+    parameter_id INTEGER NOT NULL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    default_value VARCHAR NOT NULL,
+    length INTEGER NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES Parts (part_id),
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id),
+    CHECK ((part_id IS NULL) != (model_id IS NULL))
+);
+
+INSERT INTO NewStringLengthParameters (parameter_id, name, default_value, length, part_id)
+    SELECT parameter_id, name, default_value, length, part_id FROM StringLengthParameters;
+DROP TABLE StringLengthParameters;
+ALTER TABLE NewStringLengthParameters RENAME TO StringLengthParameters;
+
+CREATE TABLE NewBoolParameters (
+    parameter_id INTEGER NOT NULL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    default_value BOOLEAN NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES Parts (part_id),
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id),
+    CHECK ((part_id IS NULL) != (model_id IS NULL))
+);
+
+INSERT INTO NewBoolParameters (parameter_id, name, default_value, part_id)
+    SELECT parameter_id, name, default_value, part_id FROM BoolParameters;
+DROP TABLE BoolParameters;
+ALTER TABLE NewBoolParameters RENAME TO BoolParameters;
+
+-- The list items refer to their parameter, so they are rebuilt along with it. Otherwise dropping the old
+-- parameter table would break their foreign keys. String list items referred to the float list parameters
+-- by mistake, and now refer to the string list parameters. Items left without a parameter are dropped.
+
+CREATE TABLE NewIntListParameters (
+    parameter_id INTEGER NOT NULL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    default_value INTEGER NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES Parts (part_id),
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id),
+    CHECK ((part_id IS NULL) != (model_id IS NULL))
+);
+
+CREATE TABLE NewIntListItems (
+    item_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    value INTEGER NOT NULL,
+    parameter_id INTEGER NOT NULL,
+    FOREIGN KEY (parameter_id)
+        REFERENCES NewIntListParameters (parameter_id)
+);
+
+INSERT INTO NewIntListParameters (parameter_id, name, default_value, part_id)
+    SELECT parameter_id, name, default_value, part_id FROM IntListParameters;
+INSERT INTO NewIntListItems (item_id, value, parameter_id)
+    SELECT item_id, value, parameter_id FROM IntListItems
+    WHERE parameter_id IN (SELECT parameter_id FROM NewIntListParameters);
+DROP TABLE IntListItems;
+DROP TABLE IntListParameters;
+ALTER TABLE NewIntListParameters RENAME TO IntListParameters;
+ALTER TABLE NewIntListItems RENAME TO IntListItems;
+
+CREATE TABLE NewFloatListParameters (
+    parameter_id INTEGER NOT NULL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    default_value REAL NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES Parts (part_id),
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id),
+    CHECK ((part_id IS NULL) != (model_id IS NULL))
+);
+
+CREATE TABLE NewFloatListItems (
+    item_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    value REAL NOT NULL,
+    parameter_id INTEGER NOT NULL,
+    FOREIGN KEY (parameter_id)
+        REFERENCES NewFloatListParameters (parameter_id)
+);
+
+INSERT INTO NewFloatListParameters (parameter_id, name, default_value, part_id)
+    SELECT parameter_id, name, default_value, part_id FROM FloatListParameters;
+INSERT INTO NewFloatListItems (item_id, value, parameter_id)
+    SELECT item_id, value, parameter_id FROM FloatListItems
+    WHERE parameter_id IN (SELECT parameter_id FROM NewFloatListParameters);
+DROP TABLE FloatListItems;
+DROP TABLE FloatListParameters;
+ALTER TABLE NewFloatListParameters RENAME TO FloatListParameters;
+ALTER TABLE NewFloatListItems RENAME TO FloatListItems;
+
+CREATE TABLE NewStringListParameters (
+    parameter_id INTEGER NOT NULL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    default_value VARCHAR NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES Parts (part_id),
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id),
+    CHECK ((part_id IS NULL) != (model_id IS NULL))
+);
+
+CREATE TABLE NewStringListItems (
+    item_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    value VARCHAR NOT NULL,
+    parameter_id INTEGER NOT NULL,
+    FOREIGN KEY (parameter_id)
+        REFERENCES NewStringListParameters (parameter_id)
+);
+
+INSERT INTO NewStringListParameters (parameter_id, name, default_value, part_id)
+    SELECT parameter_id, name, default_value, part_id FROM StringListParameters;
+INSERT INTO NewStringListItems (item_id, value, parameter_id)
+    SELECT item_id, value, parameter_id FROM StringListItems
+    WHERE parameter_id IN (SELECT parameter_id FROM NewStringListParameters);
+DROP TABLE StringListItems;
+DROP TABLE StringListParameters;
+ALTER TABLE NewStringListParameters RENAME TO StringListParameters;
+ALTER TABLE NewStringListItems RENAME TO StringListItems;
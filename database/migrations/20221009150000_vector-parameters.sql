@@ -0,0 +1,25 @@
+CREATE TABLE VectorParameters (
+    parameter_id INTEGER NOT NULL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    element_type VARCHAR NOT NULL,
+    length INTEGER NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES Parts (part_id),
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id),
+    CHECK ((part_id IS NULL) != (model_id IS NULL)),
+    CHECK (element_type IN ('int', 'float'))
+);
+
+CREATE TABLE VectorComponents (
+    component_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    position INTEGER NOT NULL,
+    default_value REAL NOT NULL,
+    lower REAL NOT NULL,
+    upper REAL NOT NULL,
+    parameter_id INTEGER NOT NULL,
+    FOREIGN KEY (parameter_id)
+        REFERENCES VectorParameters (parameter_id)
+);
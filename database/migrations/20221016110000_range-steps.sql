@@ -0,0 +1,5 @@
+-- Range parameters may optionally be restricted to a step from their lower bound, and floats
+-- to a number of decimal places, so that equivalent values share a single cached instance.
+ALTER TABLE IntRangeParameters ADD COLUMN step INTEGER;
+ALTER TABLE FloatRangeParameters ADD COLUMN step REAL;
+ALTER TABLE FloatRangeParameters ADD COLUMN precision INTEGER;
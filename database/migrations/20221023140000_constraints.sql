@@ -0,0 +1,25 @@
+CREATE TABLE Constraints (
+    constraint_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    expression VARCHAR NOT NULL,
+    message VARCHAR NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES Parts (part_id),
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id),
+    CHECK ((part_id IS NULL) != (model_id IS NULL))
+);
+
+-- Parameter ids are unique across every parameter table, so conditions refer to them directly
+CREATE TABLE ParameterConditions (
+    parameter_id INTEGER NOT NULL PRIMARY KEY,
+    expression VARCHAR NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES Parts (part_id),
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id),
+    CHECK ((part_id IS NULL) != (model_id IS NULL))
+);
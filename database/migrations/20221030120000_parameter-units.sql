@@ -0,0 +1,6 @@
+-- Units only apply to numeric parameters, values are in the declared unit unless converted by roost
+ALTER TABLE IntRangeParameters ADD COLUMN unit VARCHAR CHECK (unit IN ('mm', 'inch', 'degrees', 'count'));
+ALTER TABLE FloatRangeParameters ADD COLUMN unit VARCHAR CHECK (unit IN ('mm', 'inch', 'degrees', 'count'));
+ALTER TABLE IntListParameters ADD COLUMN unit VARCHAR CHECK (unit IN ('mm', 'inch', 'degrees', 'count'));
+ALTER TABLE FloatListParameters ADD COLUMN unit VARCHAR CHECK (unit IN ('mm', 'inch', 'degrees', 'count'));
+ALTER TABLE VectorParameters ADD COLUMN unit VARCHAR CHECK (unit IN ('mm', 'inch', 'degrees', 'count'));
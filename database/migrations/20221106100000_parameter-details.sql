@@ -0,0 +1,15 @@
+-- Presentation details shared by every parameter type, parameters are displayed by ascending position
+CREATE TABLE ParameterDetails (
+    parameter_id INTEGER NOT NULL PRIMARY KEY,
+    label VARCHAR NOT NULL,
+    description VARCHAR,
+    section VARCHAR,
+    position INTEGER NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES Parts (part_id),
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id),
+    CHECK ((part_id IS NULL) != (model_id IS NULL))
+);
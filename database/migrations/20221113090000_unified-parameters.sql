@@ -0,0 +1,107 @@
+-- Every parameter is stored in a single table, distinguished by its kind. Values are stored as JSON so
+-- that one column can hold the integers, floats, strings, booleans and vectors of the different kinds.
+CREATE TABLE Parameters (
+    parameter_id INTEGER NOT NULL PRIMARY KEY,
+    kind VARCHAR NOT NULL CHECK (kind IN ('int_range', 'float_range', 'string_length', 'bool', 'int_list', 'float_list', 'string_list', 'int_vector', 'float_vector')),
+    name VARCHAR NOT NULL,
+    label VARCHAR NOT NULL,
+    description VARCHAR,
+    section VARCHAR,
+    position INTEGER NOT NULL,
+    unit VARCHAR CHECK (unit IN ('mm', 'inch', 'degrees', 'count')),
+    condition VARCHAR,
+    default_value VARCHAR NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES Parts (part_id),
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id),
+    CHECK ((part_id IS NULL) != (model_id IS NULL))
+);
+
+-- The bounds, steps, lengths and allowed values of a parameter. Position orders the allowed values
+-- and picks the component of a vector, it is 0 for every other restriction.
+CREATE TABLE ParameterRestrictions (
+    restriction_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind VARCHAR NOT NULL CHECK (kind IN ('lower', 'upper', 'step', 'precision', 'length', 'allowed')),
+    position INTEGER NOT NULL,
+    value VARCHAR NOT NULL,
+    parameter_id INTEGER NOT NULL,
+    FOREIGN KEY (parameter_id)
+        REFERENCES Parameters (parameter_id)
+);
+
+INSERT INTO Parameters (parameter_id, kind, name, label, position, unit, default_value, part_id, model_id)
+    SELECT parameter_id, 'int_range', name, name, parameter_id, unit, json_quote(default_value), part_id, model_id FROM IntRangeParameters
+    UNION ALL
+    SELECT parameter_id, 'float_range', name, name, parameter_id, unit, json_quote(default_value), part_id, model_id FROM FloatRangeParameters
+    UNION ALL
+    SELECT parameter_id, 'string_length', name, name, parameter_id, NULL, json_quote(default_value), part_id, model_id FROM StringLengthParameters
+    UNION ALL
+    SELECT parameter_id, 'bool', name, name, parameter_id, NULL, CASE WHEN default_value THEN 'true' ELSE 'false' END, part_id, model_id FROM BoolParameters
+    UNION ALL
+    SELECT parameter_id, 'int_list', name, name, parameter_id, unit, json_quote(default_value), part_id, model_id FROM IntListParameters
+    UNION ALL
+    SELECT parameter_id, 'float_list', name, name, parameter_id, unit, json_quote(default_value), part_id, model_id FROM FloatListParameters
+    UNION ALL
+    SELECT parameter_id, 'string_list', name, name, parameter_id, NULL, json_quote(default_value), part_id, model_id FROM StringListParameters
+    UNION ALL
+    SELECT parameter_id, element_type || '_vector', name, name, parameter_id, unit,
+        (SELECT json_group_array(CASE WHEN v.element_type = 'int' THEN CAST(default_value AS INTEGER) ELSE default_value END)
+            FROM (SELECT default_value FROM VectorComponents c WHERE c.parameter_id = v.parameter_id ORDER BY position)),
+        part_id, model_id
+        FROM VectorParameters v;
+
+UPDATE Parameters SET
+    label = (SELECT label FROM ParameterDetails d WHERE d.parameter_id = Parameters.parameter_id),
+    description = (SELECT description FROM ParameterDetails d WHERE d.parameter_id = Parameters.parameter_id),
+    section = (SELECT section FROM ParameterDetails d WHERE d.parameter_id = Parameters.parameter_id),
+    position = (SELECT position FROM ParameterDetails d WHERE d.parameter_id = Parameters.parameter_id)
+    WHERE parameter_id IN (SELECT parameter_id FROM ParameterDetails);
+
+UPDATE Parameters SET condition = (SELECT expression FROM ParameterConditions c WHERE c.parameter_id = Parameters.parameter_id);
+
+INSERT INTO ParameterRestrictions (kind, position, value, parameter_id)
+    SELECT 'lower', 0, json_quote(lower), parameter_id FROM IntRangeParameters
+    UNION ALL
+    SELECT 'upper', 0, json_quote(upper), parameter_id FROM IntRangeParameters
+    UNION ALL
+    SELECT 'step', 0, json_quote(step), parameter_id FROM IntRangeParameters WHERE step IS NOT NULL
+    UNION ALL
+    SELECT 'lower', 0, json_quote(lower), parameter_id FROM FloatRangeParameters
+    UNION ALL
+    SELECT 'upper', 0, json_quote(upper), parameter_id FROM FloatRangeParameters
+    UNION ALL
+    SELECT 'step', 0, json_quote(step), parameter_id FROM FloatRangeParameters WHERE step IS NOT NULL
+    UNION ALL
+    SELECT 'precision', 0, json_quote(precision), parameter_id FROM FloatRangeParameters WHERE precision IS NOT NULL
+    UNION ALL
+    SELECT 'length', 0, json_quote(length), parameter_id FROM StringLengthParameters
+    UNION ALL
+    SELECT 'allowed', item_id, json_quote(value), parameter_id FROM IntListItems
+    UNION ALL
+    SELECT 'allowed', item_id, json_quote(value), parameter_id FROM FloatListItems
+    UNION ALL
+    SELECT 'allowed', item_id, json_quote(value), parameter_id FROM StringListItems
+    UNION ALL
+    SELECT 'lower', c.position, json_quote(CASE WHEN v.element_type = 'int' THEN CAST(c.lower AS INTEGER) ELSE c.lower END), c.parameter_id
+        FROM VectorComponents c JOIN VectorParameters v ON v.parameter_id = c.parameter_id
+    UNION ALL
+    SELECT 'upper', c.position, json_quote(CASE WHEN v.element_type = 'int' THEN CAST(c.upper AS INTEGER) ELSE c.upper END), c.parameter_id
+        FROM VectorComponents c JOIN VectorParameters v ON v.parameter_id = c.parameter_id;
+
+DROP TABLE ParameterConditions;
+DROP TABLE ParameterDetails;
+DROP TABLE VectorComponents;
+DROP TABLE VectorParameters;
+DROP TABLE IntListItems;
+DROP TABLE FloatListItems;
+DROP TABLE StringListItems;
+DROP TABLE IntListParameters;
+DROP TABLE FloatListParameters;
+DROP TABLE StringListParameters;
+DROP TABLE IntRangeParameters;
+DROP TABLE FloatRangeParameters;
+DROP TABLE StringLengthParameters;
+DROP TABLE BoolParameters;
@@ -0,0 +1,7 @@
+-- Bumped by plume whenever it re-indexes the library, so that roost knows to drop the models it has cached
+CREATE TABLE Catalog (
+    catalog_id INTEGER NOT NULL PRIMARY KEY CHECK (catalog_id = 0),
+    generation INTEGER NOT NULL
+);
+
+INSERT INTO Catalog (catalog_id, generation) VALUES (0, 0);
@@ -0,0 +1,95 @@
+-- Deleting a model now deletes its parts, parameters, constraints and instances with it, and the
+-- columns used to look those up are indexed. SQLite cannot alter a foreign key in place, so each
+-- table is rebuilt. The new tables refer to each other until they are renamed, so that dropping the
+-- old tables never leaves a row without its parent.
+
+CREATE TABLE NewParts (
+    part_id INTEGER NOT NULL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    model_id INTEGER NOT NULL,
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id) ON DELETE CASCADE
+);
+
+CREATE TABLE NewParameters (
+    parameter_id INTEGER NOT NULL PRIMARY KEY,
+    kind VARCHAR NOT NULL CHECK (kind IN ('int_range', 'float_range', 'string_length', 'bool', 'int_list', 'float_list', 'string_list', 'int_vector', 'float_vector')),
+    name VARCHAR NOT NULL,
+    label VARCHAR NOT NULL,
+    description VARCHAR,
+    section VARCHAR,
+    position INTEGER NOT NULL,
+    unit VARCHAR CHECK (unit IN ('mm', 'inch', 'degrees', 'count')),
+    condition VARCHAR,
+    default_value VARCHAR NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES NewParts (part_id) ON DELETE CASCADE,
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id) ON DELETE CASCADE,
+    CHECK ((part_id IS NULL) != (model_id IS NULL))
+);
+
+CREATE TABLE NewParameterRestrictions (
+    restriction_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind VARCHAR NOT NULL CHECK (kind IN ('lower', 'upper', 'step', 'precision', 'length', 'allowed')),
+    position INTEGER NOT NULL,
+    value VARCHAR NOT NULL,
+    parameter_id INTEGER NOT NULL,
+    FOREIGN KEY (parameter_id)
+        REFERENCES NewParameters (parameter_id) ON DELETE CASCADE
+);
+
+CREATE TABLE NewInstances (
+    path VARCHAR NOT NULL PRIMARY KEY,
+    command_string VARCHAR NOT NULL,
+    usage INTEGER NOT NULL DEFAULT 0,
+    part_id INTEGER NOT NULL,
+    FOREIGN KEY (part_id)
+        REFERENCES NewParts (part_id) ON DELETE CASCADE
+);
+
+CREATE TABLE NewConstraints (
+    constraint_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    expression VARCHAR NOT NULL,
+    message VARCHAR NOT NULL,
+    part_id INTEGER,
+    model_id INTEGER,
+    FOREIGN KEY (part_id)
+        REFERENCES NewParts (part_id) ON DELETE CASCADE,
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id) ON DELETE CASCADE,
+    CHECK ((part_id IS NULL) != (model_id IS NULL))
+);
+
+INSERT INTO NewParts SELECT part_id, name, model_id FROM Parts;
+
+INSERT INTO NewParameters
+    SELECT parameter_id, kind, name, label, description, section, position, unit, condition, default_value, part_id, model_id FROM Parameters;
+
+INSERT INTO NewParameterRestrictions SELECT restriction_id, kind, position, value, parameter_id FROM ParameterRestrictions;
+
+INSERT INTO NewInstances SELECT path, command_string, usage, part_id FROM Instances;
+
+INSERT INTO NewConstraints SELECT constraint_id, expression, message, part_id, model_id FROM Constraints;
+
+DROP TABLE ParameterRestrictions;
+DROP TABLE Instances;
+DROP TABLE Constraints;
+DROP TABLE Parameters;
+DROP TABLE Parts;
+
+ALTER TABLE NewParts RENAME TO Parts;
+ALTER TABLE NewParameters RENAME TO Parameters;
+ALTER TABLE NewParameterRestrictions RENAME TO ParameterRestrictions;
+ALTER TABLE NewInstances RENAME TO Instances;
+ALTER TABLE NewConstraints RENAME TO Constraints;
+
+CREATE INDEX PartsModelIndex ON Parts (model_id);
+CREATE INDEX InstancesPartIndex ON Instances (part_id);
+CREATE INDEX ParametersPartIndex ON Parameters (part_id);
+CREATE INDEX ParametersModelIndex ON Parameters (model_id);
+CREATE INDEX ParameterRestrictionsParameterIndex ON ParameterRestrictions (parameter_id);
+CREATE INDEX ConstraintsPartIndex ON Constraints (part_id);
+CREATE INDEX ConstraintsModelIndex ON Constraints (model_id);
@@ -0,0 +1,11 @@
+-- The gallery images of a model, in the order they are shown. The thumbnail stays in Models.image_path.
+CREATE TABLE ModelImages (
+    image_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    path VARCHAR NOT NULL,
+    position INTEGER NOT NULL,
+    model_id INTEGER NOT NULL,
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id) ON DELETE CASCADE
+);
+
+CREATE INDEX ModelImagesModelIndex ON ModelImages (model_id);
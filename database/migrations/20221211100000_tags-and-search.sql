@@ -0,0 +1,38 @@
+-- A model's category is a slash separated path such as "tools/clamps", and it can have any number of tags
+ALTER TABLE Models ADD COLUMN category VARCHAR;
+
+CREATE INDEX ModelsCategoryIndex ON Models (category);
+
+CREATE TABLE ModelTags (
+    tag VARCHAR NOT NULL,
+    model_id INTEGER NOT NULL,
+    PRIMARY KEY (model_id, tag),
+    FOREIGN KEY (model_id)
+        REFERENCES Models (model_id) ON DELETE CASCADE
+);
+
+CREATE INDEX ModelTagsTagIndex ON ModelTags (tag);
+
+-- Full-text index over the models, kept in step with the Models table by the triggers below
+CREATE VIRTUAL TABLE ModelSearch USING fts5 (
+    name,
+    description,
+    author,
+    content = 'Models',
+    content_rowid = 'model_id'
+);
+
+CREATE TRIGGER ModelSearchInsert AFTER INSERT ON Models BEGIN
+    INSERT INTO ModelSearch (rowid, name, description, author) VALUES (new.model_id, new.name, new.description, new.author);
+END;
+
+CREATE TRIGGER ModelSearchDelete AFTER DELETE ON Models BEGIN
+    INSERT INTO ModelSearch (ModelSearch, rowid, name, description, author) VALUES ('delete', old.model_id, old.name, old.description, old.author);
+END;
+
+CREATE TRIGGER ModelSearchUpdate AFTER UPDATE ON Models BEGIN
+    INSERT INTO ModelSearch (ModelSearch, rowid, name, description, author) VALUES ('delete', old.model_id, old.name, old.description, old.author);
+    INSERT INTO ModelSearch (rowid, name, description, author) VALUES (new.model_id, new.name, new.description, new.author);
+END;
+
+INSERT INTO ModelSearch (ModelSearch) VALUES ('rebuild');
@@ -0,0 +1,9 @@
+-- Every version of a model is kept side by side, as a separate row sharing the model's name. Only the
+-- versions found by the latest index are current, older ones stay available for matching replacements.
+-- Each version's files are built into a directory of their own, the existing rows were built into the
+-- root of the build directory.
+ALTER TABLE Models ADD COLUMN version VARCHAR NOT NULL DEFAULT 'unversioned';
+ALTER TABLE Models ADD COLUMN current BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE Models ADD COLUMN build_directory VARCHAR NOT NULL DEFAULT '';
+
+CREATE UNIQUE INDEX ModelsVersionIndex ON Models (name, version);
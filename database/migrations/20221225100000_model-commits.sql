@@ -0,0 +1,3 @@
+-- The last commit to change a model's directory, for models indexed from a git repository. Models with
+-- uncommitted changes, or that aren't in a repository at all, have none.
+ALTER TABLE Models ADD COLUMN commit_hash VARCHAR;
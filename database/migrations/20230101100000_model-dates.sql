@@ -0,0 +1,9 @@
+-- Creation dates are stored as ISO-8601 dates (YYYY-MM-DD), and the date a model's files last changed
+-- as an RFC 3339 timestamp in UTC. ISO dates that SQLite can read are brought into that form. The others
+-- are reset, including bare numbers such as '2022' that SQLite would read as Julian day numbers. Their
+-- versions lose their commit, so that the next index builds them again and reports the invalid date.
+ALTER TABLE Models ADD COLUMN modified_date VARCHAR NOT NULL DEFAULT '1970-01-01T00:00:00Z';
+
+UPDATE Models SET commit_hash = NULL, creation_date = '1970-01-01' WHERE date(creation_date) IS NULL
+    OR creation_date NOT GLOB '[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]*';
+UPDATE Models SET creation_date = date(creation_date), modified_date = strftime('%Y-%m-%dT%H:%M:%SZ', creation_date);
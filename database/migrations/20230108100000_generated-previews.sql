@@ -0,0 +1,3 @@
+-- Models that ship without an image are shown with a preview that plume renders, which is rendered
+-- again whenever the thumbnails are regenerated
+ALTER TABLE Models ADD COLUMN image_generated BOOLEAN NOT NULL DEFAULT FALSE;
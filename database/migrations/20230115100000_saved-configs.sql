@@ -0,0 +1,12 @@
+-- Parameter sets saved under a short id so that they can be shared. Ids change whenever the models are
+-- indexed again, so a saved config refers to its model, part and parameters by name, with the values
+-- keyed by parameter name as a JSON object.
+CREATE TABLE SavedConfigs (
+    config_id VARCHAR NOT NULL PRIMARY KEY,
+    model_name VARCHAR NOT NULL,
+    version VARCHAR NOT NULL,
+    part_name VARCHAR NOT NULL,
+    unit VARCHAR NOT NULL,
+    parameter_values VARCHAR NOT NULL,
+    creation_date VARCHAR NOT NULL
+);
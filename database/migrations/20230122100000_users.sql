@@ -0,0 +1,24 @@
+-- Local user accounts. Passwords are kept as Argon2 hashes in the PHC string format. Roles are ordered,
+-- authors can do everything viewers can and admins everything authors can.
+CREATE TABLE Users (
+    user_id INTEGER NOT NULL PRIMARY KEY,
+    username VARCHAR NOT NULL UNIQUE COLLATE NOCASE,
+    password_hash VARCHAR NOT NULL,
+    role VARCHAR NOT NULL DEFAULT 'viewer' CHECK (role IN ('viewer', 'author', 'admin')),
+    creation_date VARCHAR NOT NULL
+);
+
+-- Tokens for API clients, sent as `Authorization: Bearer <token>`. Only a SHA-256 hash of each token is
+-- kept, the token itself is shown once when it is created.
+CREATE TABLE ApiTokens (
+    token_id INTEGER NOT NULL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    name VARCHAR NOT NULL,
+    token_hash VARCHAR NOT NULL UNIQUE,
+    creation_date VARCHAR NOT NULL,
+    last_used VARCHAR,
+    FOREIGN KEY (user_id)
+        REFERENCES Users (user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX ApiTokensUserIndex ON ApiTokens (user_id);
@@ -0,0 +1,9 @@
+-- The account that first uploaded each model. Only they or an admin may upload a model of the same name
+-- again, replacing it. Models are kept by name, as each version is a row of its own in Models. Models that
+-- were indexed from the models directory rather than uploaded have no owner, and only admins may replace them.
+CREATE TABLE ModelOwners (
+    name VARCHAR NOT NULL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    FOREIGN KEY (user_id)
+        REFERENCES Users (user_id) ON DELETE CASCADE
+);
@@ -0,0 +1,389 @@
+// ***** Config *****
+// The configuration shared by plume and roost. It is stored by confy under the name `parakeet`, and
+// any of its values can be overridden by a `PARAKEET_*` environment variable or by a plume flag.
+// Overrides are applied in the order: stored config < environment variables < command line.
+// Separate installations (e.g. staging and production) can be kept as named profiles, each stored in
+// its own file and picked with `PARAKEET_PROFILE` or plume's --profile flag.
+
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::canonicalize;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_NAME: &str = "parakeet";
+
+pub const KEYS: [&str; 10] = ["models_path", "build_path", "database_path", "model_limit", "library_path", "thumbnail_size", "thumbnail_camera", "request_limit", "render_limit", "plume_path"];
+
+// The directory within the build directory that the used parts of the shared library are copied to
+pub const BUILD_LIBRARY_DIRECTORY: &str = "library";
+
+// The directory within the build directory that the preview images of .stl instances are rendered to
+pub const BUILD_PREVIEW_DIRECTORY: &str = "previews";
+
+// The preview image of an .stl instance, which lives and goes with the instance
+pub fn instance_preview_path(instance_path: &str) -> String {
+    let file_name: &str = instance_path.rsplit('/').next().unwrap_or(instance_path);
+    format!("{}/{}.png", BUILD_PREVIEW_DIRECTORY, file_name.strip_suffix(".stl").unwrap_or(file_name))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ParakeetConfig {
+    pub models_path: PathBuf,
+    pub build_path: PathBuf,
+    pub database_path: PathBuf,
+    pub model_limit: i64,
+    // Shared .scad libraries that models can `include` or `use` without a relative path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub library_path: Option<PathBuf>,
+    // The size of the previews rendered for models without an image, as "<width>x<height>"
+    #[serde(default = "default_thumbnail_size")]
+    pub thumbnail_size: String,
+    // The camera of those previews, as OpenSCAD's --camera takes it. Either the translation, rotation and
+    // distance "tx,ty,tz,rx,ry,rz,d" or the eye and center "ex,ey,ez,cx,cy,cz". The preview is zoomed to
+    // fit the model either way.
+    #[serde(default = "default_thumbnail_camera")]
+    pub thumbnail_camera: String,
+    // How many generate, preview and bundle requests each client can make, as "<requests>/<seconds>". Up to
+    // that many can be made at once, after which they are allowed again at that rate.
+    #[serde(default = "default_request_limit")]
+    pub request_limit: String,
+    // The same for requests that need a part to be rendered, as opposed to those answered from the cache
+    #[serde(default = "default_render_limit")]
+    pub render_limit: String,
+    // The plume binary roost runs to add uploaded models, looked up on the PATH unless it is a path
+    #[serde(default = "default_plume_path")]
+    pub plume_path: PathBuf
+}
+
+fn default_thumbnail_size() -> String {
+    String::from("512x512")
+}
+
+// OpenSCAD's own default view
+fn default_thumbnail_camera() -> String {
+    String::from("0,0,0,55,0,25,0")
+}
+
+fn default_request_limit() -> String {
+    String::from("120/60")
+}
+
+fn default_render_limit() -> String {
+    String::from("10/60")
+}
+
+fn default_plume_path() -> PathBuf {
+    PathBuf::from("plume")
+}
+
+impl ::std::default::Default for ParakeetConfig {
+    fn default() -> Self {
+        Self {
+            models_path: PathBuf::new(),
+            build_path: PathBuf::new(),
+            database_path: PathBuf::new(),
+            model_limit: 100,
+            library_path: None,
+            thumbnail_size: default_thumbnail_size(),
+            thumbnail_camera: default_thumbnail_camera(),
+            request_limit: default_request_limit(),
+            render_limit: default_render_limit(),
+            plume_path: default_plume_path()
+        }
+    }
+}
+
+// Values that take precedence over the stored config, unset values are left as they are
+#[derive(Default, Debug)]
+pub struct Overrides {
+    pub models_path: Option<PathBuf>,
+    pub build_path: Option<PathBuf>,
+    pub database_path: Option<PathBuf>,
+    pub model_limit: Option<i64>,
+    pub library_path: Option<PathBuf>
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    NotConfigured(&'static str),
+    MissingDirectory(&'static str, PathBuf),
+    InvalidModelLimit(i64),
+    InvalidThumbnailSize(String),
+    InvalidThumbnailCamera(String),
+    InvalidRateLimit(&'static str, String),
+    InvalidVariable(&'static str, String),
+    UnknownKey(String),
+    InvalidValue(String, String)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::NotConfigured(field) => write!(f, "'{}' has not been configured, run 'plume config init'", field),
+            ConfigError::MissingDirectory(field, path) => write!(f, "'{}' refers to a directory that does not exist (path: {})", field, path.display()),
+            ConfigError::InvalidModelLimit(limit) => write!(f, "'model_limit' must be at least 1 (found: {})", limit),
+            ConfigError::InvalidThumbnailSize(size) => write!(f, "'thumbnail_size' must be a size such as 512x512 (found: {})", size),
+            ConfigError::InvalidThumbnailCamera(camera) => {
+                write!(f, "'thumbnail_camera' must be 6 or 7 comma separated numbers, as OpenSCAD's --camera takes them (found: {})", camera)
+            }
+            ConfigError::InvalidRateLimit(key, limit) => write!(f, "'{}' must be a number of requests per number of seconds such as 10/60 (found: {})", key, limit),
+            ConfigError::InvalidVariable(variable, value) => write!(f, "environment variable {} has an invalid value (found: {})", variable, value),
+            ConfigError::UnknownKey(key) => write!(f, "'{}' is not a config key, expected one of: {}", key, KEYS.join(", ")),
+            ConfigError::InvalidValue(key, value) => write!(f, "'{}' has an invalid value (found: {})", key, value)
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+// The profile picked through the environment, if any
+pub fn profile_from_env() -> Option<String> {
+    env::var("PARAKEET_PROFILE").ok().filter(|profile| !profile.is_empty())
+}
+
+impl Overrides {
+    // Read the overrides set through environment variables
+    pub fn from_env() -> Result<Overrides, ConfigError> {
+        let model_limit: Option<i64> = match env::var("PARAKEET_MODEL_LIMIT") {
+            Ok(limit) => Some(limit.parse().map_err(|_| ConfigError::InvalidVariable("PARAKEET_MODEL_LIMIT", limit.clone()))?),
+            Err(_) => None
+        };
+
+        Ok(Overrides {
+            models_path: env::var_os("PARAKEET_MODELS_PATH").map(PathBuf::from),
+            build_path: env::var_os("PARAKEET_BUILD_PATH").map(PathBuf::from),
+            database_path: env::var_os("PARAKEET_DATABASE_PATH").map(PathBuf::from),
+            model_limit,
+            library_path: env::var_os("PARAKEET_LIBRARY_PATH").map(PathBuf::from)
+        })
+    }
+
+    // Layer these overrides on top of another set
+    pub fn over(self, other: Overrides) -> Overrides {
+        Overrides {
+            models_path: self.models_path.or(other.models_path),
+            build_path: self.build_path.or(other.build_path),
+            database_path: self.database_path.or(other.database_path),
+            model_limit: self.model_limit.or(other.model_limit),
+            library_path: self.library_path.or(other.library_path)
+        }
+    }
+}
+
+impl ParakeetConfig {
+    // Load the stored config, apply the overrides and check that the result is usable
+    pub fn load(profile: Option<&str>, overrides: Overrides) -> Result<ParakeetConfig, Box<dyn Error>> {
+        let config: ParakeetConfig = ParakeetConfig::load_stored(profile)?.with(overrides);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    // Load the stored config as it is, a profile that hasn't been stored yet loads the defaults
+    pub fn load_stored(profile: Option<&str>) -> Result<ParakeetConfig, Box<dyn Error>> {
+        Ok(confy::load(CONFIG_NAME, profile)?)
+    }
+
+    pub fn store(&self, profile: Option<&str>) -> Result<(), Box<dyn Error>> {
+        confy::store(CONFIG_NAME, profile, self)?;
+        Ok(())
+    }
+
+    // The file a profile is stored in
+    pub fn file_path(profile: Option<&str>) -> Result<PathBuf, Box<dyn Error>> {
+        Ok(confy::get_configuration_file_path(CONFIG_NAME, profile)?)
+    }
+
+    // Each key alongside its value, in the order of `KEYS`
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("models_path", self.models_path.display().to_string()),
+            ("build_path", self.build_path.display().to_string()),
+            ("database_path", self.database_path.display().to_string()),
+            ("model_limit", self.model_limit.to_string()),
+            ("library_path", self.library_path.as_ref().map_or(String::new(), |path| path.display().to_string())),
+            ("thumbnail_size", self.thumbnail_size.to_string()),
+            ("thumbnail_camera", self.thumbnail_camera.to_string()),
+            ("request_limit", self.request_limit.to_string()),
+            ("render_limit", self.render_limit.to_string()),
+            ("plume_path", self.plume_path.display().to_string())
+        ]
+    }
+
+    // Change a single value, checking it the same way `validate` would
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        match key {
+            "models_path" | "build_path" => {
+                let path: PathBuf = PathBuf::from(value);
+                if !path.is_dir() {
+                    Err(ConfigError::InvalidValue(key.to_string(), value.to_string()))?;
+                }
+                let path: PathBuf = canonicalize(path)?;
+                if key == "models_path" { self.models_path = path } else { self.build_path = path }
+            },
+            "database_path" => {
+                let database_path: &Path = Path::new(value);
+                let database_directory: &Path = parent_directory(database_path);
+                if !database_directory.is_dir() {
+                    Err(ConfigError::MissingDirectory("database_path", database_directory.to_path_buf()))?;
+                }
+                self.database_path = canonicalize(database_directory)?.join(database_path.file_name().ok_or("invalid database file path")?);
+            },
+            // An empty value removes the library
+            "library_path" if value.is_empty() => self.library_path = None,
+            "library_path" => {
+                if !Path::new(value).is_dir() {
+                    Err(ConfigError::InvalidValue(key.to_string(), value.to_string()))?;
+                }
+                self.library_path = Some(canonicalize(value)?);
+            },
+            "model_limit" => {
+                self.model_limit = match value.parse() {
+                    Ok(limit) if limit >= 1 => limit,
+                    _ => Err(ConfigError::InvalidValue(key.to_string(), value.to_string()))?
+                };
+            },
+            "thumbnail_size" => {
+                parse_thumbnail_size(value)?;
+                self.thumbnail_size = value.to_string();
+            },
+            "thumbnail_camera" => {
+                check_thumbnail_camera(value)?;
+                self.thumbnail_camera = value.to_string();
+            },
+            "request_limit" => {
+                parse_rate_limit("request_limit", value)?;
+                self.request_limit = value.to_string();
+            },
+            "render_limit" => {
+                parse_rate_limit("render_limit", value)?;
+                self.render_limit = value.to_string();
+            },
+            // An empty value goes back to the plume on the PATH
+            "plume_path" if value.is_empty() => self.plume_path = default_plume_path(),
+            "plume_path" => self.plume_path = PathBuf::from(value),
+            _ => Err(ConfigError::UnknownKey(key.to_string()))?
+        }
+
+        Ok(())
+    }
+
+    pub fn with(self, overrides: Overrides) -> ParakeetConfig {
+        ParakeetConfig {
+            models_path: overrides.models_path.unwrap_or(self.models_path),
+            build_path: overrides.build_path.unwrap_or(self.build_path),
+            database_path: overrides.database_path.unwrap_or(self.database_path),
+            model_limit: overrides.model_limit.unwrap_or(self.model_limit),
+            library_path: overrides.library_path.or(self.library_path),
+            ..self
+        }
+    }
+
+    // The models and build directories must exist, as must the directory holding the database. The
+    // database file itself is created when it is first connected to.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (field, path) in [("models_path", &self.models_path), ("build_path", &self.build_path)] {
+            if path.as_os_str().is_empty() {
+                return Err(ConfigError::NotConfigured(field));
+            }
+            if !path.is_dir() {
+                return Err(ConfigError::MissingDirectory(field, path.clone()));
+            }
+        }
+
+        if self.database_path.as_os_str().is_empty() {
+            return Err(ConfigError::NotConfigured("database_path"));
+        }
+        let database_directory: &Path = parent_directory(&self.database_path);
+        if !database_directory.is_dir() {
+            return Err(ConfigError::MissingDirectory("database_path", database_directory.to_path_buf()));
+        }
+
+        if self.model_limit < 1 {
+            return Err(ConfigError::InvalidModelLimit(self.model_limit));
+        }
+
+        if let Some(library_path) = &self.library_path {
+            if !library_path.is_dir() {
+                return Err(ConfigError::MissingDirectory("library_path", library_path.clone()));
+            }
+        }
+
+        parse_thumbnail_size(&self.thumbnail_size)?;
+        check_thumbnail_camera(&self.thumbnail_camera)?;
+        parse_rate_limit("request_limit", &self.request_limit)?;
+        parse_rate_limit("render_limit", &self.render_limit)?;
+
+        Ok(())
+    }
+
+    // Resolve every path to an absolute one, so that the config doesn't depend on where it is used from
+    pub fn canonicalize(self) -> Result<ParakeetConfig, Box<dyn Error>> {
+        self.validate()?;
+        let database_directory: PathBuf = canonicalize(parent_directory(&self.database_path))?;
+
+        Ok(ParakeetConfig {
+            models_path: canonicalize(&self.models_path)?,
+            build_path: canonicalize(&self.build_path)?,
+            database_path: database_directory.join(self.database_path.file_name().ok_or("invalid database file path")?),
+            model_limit: self.model_limit,
+            library_path: match self.library_path {
+                Some(library_path) => Some(canonicalize(library_path)?),
+                None => None
+            },
+            thumbnail_size: self.thumbnail_size,
+            thumbnail_camera: self.thumbnail_camera,
+            request_limit: self.request_limit,
+            render_limit: self.render_limit,
+            plume_path: self.plume_path
+        })
+    }
+
+    // The width and height of the rendered previews
+    pub fn thumbnail_dimensions(&self) -> Result<(u32, u32), ConfigError> {
+        parse_thumbnail_size(&self.thumbnail_size)
+    }
+
+    // The number of requests and the number of seconds of the request and render limits
+    pub fn request_rate(&self) -> Result<(u32, u32), ConfigError> {
+        parse_rate_limit("request_limit", &self.request_limit)
+    }
+
+    pub fn render_rate(&self) -> Result<(u32, u32), ConfigError> {
+        parse_rate_limit("render_limit", &self.render_limit)
+    }
+}
+
+fn parse_thumbnail_size(size: &str) -> Result<(u32, u32), ConfigError> {
+    let invalid = || ConfigError::InvalidThumbnailSize(size.to_string());
+    let (width, height): (&str, &str) = size.split_once('x').ok_or_else(invalid)?;
+    match (width.trim().parse::<u32>(), height.trim().parse::<u32>()) {
+        (Ok(width), Ok(height)) if width >= 1 && height >= 1 => Ok((width, height)),
+        _ => Err(invalid())
+    }
+}
+
+fn check_thumbnail_camera(camera: &str) -> Result<(), ConfigError> {
+    let values: Vec<&str> = camera.split(',').collect();
+    if (values.len() == 6 || values.len() == 7) && values.iter().all(|value| value.trim().parse::<f64>().is_ok()) {
+        Ok(())
+    } else {
+        Err(ConfigError::InvalidThumbnailCamera(camera.to_string()))
+    }
+}
+
+fn parse_rate_limit(key: &'static str, limit: &str) -> Result<(u32, u32), ConfigError> {
+    let invalid = || ConfigError::InvalidRateLimit(key, limit.to_string());
+    let (requests, seconds): (&str, &str) = limit.split_once('/').ok_or_else(invalid)?;
+    match (requests.trim().parse::<u32>(), seconds.trim().parse::<u32>()) {
+        (Ok(requests), Ok(seconds)) if requests >= 1 && seconds >= 1 => Ok((requests, seconds)),
+        _ => Err(invalid())
+    }
+}
+
+// The directory a file is in, a bare file name is in the current directory
+pub fn parent_directory(path: &Path) -> &Path {
+    path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."))
+}
@@ -0,0 +1,500 @@
+// ***** Constraints *****
+// A small expression language used for conditional parameters and the constraints between parameters.
+// Expressions are made up of:
+//  * literals      -> numbers, "strings" and true/false
+//  * parameters    -> referred to by name, with vector components accessed by index, e.g. `size[0]`
+//  * operators     -> `!` `-` (unary), `*` `/` `%`, `+` `-`, `<` `<=` `>` `>=`, `==` `!=`, `&&`, `||`
+//                     in order of decreasing precedence, with parentheses for grouping
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ConstraintError {
+    Syntax(String, String),
+    UnknownParameter(String, String),
+    Type(String, String),
+}
+
+impl fmt::Display for ConstraintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConstraintError::Syntax(expression, detail) => {
+                write!(f, "invalid syntax in expression '{}': {}", expression, detail)
+            }
+            ConstraintError::UnknownParameter(expression, name) => {
+                write!(f, "expression '{}' refers to unknown parameter '{}'", expression, name)
+            }
+            ConstraintError::Type(expression, detail) => {
+                write!(f, "could not evaluate expression '{}': {}", expression, detail)
+            }
+        }
+    }
+}
+
+impl Error for ConstraintError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Bool(bool),
+    Str(String),
+    Vector(Vec<f64>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Bool(_) => "boolean",
+            Value::Str(_) => "string",
+            Value::Vector(_) => "vector",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    Identifier(String),
+    Operator(&'static str),
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+}
+
+#[derive(Debug)]
+enum Node {
+    Literal(Value),
+    Parameter(String),
+    Index(Box<Node>, Box<Node>),
+    Unary(&'static str, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+}
+
+const OPERATORS: [&str; 16] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "!", "=", "&",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut i: usize = 0;
+
+    while i < chars.len() {
+        let c: char = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || (c == '.' && i + 1 < chars.len() && chars[i + 1].is_ascii_digit()) {
+            let start: usize = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(
+                number.parse::<f64>().map_err(|_| format!("invalid number '{}'", number))?,
+            ));
+        } else if c.is_alphabetic() || c == '_' {
+            let start: usize = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Identifier(chars[start..i].iter().collect()));
+        } else if c == '"' || c == '\'' {
+            let start: usize = i + 1;
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err(String::from("unterminated string"));
+            }
+            tokens.push(Token::Str(chars[start..i].iter().collect()));
+            i += 1;
+        } else if c == '(' || c == ')' || c == '[' || c == ']' {
+            tokens.push(match c {
+                '(' => Token::OpenParen,
+                ')' => Token::CloseParen,
+                '[' => Token::OpenBracket,
+                _ => Token::CloseBracket,
+            });
+            i += 1;
+        } else {
+            let rest: String = chars[i..].iter().collect();
+            match OPERATORS.iter().find(|operator| rest.starts_with(*operator)) {
+                // A lone `=` or `&` is almost certainly a typo of `==` or `&&`
+                Some(&"=") | Some(&"&") | None => {
+                    return Err(format!("unexpected character '{}'", c))
+                }
+                Some(operator) => {
+                    tokens.push(Token::Operator(operator));
+                    i += operator.len();
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token: Option<Token> = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("expected {:?} but found {:?}", expected, token)),
+            None => Err(format!("expected {:?} but reached the end", expected)),
+        }
+    }
+
+    // Parses a left-associative chain of binary operators at a single level of precedence
+    fn binary(
+        &mut self,
+        operators: &[&'static str],
+        operand: fn(&mut Parser) -> Result<Node, String>,
+    ) -> Result<Node, String> {
+        let mut left: Node = operand(self)?;
+        while let Some(Token::Operator(operator)) = self.peek() {
+            let operator: &'static str = operator;
+            if !operators.contains(&operator) {
+                break;
+            }
+            self.position += 1;
+            let right: Node = operand(self)?;
+            left = Node::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        self.binary(&["||"], Parser::and)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        self.binary(&["&&"], Parser::equality)
+    }
+
+    fn equality(&mut self) -> Result<Node, String> {
+        self.binary(&["==", "!="], Parser::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Node, String> {
+        self.binary(&["<", "<=", ">", ">="], Parser::additive)
+    }
+
+    fn additive(&mut self) -> Result<Node, String> {
+        self.binary(&["+", "-"], Parser::multiplicative)
+    }
+
+    fn multiplicative(&mut self) -> Result<Node, String> {
+        self.binary(&["*", "/", "%"], Parser::unary)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        match self.peek() {
+            Some(Token::Operator(operator)) if *operator == "!" || *operator == "-" => {
+                let operator: &'static str = operator;
+                self.position += 1;
+                Ok(Node::Unary(operator, Box::new(self.unary()?)))
+            }
+            _ => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Node, String> {
+        let mut node: Node = self.primary()?;
+        while self.peek() == Some(&Token::OpenBracket) {
+            self.position += 1;
+            let index: Node = self.or()?;
+            self.expect(Token::CloseBracket)?;
+            node = Node::Index(Box::new(node), Box::new(index));
+        }
+        Ok(node)
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Node::Literal(Value::Number(number))),
+            Some(Token::Str(string)) => Ok(Node::Literal(Value::Str(string))),
+            Some(Token::Identifier(name)) => match name.as_str() {
+                "true" => Ok(Node::Literal(Value::Bool(true))),
+                "false" => Ok(Node::Literal(Value::Bool(false))),
+                _ => Ok(Node::Parameter(name)),
+            },
+            Some(Token::OpenParen) => {
+                let node: Node = self.or()?;
+                self.expect(Token::CloseParen)?;
+                Ok(node)
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err(String::from("unexpected end of expression")),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Expression, ConstraintError> {
+        let syntax_error = |detail: String| ConstraintError::Syntax(source.to_string(), detail);
+
+        let mut parser: Parser = Parser {
+            tokens: tokenize(source).map_err(syntax_error)?,
+            position: 0,
+        };
+        let root: Node = parser.or().map_err(syntax_error)?;
+        if let Some(token) = parser.peek() {
+            return Err(syntax_error(format!("unexpected {:?}", token)));
+        }
+
+        Ok(Expression {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    // The names of every parameter referred to by the expression
+    pub fn parameters(&self) -> Vec<String> {
+        fn visit(node: &Node, names: &mut Vec<String>) {
+            match node {
+                Node::Literal(_) => {}
+                Node::Parameter(name) => {
+                    if !names.contains(name) {
+                        names.push(name.to_string());
+                    }
+                }
+                Node::Index(vector, index) => {
+                    visit(vector, names);
+                    visit(index, names);
+                }
+                Node::Unary(_, operand) => visit(operand, names),
+                Node::Binary(_, left, right) => {
+                    visit(left, names);
+                    visit(right, names);
+                }
+            }
+        }
+
+        let mut names: Vec<String> = Vec::new();
+        visit(&self.root, &mut names);
+        names
+    }
+
+    pub fn evaluate(&self, values: &HashMap<String, Value>) -> Result<Value, ConstraintError> {
+        self.evaluate_node(&self.root, values)
+    }
+
+    // Evaluates the expression, which must result in a boolean
+    pub fn is_satisfied(&self, values: &HashMap<String, Value>) -> Result<bool, ConstraintError> {
+        match self.evaluate(values)? {
+            Value::Bool(satisfied) => Ok(satisfied),
+            value => Err(self.type_error(format!("expected a boolean result but found a {}", value.type_name()))),
+        }
+    }
+
+    fn type_error(&self, detail: String) -> ConstraintError {
+        ConstraintError::Type(self.source.to_string(), detail)
+    }
+
+    fn evaluate_node(&self, node: &Node, values: &HashMap<String, Value>) -> Result<Value, ConstraintError> {
+        match node {
+            Node::Literal(value) => Ok(value.clone()),
+            Node::Parameter(name) => values
+                .get(name)
+                .cloned()
+                .ok_or(ConstraintError::UnknownParameter(self.source.to_string(), name.to_string())),
+            Node::Index(vector, index) => {
+                match (self.evaluate_node(vector, values)?, self.evaluate_node(index, values)?) {
+                    (Value::Vector(components), Value::Number(index)) => components
+                        .get(index as usize)
+                        .filter(|_| index >= 0.0 && index.fract() == 0.0)
+                        .map(|component| Value::Number(*component))
+                        .ok_or(self.type_error(format!("index {} is out of bounds", index))),
+                    (vector, index) => Err(self.type_error(format!(
+                        "cannot index a {} with a {}", vector.type_name(), index.type_name()
+                    ))),
+                }
+            }
+            Node::Unary(operator, operand) => match (*operator, self.evaluate_node(operand, values)?) {
+                ("!", Value::Bool(value)) => Ok(Value::Bool(!value)),
+                ("-", Value::Number(value)) => Ok(Value::Number(-value)),
+                (operator, value) => Err(self.type_error(format!(
+                    "cannot apply '{}' to a {}", operator, value.type_name()
+                ))),
+            },
+            Node::Binary(operator, left, right) => {
+                // Logical operators short-circuit
+                if *operator == "&&" || *operator == "||" {
+                    let left: bool = self.evaluate_bool(left, values, operator)?;
+                    if (*operator == "&&" && !left) || (*operator == "||" && left) {
+                        return Ok(Value::Bool(left));
+                    }
+                    return Ok(Value::Bool(self.evaluate_bool(right, values, operator)?));
+                }
+
+                let left: Value = self.evaluate_node(left, values)?;
+                let right: Value = self.evaluate_node(right, values)?;
+                match (*operator, &left, &right) {
+                    ("==", _, _) if left.type_name() == right.type_name() => Ok(Value::Bool(left == right)),
+                    ("!=", _, _) if left.type_name() == right.type_name() => Ok(Value::Bool(left != right)),
+                    (_, Value::Number(l), Value::Number(r)) => match *operator {
+                        "<" => Ok(Value::Bool(l < r)),
+                        "<=" => Ok(Value::Bool(l <= r)),
+                        ">" => Ok(Value::Bool(l > r)),
+                        ">=" => Ok(Value::Bool(l >= r)),
+                        "+" => Ok(Value::Number(l + r)),
+                        "-" => Ok(Value::Number(l - r)),
+                        "*" => Ok(Value::Number(l * r)),
+                        "/" | "%" if *r == 0.0 => Err(self.type_error(String::from("division by zero"))),
+                        "/" => Ok(Value::Number(l / r)),
+                        _ => Ok(Value::Number(l % r)),
+                    },
+                    (_, Value::Str(l), Value::Str(r)) if ["<", "<=", ">", ">="].contains(operator) => {
+                        match *operator {
+                            "<" => Ok(Value::Bool(l < r)),
+                            "<=" => Ok(Value::Bool(l <= r)),
+                            ">" => Ok(Value::Bool(l > r)),
+                            _ => Ok(Value::Bool(l >= r)),
+                        }
+                    }
+                    _ => Err(self.type_error(format!(
+                        "cannot apply '{}' to a {} and a {}", operator, left.type_name(), right.type_name()
+                    ))),
+                }
+            }
+        }
+    }
+
+    fn evaluate_bool(&self, node: &Node, values: &HashMap<String, Value>, operator: &str) -> Result<bool, ConstraintError> {
+        match self.evaluate_node(node, values)? {
+            Value::Bool(value) => Ok(value),
+            value => Err(self.type_error(format!("cannot apply '{}' to a {}", operator, value.type_name()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(source: &str) -> Result<Value, ConstraintError> {
+        let values: HashMap<String, Value> = HashMap::from([
+            (String::from("width"), Value::Number(20.0)),
+            (String::from("holes"), Value::Bool(true)),
+            (String::from("font"), Value::Str(String::from("sans"))),
+            (String::from("size"), Value::Vector(vec![1.0, 2.5])),
+        ]);
+        Expression::parse(source)?.evaluate(&values)
+    }
+
+    fn number(source: &str) -> f64 {
+        match evaluate(source) {
+            Ok(Value::Number(number)) => number,
+            result => panic!("'{}' evaluated to {:?}", source, result),
+        }
+    }
+
+    fn boolean(source: &str) -> bool {
+        match evaluate(source) {
+            Ok(Value::Bool(value)) => value,
+            result => panic!("'{}' evaluated to {:?}", source, result),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(number("1 + 2 * 3"), 7.0);
+        assert_eq!(number("(1 + 2) * 3"), 9.0);
+        assert_eq!(number("10 - 4 - 3"), 3.0);
+        assert_eq!(number("12 / 3 / 2"), 2.0);
+        assert_eq!(number("7 % 4 * 2"), 6.0);
+        assert!(boolean("1 + 1 == 2 && 3 > 2"));
+        assert!(boolean("false && false || true"));
+        assert!(!boolean("false && (false || true)"));
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(number("-3"), -3.0);
+        assert_eq!(number("--3"), 3.0);
+        assert_eq!(number("-2 * 3"), -6.0);
+        assert_eq!(number("4 - -2"), 6.0);
+        assert_eq!(number("-width"), -20.0);
+        assert_eq!(number("-size[1]"), -2.5);
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(boolean("width > 10"));
+        assert!(boolean("width >= 20"));
+        assert!(!boolean("width < 20"));
+        assert!(boolean("width <= 20"));
+        assert!(boolean("width == 20"));
+        assert!(boolean("width != 21"));
+        assert!(boolean("font == 'sans'"));
+        assert!(boolean("font < \"serif\""));
+        assert!(boolean("size[0] < size[1]"));
+        assert!(evaluate("width == 'sans'").is_err());
+        assert!(evaluate("font > 3").is_err());
+    }
+
+    #[test]
+    fn boolean_operators() {
+        assert!(boolean("holes && width > 10"));
+        assert!(!boolean("!holes"));
+        assert!(boolean("!holes || true"));
+        assert!(boolean("holes == true"));
+        assert!(evaluate("holes && width").is_err());
+        assert!(evaluate("!width").is_err());
+        // The right hand side is not evaluated once the result is known
+        assert!(!boolean("false && width"));
+        assert!(boolean("true || width"));
+    }
+
+    #[test]
+    fn unknown_identifiers() {
+        let expression: Expression = Expression::parse("depth > width").unwrap();
+        assert_eq!(expression.parameters(), vec![String::from("depth"), String::from("width")]);
+        assert!(matches!(evaluate("depth > 1"), Err(ConstraintError::UnknownParameter(_, name)) if name == "depth"));
+    }
+
+    #[test]
+    fn malformed_input() {
+        for source in ["", "1 +", "(1 + 2", "1 + 2)", "width = 2", "holes & true", "'open", "size[0", "1 2", "#"] {
+            assert!(
+                matches!(Expression::parse(source), Err(ConstraintError::Syntax(_, _))),
+                "'{}' should not parse", source
+            );
+        }
+    }
+
+    #[test]
+    fn evaluation_errors() {
+        assert!(evaluate("width / 0").is_err());
+        assert!(evaluate("size[2]").is_err());
+        assert!(evaluate("size[-1]").is_err());
+        assert!(evaluate("size[0.5]").is_err());
+        assert!(Expression::parse("width + 1").unwrap().is_satisfied(&HashMap::new()).is_err());
+    }
+}
@@ -0,0 +1,5 @@
+// ***** Nest *****
+// Code shared by plume and roost.
+
+pub mod config;
+pub mod constraint;
@@ -1,51 +1,48 @@
-use serde::{Deserialize, Serialize};
+use crate::schema;
+use nest::config::{Overrides, ParakeetConfig, parent_directory};
 use std::error::Error;
-use std::fs::{DirEntry, metadata, canonicalize};
+use std::fs::create_dir_all;
 use std::path::PathBuf;
-use std::env;
-
-#[derive(Serialize, Deserialize)]
-struct ParakeetConfig {
-    models_path: PathBuf,
-    build_path: PathBuf,
-    database_path: PathBuf,
-    model_limit: i64
-}
 
-impl ::std::default::Default for ParakeetConfig {
-    fn default() -> Self {
-        Self {
-            models_path: PathBuf::new(),
-            build_path: PathBuf::new(),
-            database_path: PathBuf::new(),
-            model_limit: 100
-        }
+// Shows the stored config of a profile, with any overrides applied
+pub fn show(profile: Option<&str>, overrides: Overrides) -> Result<(), Box<dyn Error>> {
+    let config: ParakeetConfig = ParakeetConfig::load_stored(profile)?.with(overrides);
+
+    println!("Profile: {} (`{}`)", profile.unwrap_or("default"), ParakeetConfig::file_path(profile)?.display());
+    for (key, value) in config.entries() {
+        println!("{} = {}", key, value);
     }
+    if let Err(error) = config.validate() {
+        println!("Warning: this config is not usable yet: [{}]", error);
+    }
+
+    Ok(())
 }
 
-// Sets up configuration for plume
-pub fn config(models_path: PathBuf, build_path: PathBuf, database_path: PathBuf, model_limit: i64) -> Result<(), Box<dyn Error>> {
-    metadata(&models_path)?;
-    metadata(&build_path)?;
-    metadata(&database_path)?;
-
-    confy::store(
-        "parakeet",
-        None,
-        ParakeetConfig {
-            models_path: canonicalize(models_path)?,
-            build_path: canonicalize(build_path)?,
-            database_path: canonicalize(database_path)?,
-            model_limit
-        },
-    )?;
+// Changes a single value of the stored config, leaving the others as they are
+pub fn set(profile: Option<&str>, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    let mut config: ParakeetConfig = ParakeetConfig::load_stored(profile)?;
+    config.set(key, value)?;
 
-    Ok(())
+    config.store(profile)
 }
 
-// Loads the 'models' and 'build' paths from the config
-pub fn get_paths() -> Result<Vec<PathBuf>, Box<dyn Error>> {
-    let config: ParakeetConfig = confy::load("parakeet", None)?;
+// Sets up configuration for plume, creating the directories and an empty database where they don't exist.
+// The shared library is optional and must already exist.
+pub async fn init(profile: Option<&str>, models_path: PathBuf, build_path: PathBuf, database_path: PathBuf, model_limit: i64, library_path: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    create_dir_all(&models_path)?;
+    create_dir_all(&build_path)?;
+    create_dir_all(parent_directory(&database_path))?;
+
+    let config: ParakeetConfig = ParakeetConfig {
+        models_path,
+        build_path,
+        database_path,
+        model_limit,
+        library_path,
+        ..ParakeetConfig::default()
+    }.canonicalize()?;
+    schema::connect(&config.database_path).await?;
 
-    Ok(vec![config.models_path, config.build_path, config.database_path])
+    config.store(profile)
 }
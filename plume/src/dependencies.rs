@@ -0,0 +1,161 @@
+// ***** Dependencies *****
+// Resolves the `include <...>` and `use <...>` statements of a model's .scad files, the same way
+// OpenSCAD does: relative to the file containing the statement first, then within the shared library.
+// Files found below the models directory keep their place relative to it when copied to the build
+// directory, so relative statements between models keep working, and library files are copied to
+// the build directory's library, which roost hands to OpenSCAD through `OPENSCADPATH`.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::fs::canonicalize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dependency {
+    // A path relative to the models directory
+    Model(PathBuf),
+    // A path relative to the library directory
+    Library(PathBuf)
+}
+
+#[derive(Debug)]
+pub enum DependencyError {
+    OutsideModels(PathBuf, String)
+}
+
+impl fmt::Display for DependencyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DependencyError::OutsideModels(file, target) => {
+                write!(f, "'{}' depends on '{}', which is outside of both the models directory and the library", file.display(), target)
+            }
+        }
+    }
+}
+
+impl Error for DependencyError {}
+
+// Every file the given files depend on, directly or not, including the given files themselves
+pub fn resolve(files: &Vec<PathBuf>, models_path: &PathBuf, library_path: Option<&PathBuf>) -> Result<Vec<Dependency>, Box<dyn Error>> {
+    let models_path: PathBuf = canonicalize(models_path)?;
+    let library_path: Option<PathBuf> = match library_path {
+        Some(library_path) => Some(canonicalize(library_path)?),
+        None => None
+    };
+
+    // The given files are always part of a model
+    let mut pending: Vec<(PathBuf, Dependency)> = Vec::new();
+    for file in files {
+        let file: PathBuf = canonicalize(file)?;
+        let dependency: Dependency = Dependency::Model(file.strip_prefix(&models_path)?.to_path_buf());
+        pending.push((file, dependency));
+    }
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+    let mut dependencies: Vec<Dependency> = Vec::new();
+
+    while let Some((file, dependency)) = pending.pop() {
+        if !visited.insert(file.clone()) {
+            continue;
+        }
+        dependencies.push(dependency);
+
+        let source: String = fs::read_to_string(&file)?;
+        for target in statement_targets(&source) {
+            let directory: &Path = file.parent().unwrap_or(Path::new("/"));
+            let candidates: Vec<PathBuf> = std::iter::once(directory.join(&target))
+                .chain(library_path.iter().map(|library_path| library_path.join(&target)))
+                .collect();
+            match candidates.into_iter().find(|candidate| candidate.is_file()) {
+                Some(found) => {
+                    let found: PathBuf = canonicalize(found)?;
+                    let dependency: Dependency = locate(&found, &models_path, library_path.as_ref())
+                        .ok_or_else(|| DependencyError::OutsideModels(file.clone(), target.clone()))?;
+                    pending.push((found, dependency));
+                },
+                // OpenSCAD has libraries of its own, which it may still find when rendering
+                None => println!("Warning: could not find '{}', used by '{}'. It will need to be installed alongside OpenSCAD.", target, file.display())
+            }
+        }
+    }
+
+    Ok(dependencies)
+}
+
+// Copy the dependencies into the build directory, `scad_path` for models and `library_path` for the library
+pub fn copy(dependencies: &Vec<Dependency>, models_path: &PathBuf, library_path: Option<&PathBuf>, scad_path: &PathBuf, build_library_path: &PathBuf) -> Result<(), Box<dyn Error>> {
+    for dependency in dependencies {
+        let (from, to): (PathBuf, PathBuf) = match (dependency, library_path) {
+            (Dependency::Model(path), _) => (models_path.join(path), scad_path.join(path)),
+            (Dependency::Library(path), Some(library_path)) => (library_path.join(path), build_library_path.join(path)),
+            (Dependency::Library(_), None) => continue
+        };
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(from, to)?;
+    }
+
+    Ok(())
+}
+
+fn locate(file: &PathBuf, models_path: &PathBuf, library_path: Option<&PathBuf>) -> Option<Dependency> {
+    if let Ok(relative) = file.strip_prefix(models_path) {
+        return Some(Dependency::Model(relative.to_path_buf()));
+    }
+    library_path
+        .and_then(|library_path| file.strip_prefix(library_path).ok())
+        .map(|relative| Dependency::Library(relative.to_path_buf()))
+}
+
+// The paths named by the `include <...>` and `use <...>` statements of a source, ignoring comments
+fn statement_targets(source: &str) -> Vec<String> {
+    let code: String = strip_comments(source);
+    let mut targets: Vec<String> = Vec::new();
+
+    for keyword in ["include", "use"] {
+        let mut rest: &str = &code;
+        while let Some(index) = rest.find(keyword) {
+            let preceding: Option<char> = rest[..index].chars().last();
+            rest = &rest[index + keyword.len()..];
+            // Skip identifiers that merely contain the keyword, such as `reuse` or `use_bolts`
+            if preceding.map_or(false, |c| c.is_alphanumeric() || c == '_') {
+                continue;
+            }
+            let statement: &str = rest.trim_start();
+            if let Some(statement) = statement.strip_prefix('<') {
+                if let Some(end) = statement.find('>') {
+                    targets.push(statement[..end].trim().to_string());
+                }
+            }
+        }
+    }
+
+    targets
+}
+
+fn strip_comments(source: &str) -> String {
+    let mut code: String = String::new();
+    let mut rest: &str = source;
+
+    loop {
+        let line_comment: Option<usize> = rest.find("//");
+        let block_comment: Option<usize> = rest.find("/*");
+        match (line_comment, block_comment) {
+            (Some(line), block) if block.map_or(true, |block| line < block) => {
+                code.push_str(&rest[..line]);
+                rest = rest[line..].find('\n').map_or("", |end| &rest[line + end..]);
+            },
+            (_, Some(block)) => {
+                code.push_str(&rest[..block]);
+                code.push(' ');
+                rest = rest[block + 2..].find("*/").map_or("", |end| &rest[block + 2 + end + 2..]);
+            },
+            (_, None) => {
+                code.push_str(rest);
+                return code;
+            }
+        }
+    }
+}
@@ -0,0 +1,368 @@
+// ***** Discover *****
+// Finds the models in the models directory. Any directory holding an `info.json` is a model, every
+// other directory is a category and is searched for models in turn. The categories a model is nested
+// in make up its default category, e.g. "tools/clamps". Hidden files and directories are
+// ignored. A model's files can be named in the optional "files" object of its info.json:
+//  * scad      -> The entry .scad file, which the parts' modules are used from
+//  * thumbnail -> The image shown in the models list, a preview is rendered for models without any image
+//  * gallery   -> The images shown on the model's page, in order
+// Files that aren't named are found by convention instead. A model's version is the "version" in its
+// info.json, or else the last git commit to change its directory (see `repository`), and it was last
+// modified when that commit was made, or else when its files were last written to. Every .scad file within the model
+// directory is treated as a source, and is copied to the build directory along with everything it
+// depends on (see `dependencies`).
+
+use crate::dependencies;
+use crate::repository;
+use chrono::{DateTime, Utc};
+use nest::config::BUILD_LIBRARY_DIRECTORY;
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::fs::canonicalize;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const IMAGE_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+
+// The version of a model that has neither a "version" nor a git history
+const UNVERSIONED: &str = "unversioned";
+
+#[derive(Debug)]
+pub enum DiscoveryError {
+    InvalidInfo(PathBuf, String),
+    MissingFile(PathBuf, String),
+    OutsideModel(PathBuf, String),
+    NoEntry(PathBuf),
+    AmbiguousEntry(PathBuf),
+    InvalidVersion(PathBuf, String)
+}
+
+impl fmt::Display for DiscoveryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiscoveryError::InvalidInfo(directory, error) => write!(f, "invalid info.json in '{}': {}", directory.display(), error),
+            DiscoveryError::MissingFile(directory, file) => write!(f, "the file '{}' named in '{}' does not exist", file, directory.display()),
+            DiscoveryError::OutsideModel(directory, file) => write!(f, "the file '{}' named in '{}' is outside of the model directory", file, directory.display()),
+            DiscoveryError::NoEntry(directory) => write!(f, "no .scad file found in '{}'", directory.display()),
+            DiscoveryError::AmbiguousEntry(directory) => {
+                write!(f, "several .scad files found in '{}', name the entry file with \"files\": {{\"scad\": ...}} in info.json", directory.display())
+            }
+            DiscoveryError::InvalidVersion(directory, version) => {
+                write!(f, "invalid version {} in '{}', versions may only contain letters, digits, '.', '-', '_' and '+'", version, directory.display())
+            }
+        }
+    }
+}
+
+impl Error for DiscoveryError {}
+
+// The files that make up a model. Every path other than `directory` is relative to the model directory.
+pub struct ModelFiles {
+    pub directory: PathBuf,
+    pub category: Option<String>,
+    pub version: String,
+    // The last commit to change the model directory, unless it has uncommitted changes
+    pub commit: Option<String>,
+    pub modified: DateTime<Utc>,
+    pub info: Value,
+    pub entry: PathBuf,
+    pub sources: Vec<PathBuf>,
+    pub thumbnail: Option<PathBuf>,
+    pub gallery: Vec<PathBuf>
+}
+
+// The build paths of a model's files, relative to the build directory
+pub struct BuiltFiles {
+    pub scad_path: String,
+    pub image_path: Option<String>,
+    pub gallery: Vec<String>
+}
+
+// Find every model below the models directory, in a stable order
+pub fn discover_models(models_path: &PathBuf) -> Result<Vec<ModelFiles>, Box<dyn Error>> {
+    let mut models: Vec<ModelFiles> = Vec::new();
+    for directory in visible_entries(models_path)? {
+        if directory.is_dir() {
+            discover_directory(&directory, None, &mut models)?;
+        }
+    }
+
+    Ok(models)
+}
+
+// A single model directory, in the given category
+pub fn discover_model(directory: &PathBuf, category: Option<&str>) -> Result<ModelFiles, Box<dyn Error>> {
+    if !directory.join("info.json").is_file() {
+        Err(DiscoveryError::InvalidInfo(directory.clone(), String::from("the directory has no info.json")))?;
+    }
+
+    model_files(directory, category)
+}
+
+fn discover_directory(directory: &PathBuf, category: Option<&str>, models: &mut Vec<ModelFiles>) -> Result<(), Box<dyn Error>> {
+    if directory.join("info.json").is_file() {
+        models.push(model_files(directory, category)?);
+        return Ok(());
+    }
+
+    let name: String = directory.file_name().unwrap().to_string_lossy().to_string();
+    let category: String = match category {
+        Some(category) => format!("{}/{}", category, name),
+        None => name
+    };
+    for entry in visible_entries(directory)? {
+        if entry.is_dir() {
+            discover_directory(&entry, Some(&category), models)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn model_files(directory: &PathBuf, category: Option<&str>) -> Result<ModelFiles, Box<dyn Error>> {
+    let info_string: String = fs::read_to_string(directory.join("info.json"))?;
+    let info: Value = serde_json::from_str(&info_string)
+        .map_err(|error| DiscoveryError::InvalidInfo(directory.clone(), error.to_string()))?;
+    check_info(directory, &info)?;
+    let files: &Value = &info["files"];
+
+    let sources: Vec<PathBuf> = find_files(directory, Path::new(""), &["scad"])?;
+    let entry: PathBuf = match files["scad"].as_str() {
+        Some(file) => named_file(directory, file)?,
+        None => default_entry(directory, &sources)?
+    };
+
+    let images: Vec<PathBuf> = visible_entries(directory)?.into_iter()
+        .filter(|path| path.is_file() && has_extension(path, &IMAGE_EXTENSIONS))
+        .map(|path| PathBuf::from(path.file_name().unwrap()))
+        .collect();
+    let thumbnail: Option<PathBuf> = match files["thumbnail"].as_str() {
+        Some(file) => Some(named_file(directory, file)?),
+        None => default_thumbnail(&images)
+    };
+    let gallery: Vec<PathBuf> = match files["gallery"].as_array() {
+        Some(gallery) => {
+            let mut named: Vec<PathBuf> = Vec::new();
+            for file in gallery {
+                let file: &str = file.as_str()
+                    .ok_or_else(|| DiscoveryError::InvalidInfo(directory.clone(), String::from("gallery images must be file names")))?;
+                named.push(named_file(directory, file)?);
+            }
+            named
+        },
+        None => images.into_iter().filter(|image| Some(image) != thumbnail.as_ref()).collect()
+    };
+
+    let last_commit: Option<repository::DirectoryCommit> = repository::last_commit(directory);
+    // Uncommitted changes are more recent than the commit
+    let modified: DateTime<Utc> = match &last_commit {
+        Some(commit) if !commit.dirty => commit.date,
+        _ => DateTime::from(last_modification(directory)?)
+    };
+
+    Ok(ModelFiles {
+        directory: directory.clone(),
+        category: category.map(String::from),
+        version: model_version(directory, &info, last_commit.as_ref())?,
+        commit: last_commit.filter(|commit| !commit.dirty).map(|commit| commit.hash),
+        modified,
+        info,
+        entry,
+        sources,
+        thumbnail,
+        gallery
+    })
+}
+
+// Copy a model's sources, their dependencies and its images into a build directory of its own. The
+// sources keep their place within the models directory.
+pub fn build_model_files(model: &ModelFiles, models_path: &PathBuf, library_path: Option<&PathBuf>, build_path: &PathBuf, build_directory: &Path) -> Result<BuiltFiles, Box<dyn Error>> {
+    // An entry named in info.json may not have the .scad extension, in which case it isn't a source
+    let mut sources: Vec<PathBuf> = model.sources.iter().map(|source| model.directory.join(source)).collect();
+    if !model.sources.contains(&model.entry) {
+        sources.push(model.directory.join(&model.entry));
+    }
+    let resolved: Vec<dependencies::Dependency> = dependencies::resolve(&sources, models_path, library_path)?;
+    let scad_directory: PathBuf = build_directory.join("scad");
+    dependencies::copy(&resolved, models_path, library_path, &build_path.join(&scad_directory), &build_path.join(build_directory).join(BUILD_LIBRARY_DIRECTORY))?;
+
+    let images_directory: PathBuf = build_directory.join("images");
+    let mut image_paths: Vec<String> = Vec::new();
+    for image in model.thumbnail.iter().chain(model.gallery.iter()) {
+        copy_file(&model.directory.join(image), &build_path.join(&images_directory).join(image))?;
+        image_paths.push(build_relative(&images_directory.join(image)));
+    }
+
+    Ok(BuiltFiles {
+        scad_path: build_relative(&scad_directory.join(model.directory.strip_prefix(models_path)?).join(&model.entry)),
+        image_path: if model.thumbnail.is_some() { Some(image_paths.remove(0)) } else { None },
+        gallery: image_paths
+    })
+}
+
+fn model_version(directory: &PathBuf, info: &Value, last_commit: Option<&repository::DirectoryCommit>) -> Result<String, DiscoveryError> {
+    let version: String = match &info["version"] {
+        Value::String(version) => version.trim().to_string(),
+        Value::Number(version) => version.to_string(),
+        // Changes that haven't been committed yet are marked as such
+        Value::Null => match last_commit {
+            Some(commit) if commit.dirty => format!("{}-dirty", commit.short_hash),
+            Some(commit) => commit.short_hash.to_string(),
+            None => String::from(UNVERSIONED)
+        },
+        version => return Err(DiscoveryError::InvalidVersion(directory.clone(), version.to_string()))
+    };
+
+    if !valid_version(&version) {
+        return Err(DiscoveryError::InvalidVersion(directory.clone(), format!("'{}'", version)));
+    }
+    Ok(version)
+}
+
+pub fn valid_version(version: &str) -> bool {
+    !version.is_empty() && version.chars().all(|c| c.is_ascii_alphanumeric() || ".-_+".contains(c))
+}
+
+// A category is a path of visible directories within the models directory, e.g. "tools/clamps"
+pub fn valid_category(category: &str) -> bool {
+    category.split('/').all(|directory| !directory.is_empty() && !directory.starts_with('.') && !directory.contains('\\'))
+}
+
+// The fields every info.json needs. Names make up the model's build directory, so they can't be paths.
+fn check_info(directory: &PathBuf, info: &Value) -> Result<(), DiscoveryError> {
+    for field in ["name", "description", "author"] {
+        if !info[field].is_string() {
+            return Err(DiscoveryError::InvalidInfo(directory.clone(), format!("\"{}\" must be a string", field)));
+        }
+    }
+    let name: &str = info["name"].as_str().unwrap();
+    if name.trim().is_empty() || name.starts_with('.') || name.contains('/') || name.contains('\\') {
+        return Err(DiscoveryError::InvalidInfo(directory.clone(), format!("'{}' is not a valid name, names can't be empty, start with '.' or contain '/' or '\\'", name)));
+    }
+    if !info["parts"].is_array() {
+        return Err(DiscoveryError::InvalidInfo(directory.clone(), String::from("\"parts\" must be a list")));
+    }
+
+    Ok(())
+}
+
+// The directory entries that aren't hidden, sorted by name
+fn visible_entries(directory: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut entries: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let entry_path: PathBuf = entry?.path();
+        let hidden: bool = entry_path.file_name()
+            .and_then(|name| name.to_str())
+            .map_or(true, |name| name.starts_with('.'));
+        if !hidden {
+            entries.push(entry_path);
+        }
+    }
+    entries.sort();
+
+    Ok(entries)
+}
+
+// Files with one of the extensions anywhere below `relative` in the model directory
+fn find_files(directory: &Path, relative: &Path, extensions: &[&str]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for entry in visible_entries(&directory.join(relative))? {
+        let entry_relative: PathBuf = relative.join(entry.file_name().unwrap());
+        if entry.is_dir() {
+            files.extend(find_files(directory, &entry_relative, extensions)?);
+        } else if has_extension(&entry, extensions) {
+            files.push(entry_relative);
+        }
+    }
+
+    Ok(files)
+}
+
+// When any file below the directory was last written to
+fn last_modification(directory: &Path) -> Result<SystemTime, Box<dyn Error>> {
+    let mut latest: SystemTime = SystemTime::UNIX_EPOCH;
+    for entry in visible_entries(directory)? {
+        let modified: SystemTime = if entry.is_dir() {
+            last_modification(&entry)?
+        } else {
+            fs::metadata(&entry)?.modified()?
+        };
+        latest = latest.max(modified);
+    }
+
+    Ok(latest)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => extensions.contains(&extension.to_lowercase().as_str()),
+        None => false
+    }
+}
+
+// A file named in info.json, which must exist within the model directory
+fn named_file(directory: &PathBuf, file: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let path: PathBuf = directory.join(file);
+    if !path.is_file() {
+        Err(DiscoveryError::MissingFile(directory.clone(), file.to_string()))?;
+    }
+    if !canonicalize(&path)?.starts_with(canonicalize(directory)?) {
+        Err(DiscoveryError::OutsideModel(directory.clone(), file.to_string()))?;
+    }
+
+    Ok(PathBuf::from(file))
+}
+
+// Without a named entry, the only .scad file at the top of the model directory is used, or the one
+// named after the directory when there are several
+fn default_entry(directory: &PathBuf, sources: &Vec<PathBuf>) -> Result<PathBuf, DiscoveryError> {
+    let top_level: Vec<&PathBuf> = sources.iter().filter(|source| source.parent() == Some(Path::new(""))).collect();
+    match top_level.len() {
+        0 => Err(DiscoveryError::NoEntry(directory.clone())),
+        1 => Ok(top_level[0].clone()),
+        _ => top_level.into_iter()
+            .find(|source| source.file_stem() == directory.file_name())
+            .cloned()
+            .ok_or_else(|| DiscoveryError::AmbiguousEntry(directory.clone()))
+    }
+}
+
+// Without a named thumbnail, an image called `thumbnail` is used, or else the first image
+fn default_thumbnail(images: &Vec<PathBuf>) -> Option<PathBuf> {
+    images.iter()
+        .find(|image| image.file_stem().and_then(|stem| stem.to_str()) == Some("thumbnail"))
+        .or(images.first())
+        .cloned()
+}
+
+// Copy a directory and everything below it, apart from hidden files and directories
+pub fn copy_directory(from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(to)?;
+    for entry in visible_entries(from)? {
+        let target: PathBuf = to.join(entry.file_name().unwrap());
+        if entry.is_dir() {
+            copy_directory(&entry, &target)?;
+        } else {
+            fs::copy(&entry, &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn copy_file(from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(from, to)?;
+
+    Ok(())
+}
+
+// Build paths are served over HTTP, so they always use forward slashes
+fn build_relative(path: &Path) -> String {
+    let components: Vec<String> = path.iter().map(|component| component.to_string_lossy().to_string()).collect();
+    components.join("/")
+}
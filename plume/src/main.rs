@@ -1,19 +1,35 @@
 // ***** Plume *****
 // Tool for the management of models in parakeet.
 // Commands:
-//  * config        -> Sets up the plume configuration with the provided paths
-//  * index         -> Traverses and indexes the models in the models directory
+//  * config init   -> Sets up the plume configuration, creating the directories and database
+//  * config show   -> Shows the current configuration
+//  * config set    -> Changes a single configuration value
+//  * index         -> Traverses and indexes the models in the models directory, or in a git repository
+//  * add           -> Adds a single model to the models directory and indexes just that model
+//  * thumbnails    -> Renders the previews of the models that ship without an image again
+//  * db migrate    -> Applies any pending migrations to the database schema
+//  * db status     -> Shows the schema version of the database
+// The configured paths can be overridden by the global --models, --build, --database, --limit and --library flags,
+// or by the PARAKEET_* environment variables. Named profiles are picked with --profile or PARAKEET_PROFILE.
 
 mod config;
+mod dependencies;
+mod discover;
 mod parse;
+mod repository;
+mod schema;
+mod thumbnail;
 
-use chrono::NaiveDate;
+use chrono::{NaiveDate, SecondsFormat};
+use nest::config::{BUILD_LIBRARY_DIRECTORY, Overrides, ParakeetConfig, instance_preview_path, profile_from_env};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use std::error::Error;
+use std::fmt;
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::{env, process};
 use sqlx::{SqlitePool};
 use structopt::StructOpt;
 
@@ -22,10 +38,84 @@ use structopt::StructOpt;
     name = "plume",
     about = "Tool for the management of models in parakeet"
 )]
+struct Plume {
+    /// Use this models directory instead of the configured one
+    #[structopt(long = "models", global = true)]
+    models: Option<PathBuf>,
+    /// Use this build directory instead of the configured one
+    #[structopt(long = "build", global = true)]
+    build: Option<PathBuf>,
+    /// Use this database file instead of the configured one
+    #[structopt(long = "database", global = true)]
+    database: Option<PathBuf>,
+    /// Use this .stl model limit instead of the configured one
+    #[structopt(long = "limit", global = true)]
+    limit: Option<i64>,
+    /// Use this shared .scad library directory instead of the configured one
+    #[structopt(long = "library", global = true)]
+    library: Option<PathBuf>,
+    /// Use this named config profile instead of the default one
+    #[structopt(long = "profile", global = true)]
+    profile: Option<String>,
+    #[structopt(subcommand)]
+    command: Commands
+}
+
+#[derive(StructOpt)]
 enum Commands {
     /// Configure plume with the relevant path information
     #[structopt(name = "config")]
     Config {
+        #[structopt(subcommand)]
+        command: ConfigCommands
+    },
+    /// Index the models directory and output an 'index.json' file
+    #[structopt(name = "index")]
+    Index {
+        /// Regenerate the .stl instances recorded in the database whose files are missing
+        #[structopt(short, long)]
+        restore: bool,
+        /// Index the models of this local git repository instead of the models directory
+        #[structopt(long = "git")]
+        git: Option<PathBuf>,
+        /// The branch, tag or commit of the git repository to index
+        #[structopt(long = "ref", default_value = "HEAD")]
+        reference: String,
+        /// Index every tag of the git repository as a version of its models, instead of a single ref
+        #[structopt(long)]
+        tags: bool
+    },
+    /// Add a single model directory to the models directory and index just that model, replacing any model
+    /// of the same name
+    #[structopt(name = "add")]
+    Add {
+        /// The model directory, holding its info.json
+        directory: PathBuf,
+        /// The category directory to add a new model to, e.g. "tools/clamps"
+        #[structopt(long)]
+        category: Option<String>,
+        /// Report the outcome as a JSON object, holding either the `path` of the added model or an `error`,
+        /// and exit with a failure status when the model could not be added
+        #[structopt(long)]
+        json: bool
+    },
+    /// Render the previews of the models that ship without an image again, e.g. after changing the thumbnail settings
+    #[structopt(name = "thumbnails")]
+    Thumbnails,
+    /// Manage the database schema
+    #[structopt(name = "db")]
+    Db {
+        #[structopt(subcommand)]
+        command: DbCommands
+    }
+}
+
+#[derive(StructOpt)]
+enum ConfigCommands {
+    /// Set up a configuration, creating its directories and an empty database where they don't exist,
+    /// with the shared library given by --library
+    #[structopt(name = "init")]
+    Init {
         /// Models directory path
         models_path: PathBuf,
         /// Build directory path
@@ -33,45 +123,74 @@ enum Commands {
         /// Database file (.sqlite) path
         database_path: PathBuf,
         /// Maximum number of .stl models stored at any one time
+        #[structopt(default_value = "100")]
         model_limit: i64
     },
-    /// Index the models directory and output an 'index.json' file
-    #[structopt(name = "index")]
-    Index {
-        /// Attempt to restore .stl instances based on database information
-        #[structopt(short, long)]
-        restore: bool
+    /// Show the current configuration, including any overrides
+    #[structopt(name = "show")]
+    Show,
+    /// Change a single configuration value (models_path, build_path, database_path, model_limit, library_path,
+    /// thumbnail_size, thumbnail_camera, request_limit, render_limit or plume_path)
+    #[structopt(name = "set")]
+    Set {
+        key: String,
+        value: String
     }
 }
 
+#[derive(StructOpt)]
+enum DbCommands {
+    /// Apply any pending migrations to the database, creating it if it doesn't exist
+    #[structopt(name = "migrate")]
+    Migrate,
+    /// Show the schema version of the database and the latest version supported by plume
+    #[structopt(name = "status")]
+    Status
+}
+
 #[tokio::main]
 async fn main() {
-    let paths = config::get_paths().expect("Could not read config information.");
-    let config_models_path = &paths[0];
-    let config_build_path= &paths[1];
-    let config_database_path = &paths[2];
-
-    // Development: For use upon changing ParakeetConfig format
-    // let config_models_path = &PathBuf::from("../models");
-    // let config_build_path= &PathBuf::from("../build");
-    // let config_database_path = &PathBuf::from("../database");
-
-    match Commands::from_args() {
-        Commands::Config {
-            models_path,
-            build_path,
-            database_path,
-            model_limit
-        } => match config::config(models_path, build_path, database_path, model_limit) {
-            Ok(_) => println!("Successfully configured plume. Plume is now ready to use."),
-            Err(error) => println!("Failed to configure plume: [{}]", error),
-        },
-        Commands::Index {restore} => {
-            let path_str = config_models_path.to_str().unwrap();
-            let pool: SqlitePool = SqlitePool::connect(&format!("sqlite:{}", &config_database_path.to_str().unwrap()))
-                .await
-                .expect("Failed to connect to database.");
-            match index(config_build_path, config_models_path, restore, pool).await {
+    let plume: Plume = Plume::from_args();
+    let profile: Option<String> = plume.profile.or_else(profile_from_env);
+
+    let overrides: Overrides = Overrides {
+        models_path: plume.models,
+        build_path: plume.build,
+        database_path: plume.database,
+        model_limit: plume.limit,
+        library_path: plume.library
+    };
+    let environment: Overrides = match Overrides::from_env() {
+        Ok(environment) => environment,
+        Err(error) => return println!("Failed to read config information: [{}]", error)
+    };
+    let overrides: Overrides = overrides.over(environment);
+
+    // Configuring doesn't need a usable config to exist yet
+    if let Commands::Config {command} = plume.command {
+        return config_command(command, profile.as_deref(), overrides).await;
+    }
+
+    let config: ParakeetConfig = match ParakeetConfig::load(profile.as_deref(), overrides) {
+        Ok(config) => config,
+        Err(error) => return println!("Failed to read config information: [{}]", error)
+    };
+    let config_models_path: &PathBuf = &config.models_path;
+    let config_database_path: &PathBuf = &config.database_path;
+
+    match plume.command {
+        Commands::Config {..} => {}, // Handled above
+        Commands::Index {restore, git, reference, tags} => {
+            let source: ModelSource = match git {
+                Some(repository) => ModelSource::Repository {repository, reference, tags},
+                None => ModelSource::Directory(config_models_path.clone())
+            };
+            let path_str = source.path().to_str().unwrap();
+            let pool: SqlitePool = match schema::connect(config_database_path).await {
+                Ok(pool) => pool,
+                Err(error) => return println!("Failed to connect to database: [{}]", error)
+            };
+            match index(&config, &source, restore, pool).await {
                 Ok(_) => println!(
                     "Successfully indexed `{}`. Outputted to `{}`",
                     path_str,
@@ -79,88 +198,390 @@ async fn main() {
                 ),
                 Err(error) => println!("Failed to index `{}`: [{}]", path_str, error),
             }
+        },
+        Commands::Add {directory, category, json: false} => {
+            let pool: SqlitePool = match schema::connect(config_database_path).await {
+                Ok(pool) => pool,
+                Err(error) => return println!("Failed to connect to database: [{}]", error)
+            };
+            match add(&config, &directory, category.as_deref(), pool).await {
+                Ok(destination) => println!("Successfully added `{}` as `{}`.", directory.to_str().unwrap(), destination.to_str().unwrap()),
+                Err(error) => println!("Failed to add `{}`: [{}]", directory.to_str().unwrap(), error),
+            }
+        },
+        // For programs such as roost that add models on someone's behalf
+        Commands::Add {directory, category, json: true} => {
+            let added: Result<PathBuf, Box<dyn Error>> = match schema::connect(config_database_path).await {
+                Ok(pool) => add(&config, &directory, category.as_deref(), pool).await,
+                Err(error) => Err(error)
+            };
+            match added {
+                Ok(destination) => println!("{}", json!({ "path": destination })),
+                Err(error) => {
+                    println!("{}", json!({ "error": error.to_string() }));
+                    process::exit(1);
+                }
+            }
+        },
+        Commands::Thumbnails => match thumbnails(&config).await {
+            Ok(rendered) => println!("Successfully rendered {} previews.", rendered),
+            Err(error) => println!("Failed to render the previews: [{}]", error),
+        },
+        Commands::Db {command: DbCommands::Migrate} => match schema::connect(config_database_path).await {
+            Ok(_) => println!("Successfully migrated `{}` to schema version {}.", config_database_path.to_str().unwrap(), schema::supported_version()),
+            Err(error) => println!("Failed to migrate `{}`: [{}]", config_database_path.to_str().unwrap(), error),
+        },
+        Commands::Db {command: DbCommands::Status} => match db_status(config_database_path).await {
+            Ok(_) => {}
+            Err(error) => println!("Failed to read the schema of `{}`: [{}]", config_database_path.to_str().unwrap(), error),
         }
     }
 }
 
-// Parse and index the models directory into the database
-async fn index(build_path: &PathBuf, models_path: &PathBuf, restore: bool, pool: SqlitePool) -> Result<(), Box<dyn Error>> {
-    let scad_path = build_path.join("scad/");
-    if !scad_path.exists() {
-        fs::create_dir(&scad_path)?;
+async fn config_command(command: ConfigCommands, profile: Option<&str>, overrides: Overrides) {
+    match command {
+        ConfigCommands::Init {
+            models_path,
+            build_path,
+            database_path,
+            model_limit
+        } => match config::init(profile, models_path, build_path, database_path, model_limit, overrides.library_path).await {
+            Ok(_) => println!("Successfully configured plume. Plume is now ready to use."),
+            Err(error) => println!("Failed to configure plume: [{}]", error),
+        },
+        ConfigCommands::Show => match config::show(profile, overrides) {
+            Ok(_) => {}
+            Err(error) => println!("Failed to read config information: [{}]", error),
+        },
+        ConfigCommands::Set {key, value} => match config::set(profile, &key, &value) {
+            Ok(_) => println!("Successfully set `{}` to `{}`.", key, value),
+            Err(error) => println!("Failed to set `{}`: [{}]", key, error),
+        }
     }
-    for entry in fs::read_dir(scad_path)? {
-        let entry_path = entry?.path();
-        fs::remove_file(entry_path)?;
+}
+
+async fn db_status(database_path: &PathBuf) -> Result<(), Box<dyn Error>> {
+    let pool: SqlitePool = schema::open(database_path).await?;
+    match schema::current_version(&pool).await? {
+        Some(version) => println!("Database schema version: {}", version),
+        None => println!("Database schema version: none, run 'plume db migrate' to create the schema")
     }
+    println!("Latest supported schema version: {}", schema::supported_version());
 
-    let images_path = build_path.join("images/");
-    if !images_path.exists() {
-        fs::create_dir(&images_path)?;
+    Ok(())
+}
+
+// Where the models to index are read from
+enum ModelSource {
+    Directory(PathBuf),
+    // A ref of a git repository, or each of its tags
+    Repository {
+        repository: PathBuf,
+        reference: String,
+        tags: bool
     }
-    for entry in fs::read_dir(images_path)? {
-        let entry_path = entry?.path();
-        fs::remove_file(entry_path)?;
+}
+
+impl ModelSource {
+    fn path(&self) -> &PathBuf {
+        match self {
+            ModelSource::Directory(models_path) => models_path,
+            ModelSource::Repository {repository, ..} => repository
+        }
     }
+}
 
+// Parse and index the models into the database. Versions that were indexed before are kept, unless the
+// same version is indexed again, in which case it is replaced.
+async fn index(config: &ParakeetConfig, source: &ModelSource, restore: bool, pool: SqlitePool) -> Result<(), Box<dyn Error>> {
+    let build_path: &PathBuf = &config.build_path;
     let stls_path = build_path.join("stls/");
     if !stls_path.exists() {
         fs::create_dir(&stls_path)?;
     }
 
-    let mut instances: Vec<parse::Instance> = Vec::new();
-    if restore { instances = parse::db_save_instances(&pool).await? }
+    // Models indexed before versioning were built into the root of the build directory and are replaced
+    for model_id in parse::db_legacy_model_ids(&pool).await? {
+        remove_model(&pool, build_path, model_id).await?;
+    }
+    for directory in ["scad", "images", BUILD_LIBRARY_DIRECTORY] {
+        let directory_path: PathBuf = build_path.join(directory);
+        if directory_path.exists() {
+            fs::remove_dir_all(&directory_path)?;
+        }
+    }
+
+    parse::db_retire_models(&pool).await?;
 
-    parse::db_reset(&pool).await?;
+    let mut id_counter: parse::IdCounter = parse::db_next_ids(&pool).await?;
+    match source {
+        ModelSource::Directory(models_path) => {
+            index_models(&pool, config, models_path, None, &mut id_counter).await?;
+        },
+        ModelSource::Repository {repository, reference, tags} => {
+            // Tags are indexed oldest first, which leaves the newest current
+            let mut references: Vec<(String, Option<String>)> = Vec::new();
+            if *tags {
+                for tag in repository::tags(repository)? {
+                    if discover::valid_version(&tag) {
+                        references.push((tag.to_string(), Some(tag)));
+                    } else {
+                        println!("Warning: skipped the tag '{}', versions may only contain letters, digits, '.', '-', '_' and '+'", tag);
+                    }
+                }
+            } else {
+                references.push((reference.to_string(), None));
+            }
 
-    let mut id_counter: parse::IdCounter = parse::IdCounter {
-        model_id: 0,
-        part_id: 0,
-        parameter_id: 0
-    };
-    let flattened_models = parse::traverse_models_dir(models_path, false)?;
-    for entry in flattened_models {
-        let info_string = fs::read_to_string(&entry.2)?;
-        let info_json: Value = serde_json::from_str(&info_string)?;
-
-        parse::db_add_model(
-            &pool,
-            id_counter.model_id,
-            info_json["name"].as_str().unwrap(),
-            info_json["date"].as_str().unwrap(),
-            info_json["description"].as_str().unwrap(),
-            info_json["author"].as_str().unwrap(),
-            &format!("images/{}.jpg", info_json["name"].as_str().unwrap()),
-            &format!("scad/{}.scad", info_json["name"].as_str().unwrap()),
-        ).await?;
-
-        fs::metadata(&entry.1)?;
-        fs::copy(
-            &entry.1,
-            build_path.join(format!("scad/{}.scad", info_json["name"].as_str().unwrap())),
-        )?;
-        fs::metadata(&entry.0)?;
-        fs::copy(
-            &entry.0,
-            build_path.join(format!("images/{}.jpg", info_json["name"].as_str().unwrap())),
-        )?;
-
-        parse::parse_parts(
-            &pool,
-            &info_json["parts"].as_array().unwrap(),
-            info_json["name"].as_str().unwrap(),
-            &mut id_counter,
-            &build_path.join(format!("scad/{}.scad", info_json["name"].as_str().unwrap())),
-        ).await?;
-        id_counter.model_id += 1;
+            let checkout_path: PathBuf = env::temp_dir().join(format!("plume-checkout-{}", process::id()));
+            for (reference, version) in references {
+                let commit: String = repository::resolve(repository, &reference)?;
+                repository::checkout(repository, &commit, &checkout_path)?;
+                println!("Indexing `{}` at {} ({})", repository.to_str().unwrap(), reference, commit);
+
+                let indexed: Result<(), Box<dyn Error>> = index_models(&pool, config, &checkout_path, version.as_deref(), &mut id_counter).await;
+                fs::remove_dir_all(&checkout_path)?;
+                indexed?;
+            }
+        }
     }
 
     if restore {
-        match parse::restore(&pool, build_path, instances).await {
-            Ok(_) => {}
-            Err(_) => println!("Models' structure has changed, restoration has failed. Run 'plume index' without '--restore' flag.")
+        parse::restore(&pool, build_path).await?;
+    }
+
+    // Let roost know that the models it has cached are out of date
+    parse::db_bump_generation(&pool).await?;
+
+    Ok(())
+}
+
+// Index every model of a models directory, as the given version when there is one
+async fn index_models(pool: &SqlitePool, config: &ParakeetConfig, models_path: &PathBuf, version: Option<&str>, id_counter: &mut parse::IdCounter) -> Result<(), Box<dyn Error>> {
+    for model in discover::discover_models(models_path)? {
+        index_model(pool, config, models_path, &model, version, id_counter).await?;
+    }
+
+    Ok(())
+}
+
+// Index a model as the given version, or else as its own version, and make it the current version. Models
+// without an image are given a rendered preview.
+async fn index_model(pool: &SqlitePool, config: &ParakeetConfig, models_path: &PathBuf, model: &discover::ModelFiles, version: Option<&str>, id_counter: &mut parse::IdCounter) -> Result<(), Box<dyn Error>> {
+    let build_path: &PathBuf = &config.build_path;
+    let info_json: &Value = &model.info;
+    let name: &str = info_json["name"].as_str().unwrap();
+    let version: &str = version.unwrap_or(&model.version);
+    let tags: Vec<String> = parse::parse_tags(info_json, name)?;
+    let category: Option<String> = parse::parse_category(info_json, model.category.as_ref(), name)?;
+    let creation_date: String = parse::parse_date(info_json, name)?;
+
+    if let Some((model_id, commit)) = parse::db_find_version(pool, name, version).await? {
+        // A version built from the same commit is already up to date, along with its instances
+        if commit.is_some() && commit == model.commit {
+            parse::db_make_current(pool, name, model_id).await?;
+            return Ok(());
+        }
+        remove_model(pool, build_path, model_id).await?;
+    }
+
+    let build_directory: String = format!("versions/{}/{}", name, version);
+    let built: discover::BuiltFiles = discover::build_model_files(model, models_path, config.library_path.as_ref(), build_path, Path::new(&build_directory))?;
+
+    parse::db_add_model(
+        pool,
+        id_counter.model_id,
+        name,
+        &creation_date,
+        &model.modified.to_rfc3339_opts(SecondsFormat::Secs, true),
+        info_json["description"].as_str().unwrap(),
+        info_json["author"].as_str().unwrap(),
+        built.image_path.as_deref().unwrap_or(""),
+        &built.scad_path,
+        category.as_deref(),
+        version,
+        model.commit.as_deref(),
+        &build_directory,
+    ).await?;
+    parse::db_make_current(pool, name, id_counter.model_id).await?;
+    for tag in &tags {
+        parse::db_add_tag(pool, id_counter.model_id, tag).await?;
+    }
+    for (position, image_path) in built.gallery.iter().enumerate() {
+        parse::db_add_image(pool, id_counter.model_id, position as i64, image_path).await?;
+    }
+
+    // Parameters shared by every part of the model are optional
+    let shared_parameters: Vec<Value> = match info_json["parameters"].as_array() {
+        Some(parameters) => parameters.to_vec(),
+        None => Vec::new()
+    };
+    let model_constraints: Vec<Value> = match info_json["constraints"].as_array() {
+        Some(constraints) => constraints.to_vec(),
+        None => Vec::new()
+    };
+    parse::validate_expressions(&shared_parameters, &model_constraints, &parse::default_values(&shared_parameters))?;
+
+    let model_owner: parse::ParameterOwner = parse::ParameterOwner::Model(id_counter.model_id);
+    parse::parse_parameters(
+        pool,
+        &shared_parameters,
+        id_counter,
+        name,
+        model_owner
+    ).await?;
+    parse::parse_constraints(pool, &model_constraints, model_owner).await?;
+
+    parse::parse_parts(
+        pool,
+        &info_json["parts"].as_array().unwrap(),
+        &shared_parameters,
+        name,
+        id_counter
+    ).await?;
+
+    // A model is still indexed when its preview can't be rendered, it is only shown without an image
+    if built.image_path.is_none() {
+        if let Err(error) = thumbnail::render(pool, config, id_counter.model_id).await {
+            println!("Warning: {}", error);
+        }
+    }
+    id_counter.model_id += 1;
+
+    Ok(())
+}
+
+// Copy a model directory into the models directory and index just that model, leaving every other model
+// as it is. A model of the same name is replaced where it is, other models are added to the given category
+// directory. The model's files are put back as they were when it fails to index. Returns where the model
+// was added to.
+async fn add(config: &ParakeetConfig, directory: &PathBuf, category: Option<&str>, pool: SqlitePool) -> Result<PathBuf, Box<dyn Error>> {
+    let models_path: &PathBuf = &config.models_path;
+    if let Some(category) = category {
+        if !discover::valid_category(category) {
+            Err(AddError::InvalidCategory(category.to_string()))?;
+        }
+    }
+
+    // The model is checked before anything in the models directory is touched
+    let added: discover::ModelFiles = discover::discover_model(directory, category)?;
+    let name: &str = added.info["name"].as_str().unwrap();
+    let existing: Option<discover::ModelFiles> = discover::discover_models(models_path)?.into_iter()
+        .find(|model| model.info["name"].as_str() == Some(name));
+    let destination: PathBuf = match &existing {
+        Some(model) => model.directory.clone(),
+        None => {
+            let parent: PathBuf = category.map_or(models_path.clone(), |category| models_path.join(category));
+            let destination: PathBuf = parent.join(directory.file_name().ok_or("invalid model directory")?);
+            if destination.exists() {
+                Err(AddError::DirectoryTaken(destination.clone()))?;
+            }
+            destination
+        }
+    };
+
+    // The model being replaced is set aside as a hidden directory, which isn't discovered
+    let backup: PathBuf = destination.with_file_name(format!(".{}.previous", destination.file_name().unwrap().to_string_lossy()));
+    if destination.exists() {
+        fs::rename(&destination, &backup)?;
+    }
+    discover::copy_directory(directory, &destination)?;
+
+    match index_added(&pool, config, &destination).await {
+        Ok(_) => {
+            if backup.exists() {
+                fs::remove_dir_all(&backup)?;
+            }
+        },
+        Err(error) => {
+            fs::remove_dir_all(&destination)?;
+            if backup.exists() {
+                fs::rename(&backup, &destination)?;
+                if let Err(restore_error) = index_added(&pool, config, &destination).await {
+                    println!("Warning: could not index the previous version of '{}' again: {}", name, restore_error);
+                }
+            }
+            return Err(error);
+        }
+    }
+
+    Ok(destination)
+}
+
+// Index a single model of the models directory. A version that fails part way through indexing is removed
+// again, so that it doesn't show up half indexed.
+async fn index_added(pool: &SqlitePool, config: &ParakeetConfig, directory: &PathBuf) -> Result<(), Box<dyn Error>> {
+    let models_path: &PathBuf = &config.models_path;
+    let category: Option<String> = directory.parent()
+        .and_then(|parent| parent.strip_prefix(models_path).ok())
+        .map(|parent| parent.iter().map(|component| component.to_string_lossy().to_string()).collect::<Vec<String>>().join("/"))
+        .filter(|category| !category.is_empty());
+    let model: discover::ModelFiles = discover::discover_model(directory, category.as_deref())?;
+    let name: &str = model.info["name"].as_str().unwrap();
+
+    let mut id_counter: parse::IdCounter = parse::db_next_ids(pool).await?;
+    let model_id: i64 = id_counter.model_id;
+    if let Err(error) = index_model(pool, config, models_path, &model, None, &mut id_counter).await {
+        if let Some((found_id, _)) = parse::db_find_version(pool, name, &model.version).await? {
+            if found_id == model_id {
+                remove_model(pool, &config.build_path, model_id).await?;
+            }
+        }
+        return Err(error);
+    }
+
+    // Let roost know that the models it has cached are out of date
+    parse::db_bump_generation(pool).await?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+enum AddError {
+    InvalidCategory(String),
+    DirectoryTaken(PathBuf)
+}
+
+impl fmt::Display for AddError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddError::InvalidCategory(category) => write!(f, "'{}' is not a valid category, categories are directories such as \"tools/clamps\"", category),
+            AddError::DirectoryTaken(directory) => write!(f, "'{}' already exists and doesn't hold a model of the same name", directory.display())
+        }
+    }
+}
+
+impl Error for AddError {}
+
+// Render the previews of the current models again, returning how many were rendered
+async fn thumbnails(config: &ParakeetConfig) -> Result<usize, Box<dyn Error>> {
+    let pool: SqlitePool = schema::connect(&config.database_path).await?;
+
+    let mut rendered: usize = 0;
+    for model_id in thumbnail::generated(&pool).await? {
+        match thumbnail::render(&pool, config, model_id).await {
+            Ok(_) => rendered += 1,
+            Err(error) => println!("Warning: {}", error)
         }
     }
 
+    Ok(rendered)
+}
+
+// Remove a version of a model, along with its build directory and the .stl instances made from it
+async fn remove_model(pool: &SqlitePool, build_path: &PathBuf, model_id: i64) -> Result<(), Box<dyn Error>> {
+    let (build_directory, instance_paths): (String, Vec<String>) = parse::db_remove_model(pool, model_id).await?;
+    for instance_path in instance_paths {
+        for path in [build_path.join(&instance_path), build_path.join(instance_preview_path(&instance_path))] {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+    }
+    // Models indexed before versioning share the root of the build directory, which is cleared separately
+    if !build_directory.is_empty() && build_path.join(&build_directory).exists() {
+        fs::remove_dir_all(build_path.join(&build_directory))?;
+    }
+
     Ok(())
 }
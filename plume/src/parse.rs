@@ -1,50 +1,89 @@
+use chrono::{DateTime, NaiveDate};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::error::Error;
 use std::path::PathBuf;
-use std::{fmt, fs};
+use std::fmt;
 use std::fs::canonicalize;
-use std::io::Read;
-use std::process::{Command, Output};
+use std::io::Write;
+use std::process::{Child, Command, Output, Stdio};
 use sqlx::Acquire;
 use sqlx::sqlite::SqlitePool;
+use nest::constraint;
+use nest::config::BUILD_LIBRARY_DIRECTORY;
 
-// Traverse the provided models directory and extract the relevant files
-pub fn traverse_models_dir(
-    path: &PathBuf,
-    valid_model: bool,
-) -> Result<Vec<(PathBuf, PathBuf, PathBuf)>, Box<dyn Error>> {
-    let mut image_path: PathBuf = PathBuf::new();
-    let mut scad_path: PathBuf = PathBuf::new();
-    let mut info_path: PathBuf = PathBuf::new();
-    let mut model_vec: Vec<(PathBuf, PathBuf, PathBuf)> = Vec::new();
-
-    for entry in fs::read_dir(path)? {
-        let entry_path = entry?.path();
-        if entry_path.is_dir() && !valid_model {
-            let entry_contents = traverse_models_dir(&entry_path, true)?;
-            model_vec.extend(entry_contents);
-        } else if entry_path.extension().unwrap() == "jpg" && valid_model {
-            image_path = entry_path;
-        } else if entry_path.extension().unwrap() == "scad" && valid_model {
-            scad_path = entry_path;
-        } else if entry_path.extension().unwrap() == "json" && valid_model {
-            info_path = entry_path;
+// Errors related to the model details in info.json
+#[derive(Debug)]
+enum InfoError {
+    InvalidTags(String),
+    InvalidCategory(String),
+    InvalidDate(String, String),
+}
+
+impl fmt::Display for InfoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InfoError::InvalidTags(model_name) => write!(f, "'tags' must be a list of strings in the '{}' model", model_name),
+            InfoError::InvalidCategory(model_name) => write!(f, "'category' must be a string in the '{}' model", model_name),
+            InfoError::InvalidDate(model_name, date) => {
+                write!(f, "'date' must be a date such as \"2022-10-01\" in the '{}' model (found: {})", model_name, date)
+            }
         }
     }
+}
 
-    if valid_model {
-        Ok(vec![(image_path, scad_path, info_path)])
-    } else {
-        Ok(model_vec)
+impl Error for InfoError {}
+
+// Tags are compared case-insensitively, so they are stored in lowercase without duplicates
+pub fn parse_tags(info: &Value, model_name: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut tags: Vec<String> = Vec::new();
+    if info["tags"].is_null() {
+        return Ok(tags);
+    }
+
+    for tag in info["tags"].as_array().ok_or(InfoError::InvalidTags(model_name.to_string()))? {
+        let tag: String = tag.as_str().ok_or(InfoError::InvalidTags(model_name.to_string()))?.trim().to_lowercase();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
     }
+    Ok(tags)
+}
+
+// The formats accepted for the date of a model besides ISO-8601 (e.g. "2022-10-01"), which every date
+// is stored as. Day and month orders that could be mistaken for one another aren't accepted.
+const DATE_FORMATS: [&str; 3] = ["%Y/%m/%d", "%d %B %Y", "%B %d, %Y"];
+
+pub fn parse_date(info: &Value, model_name: &str) -> Result<String, Box<dyn Error>> {
+    let date: &str = match info["date"].as_str() {
+        Some(date) => date.trim(),
+        None => Err(InfoError::InvalidDate(model_name.to_string(), info["date"].to_string()))?
+    };
+
+    let parsed: Option<NaiveDate> = date.parse::<NaiveDate>().ok()
+        .or_else(|| DateTime::parse_from_rfc3339(date).ok().map(|date| date.date_naive()))
+        .or_else(|| DATE_FORMATS.iter().find_map(|format| NaiveDate::parse_from_str(date, format).ok()));
+    match parsed {
+        Some(parsed) => Ok(parsed.format("%Y-%m-%d").to_string()),
+        None => Err(InfoError::InvalidDate(model_name.to_string(), format!("'{}'", date)))?
+    }
+}
+
+// A category given in info.json takes precedence over the directories the model is in
+pub fn parse_category(info: &Value, directory_category: Option<&String>, model_name: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let category: Option<String> = match &info["category"] {
+        Value::Null => directory_category.cloned(),
+        Value::String(category) => Some(category.trim_matches('/').to_string()),
+        _ => Err(InfoError::InvalidCategory(model_name.to_string()))?
+    };
+    Ok(category.filter(|category| !category.is_empty()))
 }
 
 // Errors related to parameter parsing
 #[derive(Debug)]
 enum ParamError {
     InvalidFormatting(String),
-    DoesNotExist(String),
 }
 
 impl fmt::Display for ParamError {
@@ -53,7 +92,6 @@ impl fmt::Display for ParamError {
             ParamError::InvalidFormatting(name) => {
                 write!(f, "invalid parameter formatting for '{}'", name)
             }
-            ParamError::DoesNotExist(name) => write!(f, "the '{}' parameter does not exist", name),
         }
     }
 }
@@ -75,6 +113,10 @@ impl Error for TypeError {}
 enum RestrictionError {
     InvalidRange(String),
     InvalidList(String),
+    InvalidVector(String),
+    DefaultOutOfRange(String),
+    InvalidStep(String),
+    InvalidUnit(String),
 }
 
 impl fmt::Display for RestrictionError {
@@ -86,6 +128,18 @@ impl fmt::Display for RestrictionError {
             RestrictionError::InvalidList(name) => {
                 write!(f, "invalid parameter list for '{}'", name)
             }
+            RestrictionError::InvalidVector(name) => {
+                write!(f, "invalid vector restriction for '{}'", name)
+            }
+            RestrictionError::DefaultOutOfRange(name) => {
+                write!(f, "the default value of '{}' is outside of its lower and upper bounds", name)
+            }
+            RestrictionError::InvalidStep(name) => {
+                write!(f, "invalid parameter step or precision for '{}'", name)
+            }
+            RestrictionError::InvalidUnit(name) => {
+                write!(f, "invalid unit for '{}', expected one of {}", name, UNITS.join(", "))
+            }
         }
     }
 }
@@ -98,33 +152,172 @@ pub struct IdCounter {
     pub parameter_id: i64
 }
 
+// Parameters belong either to a single part or, when shared, to every part of a model
+#[derive(Clone, Copy)]
+pub enum ParameterOwner {
+    Model(i64),
+    Part(i64)
+}
+
+impl ParameterOwner {
+    fn model_id(&self) -> Option<i64> {
+        match self {
+            ParameterOwner::Model(model_id) => Some(*model_id),
+            ParameterOwner::Part(_) => None
+        }
+    }
+
+    fn part_id(&self) -> Option<i64> {
+        match self {
+            ParameterOwner::Model(_) => None,
+            ParameterOwner::Part(part_id) => Some(*part_id)
+        }
+    }
+}
+
+const UNITS: [&str; 4] = ["mm", "inch", "degrees", "count"];
+
+// Parse an optional unit, which only numeric parameters may declare
+fn parse_unit<'a>(unit: &'a Value, numeric: bool, name: &str) -> Result<Option<&'a str>, Box<dyn Error>> {
+    match unit.as_str() {
+        Some(unit) if numeric && UNITS.contains(&unit) => Ok(Some(unit)),
+        None if unit.is_null() => Ok(None),
+        _ => Err(RestrictionError::InvalidUnit(name.to_string()).into())
+    }
+}
+
+// Parse an optional step restriction, which must be strictly positive
+fn parse_step(step: &Value, integer: bool, name: &str) -> Result<Option<f64>, Box<dyn Error>> {
+    if step.is_null() {
+        Ok(None)
+    } else if (step.is_i64() || (!integer && step.is_f64())) && step.as_f64().unwrap() > 0.0 {
+        Ok(step.as_f64())
+    } else {
+        Err(RestrictionError::InvalidStep(name.to_string()).into())
+    }
+}
+
+// Parse an optional number of decimal places
+fn parse_precision(precision: &Value, name: &str) -> Result<Option<i64>, Box<dyn Error>> {
+    if precision.is_null() {
+        Ok(None)
+    } else if precision.is_u64() {
+        Ok(precision.as_i64())
+    } else {
+        Err(RestrictionError::InvalidStep(name.to_string()).into())
+    }
+}
+
+// Expand a vector restriction, given either per component or as a single value for every component
+fn parse_vector_restriction(restriction: &Value, length: usize, integer: bool, name: &str) -> Result<Vec<f64>, Box<dyn Error>> {
+    let is_valid = |element: &Value| if integer { element.is_i64() } else { element.is_number() };
+
+    if is_valid(restriction) {
+        Ok(vec![restriction.as_f64().unwrap(); length])
+    } else if restriction.is_array()
+        && restriction.as_array().unwrap().len() == length
+        && restriction.as_array().unwrap().iter().all(is_valid)
+    {
+        Ok(restriction.as_array().unwrap().iter().map(|element| element.as_f64().unwrap()).collect())
+    } else {
+        Err(RestrictionError::InvalidVector(name.to_string()).into())
+    }
+}
+
+// The fields shared by every kind of parameter
+struct ParameterInfo<'a> {
+    name: &'a str,
+    label: &'a str,
+    description: Option<&'a str>,
+    section: Option<&'a str>,
+    position: i64,
+    unit: Option<&'a str>,
+    condition: Option<&'a str>
+}
+
+// Parse the optional label, help text, group, order, unit and condition of a parameter, which default
+// to its name, nothing, its position in the list, no unit and no condition
+fn parse_info(parameter: &Value, index: i64) -> Result<ParameterInfo<'_>, Box<dyn Error>> {
+    let name: &str = parameter["name"].as_str().unwrap();
+    let is_text = |field: &Value| field.is_null() || field.is_string();
+    if !is_text(&parameter["label"])
+        || !is_text(&parameter["description"])
+        || !is_text(&parameter["group"])
+        || !(parameter["order"].is_null() || parameter["order"].is_i64())
+    {
+        Err(ParamError::InvalidFormatting(name.to_string()))?;
+    }
+
+    let numeric: bool = parameter["default"].is_number() || parameter["default"].is_array();
+    Ok(ParameterInfo {
+        name,
+        label: parameter["label"].as_str().unwrap_or(name),
+        description: parameter["description"].as_str(),
+        section: parameter["group"].as_str(),
+        position: parameter["order"].as_i64().unwrap_or(index),
+        unit: parse_unit(&parameter["unit"], numeric, name)?,
+        // Conditions have already been checked by `validate_expressions`
+        condition: parameter["condition"].as_str()
+    })
+}
+
+// Order a range, warning if the bounds had to be swapped
+fn order_range(lower: f64, upper: f64, name: &str, model_name: &str) -> Result<(f64, f64), Box<dyn Error>> {
+    if lower < upper {
+        Ok((lower, upper))
+    } else if lower > upper {
+        println!("Warning: 'lower' and 'upper' fields for the '{}' parameter in the '{}' model have been swapped", name, model_name);
+        Ok((upper, lower))
+    } else {
+        Err(RestrictionError::InvalidRange(name.to_string()).into())
+    }
+}
+
+// Store the allowed values of a list parameter, skipping duplicates
+async fn parse_allowed(pool: &SqlitePool, parameter: &Value, parameter_id: i64, is_valid: fn(&Value) -> bool, model_name: &str) -> Result<(), Box<dyn Error>> {
+    let mut allowed: Vec<&Value> = Vec::new();
+    for element in parameter["allowed"].as_array().unwrap() {
+        if !is_valid(element) {
+            Err(RestrictionError::InvalidList(
+                parameter["name"].as_str().unwrap().to_string(),
+            ))?;
+        }
+
+        if !allowed.contains(&element) {
+            db_add_restriction(pool, parameter_id, "allowed", allowed.len() as i64, element).await?;
+            allowed.push(element);
+        } else {
+            println!("Warning: ignored duplicate value of '{}' in the 'allowed' field for the '{}' parameter in the '{}' model", element, parameter["name"], model_name);
+        }
+    }
+
+    Ok(())
+}
+
 // Parse the json parameters and validate their types and restrictions
 pub async fn parse_parameters(
     pool: &SqlitePool,
     parameters: &Vec<Value>,
     id_counter: &mut IdCounter,
-    model_name: &str
+    model_name: &str,
+    owner: ParameterOwner
 ) -> Result<(), Box<dyn Error>> {
-    for parameter in parameters {
+    for (index, parameter) in parameters.iter().enumerate() {
+        let info: ParameterInfo = parse_info(parameter, index as i64)?;
+        let name: &str = info.name;
+        check_identifier(name)?;
+        let parameter_id: i64 = id_counter.parameter_id;
+
         if parameter["default"].is_boolean() {
             // Bool parameter
             if parameter["lower"].is_null()
                 && parameter["upper"].is_null()
-                && parameter["upper"].is_null()
+                && parameter["allowed"].is_null()
                 && parameter["length"].is_null()
             {
-                db_add_bool_parameter(
-                    pool,
-                    id_counter.parameter_id,
-                    parameter["name"].as_str().unwrap(),
-                    parameter["default"].as_bool().unwrap(),
-                    id_counter.part_id
-                ).await?;
-                id_counter.parameter_id += 1;
+                db_add_parameter(pool, parameter_id, "bool", &info, &parameter["default"], owner).await?;
             } else {
-                Err(ParamError::InvalidFormatting(
-                    parameter["name"].as_str().unwrap().to_string(),
-                ))?;
+                Err(ParamError::InvalidFormatting(name.to_string()))?;
             }
         } else if parameter["default"].is_i64() {
             // Integer parameter
@@ -133,148 +326,61 @@ pub async fn parse_parameters(
                 && parameter["allowed"].is_null()
                 && parameter["length"].is_null()
             {
-                // Range restricted
-                if parameter["lower"].as_i64() < parameter["upper"].as_i64() {
-                    db_add_int_range_parameter(
-                        pool,
-                        id_counter.parameter_id,
-                        parameter["name"].as_str().unwrap(),
-                        parameter["default"].as_i64().unwrap(),
-                        parameter["lower"].as_i64().unwrap(),
-                        parameter["upper"].as_i64().unwrap(),
-                        id_counter.part_id
-                    ).await?;
-                    id_counter.parameter_id += 1;
-                } else if parameter["lower"].as_i64() > parameter["upper"].as_i64() {
-                    println!("Warning: 'lower' and 'upper' fields for the '{}' parameter in the '{}' model have been swapped", parameter["name"], model_name);
-                    db_add_int_range_parameter(
-                        pool,
-                        id_counter.parameter_id,
-                        parameter["name"].as_str().unwrap(),
-                        parameter["default"].as_i64().unwrap(),
-                        parameter["upper"].as_i64().unwrap(),
-                        parameter["lower"].as_i64().unwrap(),
-                        id_counter.part_id
-                    ).await?;
-                    id_counter.parameter_id += 1;
-                } else {
-                    Err(RestrictionError::InvalidRange(
-                        parameter["name"].as_str().unwrap().to_string(),
-                    ))?;
+                // Range restricted, optionally stepped from the lower bound
+                let step: Option<i64> = parse_step(&parameter["step"], true, name)?.map(|step| step as i64);
+                let (lower, upper): (f64, f64) = order_range(parameter["lower"].as_f64().unwrap(), parameter["upper"].as_f64().unwrap(), name, model_name)?;
+
+                db_add_parameter(pool, parameter_id, "int_range", &info, &parameter["default"], owner).await?;
+                db_add_restriction(pool, parameter_id, "lower", 0, &json!(lower as i64)).await?;
+                db_add_restriction(pool, parameter_id, "upper", 0, &json!(upper as i64)).await?;
+                if let Some(step) = step {
+                    db_add_restriction(pool, parameter_id, "step", 0, &json!(step)).await?;
                 }
             } else if parameter["allowed"].is_array()
                 && parameter["lower"].is_null()
                 && parameter["upper"].is_null()
                 && parameter["length"].is_null()
+                && parameter["step"].is_null()
             {
                 // List restricted
-                db_add_int_list_parameter(
-                    pool,
-                    id_counter.parameter_id,
-                    parameter["name"].as_str().unwrap(),
-                    parameter["default"].as_i64().unwrap(),
-                    id_counter.part_id
-                ).await?;
-
-                let mut allowed: Vec<i64> = Vec::new();
-                for element in parameter["allowed"].as_array().unwrap() {
-                    if element.is_i64() {
-                        let int_element = element.as_i64().unwrap();
-                        if !allowed.contains(&int_element) {
-                            db_add_int_list_item(
-                                pool,
-                                int_element,
-                                id_counter.parameter_id
-                            ).await?;
-                        } else {
-                            println!("Warning: ignored duplicate value of '{}' in the 'allowed' field for the '{}' parameter in the '{}' model", int_element, parameter["name"], model_name);
-                        }
-                    } else {
-                        Err(RestrictionError::InvalidList(
-                            parameter["name"].as_str().unwrap().to_string(),
-                        ))?;
-                    }
-                }
-                id_counter.parameter_id += 1;
+                db_add_parameter(pool, parameter_id, "int_list", &info, &parameter["default"], owner).await?;
+                parse_allowed(pool, parameter, parameter_id, Value::is_i64, model_name).await?;
             } else {
-                Err(ParamError::InvalidFormatting(
-                    parameter["name"].as_str().unwrap().to_string(),
-                ))?;
+                Err(ParamError::InvalidFormatting(name.to_string()))?;
             }
         } else if parameter["default"].is_f64() {
             // Float parameter
-            if (parameter["lower"].is_f64() || parameter["lower"].is_i64())
-                && (parameter["upper"].is_f64() || parameter["upper"].is_i64())
+            if parameter["lower"].is_number()
+                && parameter["upper"].is_number()
                 && parameter["allowed"].is_null()
                 && parameter["length"].is_null()
             {
-                // Range restricted
-                if parameter["lower"].as_f64() < parameter["upper"].as_f64() {
-                    db_add_float_range_parameter(
-                        pool,
-                        id_counter.parameter_id,
-                        parameter["name"].as_str().unwrap(),
-                        parameter["default"].as_f64().unwrap(),
-                        parameter["lower"].as_f64().unwrap(),
-                        parameter["upper"].as_f64().unwrap(),
-                        id_counter.part_id
-                    ).await?;
-                    id_counter.parameter_id += 1;
-                } else if parameter["lower"].as_f64() > parameter["upper"].as_f64() {
-                    println!("Warning: 'lower' and 'upper' fields for the '{}' parameter in the '{}' model have been swapped", parameter["name"], model_name);
-                    db_add_float_range_parameter(
-                        pool,
-                        id_counter.parameter_id,
-                        parameter["name"].as_str().unwrap(),
-                        parameter["default"].as_f64().unwrap(),
-                        parameter["upper"].as_f64().unwrap(),
-                        parameter["lower"].as_f64().unwrap(),
-                        id_counter.part_id
-                    ).await?;
-                    id_counter.parameter_id += 1;
-                } else {
-                    Err(RestrictionError::InvalidRange(
-                        parameter["name"].as_str().unwrap().to_string(),
-                    ))?;
+                // Range restricted, optionally stepped from the lower bound and rounded to a precision
+                let step: Option<f64> = parse_step(&parameter["step"], false, name)?;
+                let precision: Option<i64> = parse_precision(&parameter["precision"], name)?;
+                let (lower, upper): (f64, f64) = order_range(parameter["lower"].as_f64().unwrap(), parameter["upper"].as_f64().unwrap(), name, model_name)?;
+
+                db_add_parameter(pool, parameter_id, "float_range", &info, &parameter["default"], owner).await?;
+                db_add_restriction(pool, parameter_id, "lower", 0, &json!(lower)).await?;
+                db_add_restriction(pool, parameter_id, "upper", 0, &json!(upper)).await?;
+                if let Some(step) = step {
+                    db_add_restriction(pool, parameter_id, "step", 0, &json!(step)).await?;
+                }
+                if let Some(precision) = precision {
+                    db_add_restriction(pool, parameter_id, "precision", 0, &json!(precision)).await?;
                 }
             } else if parameter["allowed"].is_array()
                 && parameter["lower"].is_null()
                 && parameter["upper"].is_null()
                 && parameter["length"].is_null()
+                && parameter["step"].is_null()
+                && parameter["precision"].is_null()
             {
-                // List restricted
-                db_add_float_list_parameter(
-                    pool,
-                    id_counter.parameter_id,
-                    parameter["name"].as_str().unwrap(),
-                    parameter["default"].as_f64().unwrap(),
-                    id_counter.part_id
-                ).await?;
-
-                let mut allowed: Vec<f64> = Vec::new();
-                for element in parameter["allowed"].as_array().unwrap() {
-                    if element.is_f64() || element.is_i64() {
-                        let float_element = element.as_f64().unwrap();
-                        if !allowed.contains(&float_element) {
-                            db_add_float_list_item(
-                                pool,
-                                float_element,
-                                id_counter.parameter_id
-                            ).await?;
-                        } else {
-                            println!("Warning: ignored duplicate value of '{}' in the 'allowed' field for the '{}' parameter in the '{}' model", float_element, parameter["name"], model_name);
-                        }
-                    } else {
-                        Err(RestrictionError::InvalidList(
-                            parameter["name"].as_str().unwrap().to_string(),
-                        ))?;
-                    }
-                }
-                id_counter.parameter_id += 1;
+                // List restricted, integers are allowed as they are stored as floats
+                db_add_parameter(pool, parameter_id, "float_list", &info, &parameter["default"], owner).await?;
+                parse_allowed(pool, parameter, parameter_id, Value::is_number, model_name).await?;
             } else {
-                Err(ParamError::InvalidFormatting(
-                    parameter["name"].as_str().unwrap().to_string(),
-                ))?;
+                Err(ParamError::InvalidFormatting(name.to_string()))?;
             }
         } else if parameter["default"].is_string() {
             // String parameter
@@ -285,19 +391,10 @@ pub async fn parse_parameters(
             {
                 // Length restricted
                 if parameter["length"].as_i64().unwrap() > 0 {
-                    db_add_string_length_parameter(
-                        pool,
-                        id_counter.parameter_id,
-                        parameter["name"].as_str().unwrap(),
-                        parameter["default"].as_str().unwrap(),
-                        parameter["length"].as_i64().unwrap(),
-                        id_counter.part_id
-                    ).await?;
-                    id_counter.parameter_id += 1;
+                    db_add_parameter(pool, parameter_id, "string_length", &info, &parameter["default"], owner).await?;
+                    db_add_restriction(pool, parameter_id, "length", 0, &parameter["length"]).await?;
                 } else {
-                    Err(RestrictionError::InvalidRange(
-                        parameter["name"].as_str().unwrap().to_string(),
-                    ))?;
+                    Err(RestrictionError::InvalidRange(name.to_string()))?;
                 }
             } else if parameter["allowed"].is_array()
                 && parameter["lower"].is_null()
@@ -305,42 +402,164 @@ pub async fn parse_parameters(
                 && parameter["length"].is_null()
             {
                 // List restricted
-                db_add_string_list_parameter(
-                    pool,
-                    id_counter.parameter_id,
-                    parameter["name"].as_str().unwrap(),
-                    parameter["default"].as_str().unwrap(),
-                    id_counter.part_id
-                ).await?;
-
-                let mut allowed: Vec<&str> = Vec::new();
-                for element in parameter["allowed"].as_array().unwrap() {
-                    if element.is_string() {
-                        let string_element = element.as_str().unwrap();
-                        if !allowed.contains(&string_element) {
-                            db_add_string_list_item(
-                                pool,
-                                string_element,
-                                id_counter.parameter_id
-                            ).await?;
-                        } else {
-                            println!("Warning: ignored duplicate value of '{}' in the 'allowed' field for the '{}' parameter in the '{}' model", string_element, parameter["name"], model_name);
-                        }
-                    } else {
-                        Err(RestrictionError::InvalidList(
-                            parameter["name"].as_str().unwrap().to_string(),
-                        ))?;
+                db_add_parameter(pool, parameter_id, "string_list", &info, &parameter["default"], owner).await?;
+                parse_allowed(pool, parameter, parameter_id, Value::is_string, model_name).await?;
+            } else {
+                Err(ParamError::InvalidFormatting(name.to_string()))?;
+            }
+        } else if parameter["default"].is_array() {
+            // Vector parameter
+            if parameter["allowed"].is_null() && parameter["length"].is_null() {
+                let default: &Vec<Value> = parameter["default"].as_array().unwrap();
+                if default.is_empty() || !default.iter().all(|element| element.is_number()) {
+                    Err(ParamError::InvalidFormatting(name.to_string()))?;
+                }
+
+                // The element type may be given explicitly. Otherwise it is float when any default or bound
+                // component is written with a decimal point, so that `[10.0, 20.0]` is a float vector.
+                let written_as_float = |restriction: &Value| match restriction {
+                    Value::Array(components) => components.iter().any(Value::is_f64),
+                    restriction => restriction.is_f64()
+                };
+                let integer: bool = match parameter["element"].as_str() {
+                    Some("int") => true,
+                    Some("float") => false,
+                    None if parameter["element"].is_null() => {
+                        !written_as_float(&parameter["default"]) && !written_as_float(&parameter["lower"]) && !written_as_float(&parameter["upper"])
+                    }
+                    _ => Err(TypeError(name.to_string()))?
+                };
+                if integer && !default.iter().all(Value::is_i64) {
+                    Err(ParamError::InvalidFormatting(name.to_string()))?;
+                }
+                let lower: Vec<f64> = parse_vector_restriction(&parameter["lower"], default.len(), integer, name)?;
+                let upper: Vec<f64> = parse_vector_restriction(&parameter["upper"], default.len(), integer, name)?;
+                let component = |value: f64| if integer { json!(value as i64) } else { json!(value) };
+
+                db_add_parameter(pool, parameter_id, if integer { "int_vector" } else { "float_vector" }, &info, &parameter["default"], owner).await?;
+                for position in 0..default.len() {
+                    let (component_lower, component_upper): (f64, f64) = order_range(lower[position], upper[position], name, model_name)?;
+                    let component_default: f64 = default[position].as_f64().unwrap();
+                    if component_default < component_lower || component_default > component_upper {
+                        Err(RestrictionError::DefaultOutOfRange(name.to_string()))?;
                     }
+                    db_add_restriction(pool, parameter_id, "lower", position as i64, &component(component_lower)).await?;
+                    db_add_restriction(pool, parameter_id, "upper", position as i64, &component(component_upper)).await?;
                 }
-                id_counter.parameter_id += 1;
             } else {
-                Err(ParamError::InvalidFormatting(
-                    parameter["name"].as_str().unwrap().to_string(),
-                ))?;
+                Err(ParamError::InvalidFormatting(name.to_string()))?;
             }
         } else {
-            Err(TypeError(parameter["name"].as_str().unwrap().to_string()))?;
+            Err(TypeError(name.to_string()))?;
         }
+
+        id_counter.parameter_id += 1;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+enum ExpressionError {
+    InvalidConstraint(String),
+    SelfReferentialCondition(String),
+    DefaultsViolateConstraint(String),
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpressionError::InvalidConstraint(constraint) => {
+                write!(f, "invalid constraint formatting for '{}'", constraint)
+            }
+            ExpressionError::SelfReferentialCondition(name) => {
+                write!(f, "the condition of the '{}' parameter refers to itself", name)
+            }
+            ExpressionError::DefaultsViolateConstraint(expression) => {
+                write!(f, "the default parameter values do not satisfy the constraint '{}'", expression)
+            }
+        }
+    }
+}
+
+impl Error for ExpressionError {}
+
+// The default values of the provided parameters, keyed by name, for use when evaluating expressions
+pub fn default_values(parameters: &Vec<Value>) -> HashMap<String, constraint::Value> {
+    let mut values: HashMap<String, constraint::Value> = HashMap::new();
+    for parameter in parameters {
+        let value: Option<constraint::Value> = match &parameter["default"] {
+            Value::Bool(default) => Some(constraint::Value::Bool(*default)),
+            Value::Number(default) => default.as_f64().map(constraint::Value::Number),
+            Value::String(default) => Some(constraint::Value::Str(default.to_string())),
+            Value::Array(default) => Some(constraint::Value::Vector(
+                default.iter().filter_map(|component| component.as_f64()).collect()
+            )),
+            _ => None
+        };
+
+        if let (Some(name), Some(value)) = (parameter["name"].as_str(), value) {
+            values.insert(name.to_string(), value);
+        }
+    }
+    values
+}
+
+// Check that a parsed expression only refers to parameters that are in scope
+fn check_scope(expression: &constraint::Expression, source: &str, in_scope: &HashMap<String, constraint::Value>) -> Result<(), Box<dyn Error>> {
+    for name in expression.parameters() {
+        if !in_scope.contains_key(&name) {
+            Err(constraint::ConstraintError::UnknownParameter(source.to_string(), name))?;
+        }
+    }
+    Ok(())
+}
+
+// Validate the conditions of the parameters and the constraints between them against the default values of the parameters in scope
+pub fn validate_expressions(parameters: &Vec<Value>, constraints: &Vec<Value>, in_scope: &HashMap<String, constraint::Value>) -> Result<(), Box<dyn Error>> {
+    for parameter in parameters {
+        if parameter["condition"].is_null() {
+            continue;
+        }
+
+        let name: &str = parameter["name"].as_str().unwrap();
+        let source: &str = parameter["condition"].as_str().ok_or(ParamError::InvalidFormatting(name.to_string()))?;
+        let expression: constraint::Expression = constraint::Expression::parse(source)?;
+        check_scope(&expression, source, in_scope)?;
+        if expression.parameters().iter().any(|parameter_name| parameter_name == name) {
+            Err(ExpressionError::SelfReferentialCondition(name.to_string()))?;
+        }
+
+        // Conditions may well be false by default, but must still evaluate to a boolean
+        expression.is_satisfied(in_scope)?;
+    }
+
+    for constraint in constraints {
+        let source: &str = constraint["expression"].as_str().ok_or(ExpressionError::InvalidConstraint(constraint.to_string()))?;
+        if !constraint["message"].is_null() && !constraint["message"].is_string() {
+            Err(ExpressionError::InvalidConstraint(constraint.to_string()))?;
+        }
+
+        let expression: constraint::Expression = constraint::Expression::parse(source)?;
+        check_scope(&expression, source, in_scope)?;
+        if !expression.is_satisfied(in_scope)? {
+            Err(ExpressionError::DefaultsViolateConstraint(source.to_string()))?;
+        }
+    }
+
+    Ok(())
+}
+
+// Store the constraints, which must already have been checked by `validate_expressions`
+pub async fn parse_constraints(pool: &SqlitePool, constraints: &Vec<Value>, owner: ParameterOwner) -> Result<(), Box<dyn Error>> {
+    for constraint in constraints {
+        let expression: &str = constraint["expression"].as_str().unwrap();
+        let message: String = match constraint["message"].as_str() {
+            Some(message) => message.to_string(),
+            None => format!("'{}' must hold", expression)
+        };
+
+        db_add_constraint(pool, expression, &message, owner).await?;
     }
 
     Ok(())
@@ -348,18 +567,18 @@ pub async fn parse_parameters(
 
 #[derive(Debug)]
 enum PartError {
-    PartNotPresent(String),
-    ParameterNotPresent(String, String),
+    InvalidIdentifier(String),
+    SharedParameterConflict(String, String),
 }
 
 impl fmt::Display for PartError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            PartError::PartNotPresent(part) => {
-                write!(f, "part '{}' not present in file", part)
+            PartError::InvalidIdentifier(name) => {
+                write!(f, "'{}' is not a valid name for a part or parameter, names are letters, digits and '_' and can't start with a digit", name)
             }
-            PartError::ParameterNotPresent(part, parameter) => {
-                write!(f, "parameter '{}' not present in part '{}'", parameter, part)
+            PartError::SharedParameterConflict(part, parameter) => {
+                write!(f, "parameter '{}' in part '{}' is already shared by the model", parameter, part)
             }
         }
     }
@@ -367,9 +586,39 @@ impl fmt::Display for PartError {
 
 impl Error for PartError {}
 
+// Part and parameter names end up in the SCAD source roost renders, so they must be plain SCAD identifiers
+fn check_identifier(name: &str) -> Result<(), PartError> {
+    let mut chars: std::str::Chars = name.chars();
+    let valid: bool = match chars.next() {
+        Some(first) => (first.is_ascii_alphabetic() || first == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        None => false
+    };
+    if valid { Ok(()) } else { Err(PartError::InvalidIdentifier(name.to_string())) }
+}
+
 // Parse the json modules and the parameters that they contain ensuring existence and restrictions
-pub async fn parse_parts(pool: &SqlitePool, parts: &Vec<Value>, model_name: &str, id_counter: &mut IdCounter, _scad_path: &PathBuf) -> Result<(), Box<dyn Error>> {
+pub async fn parse_parts(pool: &SqlitePool, parts: &Vec<Value>, shared_parameters: &Vec<Value>, model_name: &str, id_counter: &mut IdCounter) -> Result<(), Box<dyn Error>> {
     for part in parts {
+        check_identifier(part["name"].as_str().unwrap())?;
+        for parameter in part["parameters"].as_array().unwrap() {
+            if shared_parameters.iter().any(|shared| shared["name"] == parameter["name"]) {
+                Err(PartError::SharedParameterConflict(
+                    part["name"].as_str().unwrap().to_string(),
+                    parameter["name"].as_str().unwrap().to_string(),
+                ))?;
+            }
+        }
+
+        // Part constraints and conditions may refer to both the shared parameters and the part's own parameters
+        let part_parameters: &Vec<Value> = part["parameters"].as_array().unwrap();
+        let part_constraints: Vec<Value> = match part["constraints"].as_array() {
+            Some(constraints) => constraints.to_vec(),
+            None => Vec::new()
+        };
+        let mut in_scope: HashMap<String, constraint::Value> = default_values(shared_parameters);
+        in_scope.extend(default_values(part_parameters));
+        validate_expressions(part_parameters, &part_constraints, &in_scope)?;
+
         db_add_part(
             pool,
             id_counter.part_id,
@@ -377,132 +626,124 @@ pub async fn parse_parts(pool: &SqlitePool, parts: &Vec<Value>, model_name: &str
             id_counter.model_id
         ).await?;
 
-        parse_parameters(pool, &part["parameters"].as_array().unwrap(), id_counter, model_name).await?;
+        parse_parameters(pool, part_parameters, id_counter, model_name, ParameterOwner::Part(id_counter.part_id)).await?;
+        parse_constraints(pool, &part_constraints, ParameterOwner::Part(id_counter.part_id)).await?;
         id_counter.part_id += 1;
     }
 
-    // validate_scad(&parsed_modules, scad_path)?;
-
     Ok(())
 }
 
-pub async fn db_reset(pool: &SqlitePool) -> Result<(), Box<dyn Error>> {
+// Ids carry on from the versions already in the database
+pub async fn db_next_ids(pool: &SqlitePool) -> Result<IdCounter, Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
 
-    let table_list: [&str; 13] = ["IntListItems", "FloatListItems", "StringListItems", "IntListParameters", "FloatListParameters", "StringListParameters", "BoolParameters", "IntRangeParameters", "FloatRangeParameters", "StringLengthParameters", "Instances", "Parts", "Models"];
-    for table_name in table_list {
-        sqlx::query(&format!("DELETE FROM {}", table_name))
-            .execute(&mut connection)
-            .await?;
-    }
-    Ok(())
+    Ok(sqlx::query!(r#"SELECT
+        (SELECT COALESCE(MAX(model_id) + 1, 0) FROM Models) AS "model_id!: i64",
+        (SELECT COALESCE(MAX(part_id) + 1, 0) FROM Parts) AS "part_id!: i64",
+        (SELECT COALESCE(MAX(parameter_id) + 1, 0) FROM Parameters) AS "parameter_id!: i64""#)
+        .fetch_one(&mut connection)
+        .await
+        .map(|ids| IdCounter {
+            model_id: ids.model_id,
+            part_id: ids.part_id,
+            parameter_id: ids.parameter_id
+        })?)
 }
 
-pub async fn db_add_model(pool: &SqlitePool, model_id: i64, name: &str, creation_date: &str, description: &str, author: &str, image_path: &str, scad_path: &str) -> Result<(), Box<dyn Error>> {
+// Every version stops being current until it is found again
+pub async fn db_retire_models(pool: &SqlitePool) -> Result<(), Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
 
-    sqlx::query!("INSERT INTO Models (model_id, name, creation_date, description, author, image_path, scad_path) VALUES (?, ?, ?, ?, ?, ?, ?)",
-        model_id,
-        name,
-        creation_date,
-        description,
-        author,
-        image_path,
-        scad_path
-    )
+    sqlx::query!("UPDATE Models SET current = FALSE")
         .execute(&mut connection)
         .await?;
 
     Ok(())
 }
 
-async fn db_add_part(pool: &SqlitePool, part_id: i64, name: &str, model_id: i64) -> Result<(), Box<dyn Error>> {
+// The id of a version of a model, along with the commit it was indexed from
+pub async fn db_find_version(pool: &SqlitePool, name: &str, version: &str) -> Result<Option<(i64, Option<String>)>, Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
 
-    sqlx::query!("INSERT INTO Parts (part_id, name, model_id) VALUES (?, ?, ?)",
-        part_id,
-        name,
-        model_id
-    )
-        .execute(&mut connection)
-        .await?;
-
-    Ok(())
+    Ok(sqlx::query!("SELECT model_id, commit_hash FROM Models WHERE name = ? AND version = ?", name, version)
+        .fetch_optional(&mut connection)
+        .await?
+        .map(|model| (model.model_id, model.commit_hash)))
 }
 
-async fn db_add_int_range_parameter(pool: &SqlitePool, parameter_id: i64, name: &str, default_value: i64, lower: i64, upper: i64, part_id: i64) -> Result<(), Box<dyn Error>> {
+// Make a version the only current version of its model
+pub async fn db_make_current(pool: &SqlitePool, name: &str, model_id: i64) -> Result<(), Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
 
-    sqlx::query!("INSERT INTO IntRangeParameters (parameter_id, name, default_value, lower, upper, part_id) VALUES (?, ?, ?, ?, ?, ?)",
-        parameter_id,
-        name,
-        default_value,
-        lower,
-        upper,
-        part_id
-    )
+    sqlx::query!("UPDATE Models SET current = (model_id = ?) WHERE name = ?", model_id, name)
         .execute(&mut connection)
         .await?;
 
     Ok(())
 }
 
-async fn db_add_float_range_parameter(pool: &SqlitePool, parameter_id: i64, name: &str, default_value: f64, lower: f64, upper: f64, part_id: i64) -> Result<(), Box<dyn Error>> {
+// Models indexed before versioning have no build directory of their own
+pub async fn db_legacy_model_ids(pool: &SqlitePool) -> Result<Vec<i64>, Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
 
-    sqlx::query!("INSERT INTO FloatRangeParameters (parameter_id, name, default_value, lower, upper, part_id) VALUES (?, ?, ?, ?, ?, ?)",
-        parameter_id,
-        name,
-        default_value,
-        lower,
-        upper,
-        part_id
-    )
-        .execute(&mut connection)
-        .await?;
-
-    Ok(())
+    Ok(sqlx::query!("SELECT model_id FROM Models WHERE build_directory = ''")
+        .fetch_all(&mut connection)
+        .await?
+        .into_iter()
+        .map(|model| model.model_id)
+        .collect())
 }
 
-async fn db_add_string_length_parameter(pool: &SqlitePool, parameter_id: i64, name: &str, default_value: &str, length: i64, part_id: i64) -> Result<(), Box<dyn Error>> {
+// Delete a version of a model and everything that belongs to it, returning its build directory and the
+// paths of its instances, whose files are left to the caller
+pub async fn db_remove_model(pool: &SqlitePool, model_id: i64) -> Result<(String, Vec<String>), Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
 
-    sqlx::query!("INSERT INTO StringLengthParameters (parameter_id, name, default_value, length, part_id) VALUES (?, ?, ?, ?, ?)",
-        parameter_id,
-        name,
-        default_value,
-        length,
-        part_id
-    )
+    let build_directory: String = sqlx::query!("SELECT build_directory FROM Models WHERE model_id = ?", model_id)
+        .fetch_one(&mut connection)
+        .await?
+        .build_directory;
+    let instance_paths: Vec<String> = sqlx::query!("SELECT i.path FROM Instances i JOIN Parts p ON p.part_id = i.part_id WHERE p.model_id = ?", model_id)
+        .fetch_all(&mut connection)
+        .await?
+        .into_iter()
+        .map(|instance| instance.path)
+        .collect();
+
+    sqlx::query!("DELETE FROM Models WHERE model_id = ?", model_id)
         .execute(&mut connection)
         .await?;
 
-    Ok(())
+    Ok((build_directory, instance_paths))
 }
 
-async fn db_add_bool_parameter(pool: &SqlitePool, parameter_id: i64, name: &str, default_value: bool, part_id: i64) -> Result<(), Box<dyn Error>> {
+pub async fn db_bump_generation(pool: &SqlitePool) -> Result<(), Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
 
-    sqlx::query!("INSERT INTO BoolParameters (parameter_id, name, default_value, part_id) VALUES (?, ?, ?, ?)",
-        parameter_id,
-        name,
-        default_value,
-        part_id
-    )
+    sqlx::query!("UPDATE Catalog SET generation = generation + 1")
         .execute(&mut connection)
         .await?;
 
     Ok(())
 }
 
-async fn db_add_int_list_parameter(pool: &SqlitePool, parameter_id: i64, name: &str, default_value: i64, part_id: i64) -> Result<(), Box<dyn Error>> {
+pub async fn db_add_model(pool: &SqlitePool, model_id: i64, name: &str, creation_date: &str, modified_date: &str, description: &str, author: &str, image_path: &str, scad_path: &str, category: Option<&str>, version: &str, commit: Option<&str>, build_directory: &str) -> Result<(), Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
 
-    sqlx::query!("INSERT INTO IntListParameters (parameter_id, name, default_value, part_id) VALUES (?, ?, ?, ?)",
-        parameter_id,
+    sqlx::query!("INSERT INTO Models (model_id, name, creation_date, modified_date, description, author, image_path, scad_path, category, version, commit_hash, build_directory) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        model_id,
         name,
-        default_value,
-        part_id
+        creation_date,
+        modified_date,
+        description,
+        author,
+        image_path,
+        scad_path,
+        category,
+        version,
+        commit,
+        build_directory
     )
         .execute(&mut connection)
         .await?;
@@ -510,12 +751,12 @@ async fn db_add_int_list_parameter(pool: &SqlitePool, parameter_id: i64, name: &
     Ok(())
 }
 
-async fn db_add_int_list_item(pool: &SqlitePool, value: i64, parameter_id: i64) -> Result<(), Box<dyn Error>> {
+pub async fn db_add_tag(pool: &SqlitePool, model_id: i64, tag: &str) -> Result<(), Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
 
-    sqlx::query!("INSERT INTO IntListItems (value, parameter_id) VALUES (?, ?)",
-        value,
-        parameter_id
+    sqlx::query!("INSERT INTO ModelTags (tag, model_id) VALUES (?, ?)",
+        tag,
+        model_id
     )
         .execute(&mut connection)
         .await?;
@@ -523,14 +764,13 @@ async fn db_add_int_list_item(pool: &SqlitePool, value: i64, parameter_id: i64)
     Ok(())
 }
 
-async fn db_add_float_list_parameter(pool: &SqlitePool, parameter_id: i64, name: &str, default_value: f64, part_id: i64) -> Result<(), Box<dyn Error>> {
+pub async fn db_add_image(pool: &SqlitePool, model_id: i64, position: i64, path: &str) -> Result<(), Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
 
-    sqlx::query!("INSERT INTO FloatListParameters (parameter_id, name, default_value, part_id) VALUES (?, ?, ?, ?)",
-        parameter_id,
-        name,
-        default_value,
-        part_id
+    sqlx::query!("INSERT INTO ModelImages (path, position, model_id) VALUES (?, ?, ?)",
+        path,
+        position,
+        model_id
     )
         .execute(&mut connection)
         .await?;
@@ -538,12 +778,13 @@ async fn db_add_float_list_parameter(pool: &SqlitePool, parameter_id: i64, name:
     Ok(())
 }
 
-async fn db_add_float_list_item(pool: &SqlitePool, value: f64, parameter_id: i64) -> Result<(), Box<dyn Error>> {
+async fn db_add_part(pool: &SqlitePool, part_id: i64, name: &str, model_id: i64) -> Result<(), Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
 
-    sqlx::query!("INSERT INTO FloatListItems (value, parameter_id) VALUES (?, ?)",
-        value,
-        parameter_id
+    sqlx::query!("INSERT INTO Parts (part_id, name, model_id) VALUES (?, ?, ?)",
+        part_id,
+        name,
+        model_id
     )
         .execute(&mut connection)
         .await?;
@@ -551,14 +792,25 @@ async fn db_add_float_list_item(pool: &SqlitePool, value: f64, parameter_id: i64
     Ok(())
 }
 
-async fn db_add_string_list_parameter(pool: &SqlitePool, parameter_id: i64, name: &str, default_value: &str, part_id: i64) -> Result<(), Box<dyn Error>> {
+async fn db_add_parameter(pool: &SqlitePool, parameter_id: i64, kind: &str, info: &ParameterInfo<'_>, default_value: &Value, owner: ParameterOwner) -> Result<(), Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
+    let part_id: Option<i64> = owner.part_id();
+    let model_id: Option<i64> = owner.model_id();
+    let default_value: String = default_value.to_string();
 
-    sqlx::query!("INSERT INTO FloatListParameters (parameter_id, name, default_value, part_id) VALUES (?, ?, ?, ?)",
+    sqlx::query!("INSERT INTO Parameters (parameter_id, kind, name, label, description, section, position, unit, condition, default_value, part_id, model_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         parameter_id,
-        name,
+        kind,
+        info.name,
+        info.label,
+        info.description,
+        info.section,
+        info.position,
+        info.unit,
+        info.condition,
         default_value,
-        part_id
+        part_id,
+        model_id
     )
         .execute(&mut connection)
         .await?;
@@ -566,10 +818,13 @@ async fn db_add_string_list_parameter(pool: &SqlitePool, parameter_id: i64, name
     Ok(())
 }
 
-async fn db_add_string_list_item(pool: &SqlitePool, value: &str, parameter_id: i64) -> Result<(), Box<dyn Error>> {
+async fn db_add_restriction(pool: &SqlitePool, parameter_id: i64, kind: &str, position: i64, value: &Value) -> Result<(), Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
+    let value: String = value.to_string();
 
-    sqlx::query!("INSERT INTO FloatListItems (value, parameter_id) VALUES (?, ?)",
+    sqlx::query!("INSERT INTO ParameterRestrictions (kind, position, value, parameter_id) VALUES (?, ?, ?, ?)",
+        kind,
+        position,
         value,
         parameter_id
     )
@@ -579,111 +834,107 @@ async fn db_add_string_list_item(pool: &SqlitePool, value: &str, parameter_id: i
     Ok(())
 }
 
-// Checks that the provided parameters exist and follow the described type
-// fn validate_scad(
-//     modules: &Vec<Module>,
-//     scad_path: &PathBuf,
-// ) -> Result<(), Box<dyn Error>> {
-//     let mut scad_file = fs::File::open(scad_path)?;
-//     let mut scad_string: String = String::new();
-//     scad_file.read_to_string(&mut scad_string)?;
-//
-//     let scad_lines: Vec<&str> = scad_string.split("\n").collect();
-//     let mut scad_modules: Vec<(&str, Vec<&str>)> = Vec::new();
-//     for line in scad_lines {
-//         if line.len() > 6 {
-//             if &line[0..6] == "module" {
-//                 let split_line: Vec<&str> = line[7..line.len()-1].split(&['(', ')'][..]).collect();
-//                 let split_params: Vec<&str> = split_line[1].split(",").collect();
-//
-//                 let mut params: Vec<&str> = Vec::new();
-//                 for param in split_params {
-//                     let parameter: Vec<&str> = param.split("=").collect();
-//                     // TODO: Implement type checking logic
-//                     params.push(parameter[0].trim());
-//                 }
-//                 scad_modules.push((split_line[0].trim(), params))
-//             }
-//         }
-//     }
-//
-//     for module in modules {
-//         let mut present: bool = false;
-//         for scad_module in &scad_modules {
-//             if module.name == scad_module.0 {
-//                 present = true;
-//                 for parameter in &module.parameters {
-//                     if !scad_module.1.contains(&parameter.name.as_str()) {
-//                         Err(ModuleError::ParameterNotPresent(module.name.to_string(), parameter.name.to_string()))?;
-//                     }
-//                 }
-//             }
-//         }
-//
-//         if !present {
-//             Err(ModuleError::ModuleNotPresent(module.name.to_string()))?;
-//         }
-//     }
-//
-//     Ok(())
-// }
-
-pub struct Instance {
-    path: String,
-    command_string: String,
-    usage: i64,
-    part_id: i64
-}
-
-pub async fn db_save_instances(pool: &SqlitePool) -> Result<Vec<Instance>, Box<dyn Error>> {
+async fn db_add_constraint(pool: &SqlitePool, expression: &str, message: &str, owner: ParameterOwner) -> Result<(), Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
+    let part_id: Option<i64> = owner.part_id();
+    let model_id: Option<i64> = owner.model_id();
 
-    Ok(sqlx::query_as!(Instance, "SELECT path, command_string, usage, part_id FROM Instances")
-        .fetch_all(&mut connection)
-        .await?)
+    sqlx::query!("INSERT INTO Constraints (expression, message, part_id, model_id) VALUES (?, ?, ?, ?)",
+        expression,
+        message,
+        part_id,
+        model_id
+    )
+        .execute(&mut connection)
+        .await?;
+
+    Ok(())
 }
 
-pub async fn restore(pool: &SqlitePool, build_path: &PathBuf, mut instances: Vec<Instance>) -> Result<(), Box<dyn Error>> {
+// Run a command with the given input written to its stdin, e.g. openscad reading a part's source from /dev/stdin
+fn run_with_input(mut command: Command, input: &str) -> Result<Output, Box<dyn Error>> {
+    let mut child: Child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Dropping stdin once it has been written closes it, so that openscad sees the end of the source
+    child.stdin.take().unwrap().write_all(input.as_bytes())?;
+    Ok(child.wait_with_output()?)
+}
+
+// Regenerate the .stl files of the recorded instances that have gone missing, forgetting any that fail
+pub async fn restore(pool: &SqlitePool, build_path: &PathBuf) -> Result<(), Box<dyn Error>> {
     let mut connection = pool.acquire().await?;
 
-    for i in 0..instances.len() {
-        let stl_path = build_path.join(&instances[i].path);
+    let instances = sqlx::query!("SELECT i.path, i.command_string, m.build_directory FROM Instances i
+        JOIN Parts p ON p.part_id = i.part_id
+        JOIN Models m ON m.model_id = p.model_id")
+        .fetch_all(&mut connection)
+        .await?;
 
+    for instance in instances {
+        let stl_path = build_path.join(&instance.path);
         if stl_path.exists() {
-            // FIXME: Further checks should definitely be made to ensure that instances are linked correctly to parts
-            println!("Instance at {} already exists, no need to restore. Skipping.", &instances[i].path);
-
-            sqlx::query!("INSERT INTO Instances (path, command_string, usage, part_id) VALUES (?, ?, ?, ?)",
-                instances[i].path,
-                instances[i].command_string,
-                instances[i].usage,
-                instances[i].part_id
-            )
+            continue;
+        }
+        println!("Instance at {} does not exist. Attempting to restore.", &instance.path);
+
+        let mut command: Command = Command::new("openscad");
+        command
+            .env("OPENSCADPATH", build_path.join(&instance.build_directory).join(BUILD_LIBRARY_DIRECTORY))
+            .arg("-o").arg(&stl_path)
+            .arg("/dev/stdin");
+        let restored: bool = match run_with_input(command, &instance.command_string) {
+            Ok(output) => output.status.success() && stl_path.exists(),
+            Err(_) => false
+        };
+
+        if restored {
+            println!("\t -> Success.");
+        } else {
+            println!("\t -> Failure. Removing instance.");
+            sqlx::query!("DELETE FROM Instances WHERE path = ?", instance.path)
                 .execute(&mut connection)
                 .await?;
-        } else {
-            println!("Instance at {} does not exist. Attempting to restore.", &instances[i].path);
-
-            match Command::new("sh")
-                .arg("-c")
-                .arg(format!("echo \"{}\" | openscad -o {} /dev/stdin", &instances[i].command_string, stl_path.to_str().unwrap()))
-                .output() {
-                Ok(_) => {
-                    println!("\t -> Success.");
-
-                    sqlx::query!("INSERT INTO Instances (path, command_string, usage, part_id) VALUES (?, ?, ?, ?)",
-                        instances[i].path,
-                        instances[i].command_string,
-                        instances[i].usage,
-                        instances[i].part_id
-                    )
-                        .execute(&mut connection)
-                        .await?;
-                },
-                Err(_) => println!("\t -> Failure. Removing instance.")
-            }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: Value) -> Result<String, Box<dyn Error>> {
+        parse_date(&json!({"date": date}), "model")
+    }
+
+    #[test]
+    fn parse_date_iso() {
+        assert_eq!(date(json!("2022-10-01")).unwrap(), "2022-10-01");
+        assert_eq!(date(json!(" 2022-10-01 ")).unwrap(), "2022-10-01");
+        assert_eq!(date(json!("2022-10-01T12:30:00+02:00")).unwrap(), "2022-10-01");
+    }
+
+    #[test]
+    fn parse_date_formats() {
+        assert_eq!(date(json!("2022/10/01")).unwrap(), "2022-10-01");
+        assert_eq!(date(json!("1 October 2022")).unwrap(), "2022-10-01");
+        assert_eq!(date(json!("October 1, 2022")).unwrap(), "2022-10-01");
+    }
+
+    #[test]
+    fn parse_date_invalid() {
+        // A bare year or Julian day number isn't a date, even though SQLite would read it as one
+        assert!(date(json!("2022")).is_err());
+        assert!(date(json!("2459854.5")).is_err());
+        assert!(date(json!("garbage")).is_err());
+        assert!(date(json!("2022-13-01")).is_err());
+        // Day and month orders that could be mistaken for one another
+        assert!(date(json!("01/10/2022")).is_err());
+        assert!(date(json!(2022)).is_err());
+        assert!(parse_date(&json!({}), "model").is_err());
+    }
+}
@@ -0,0 +1,107 @@
+// ***** Repository *****
+// Reads models straight from a local git repository through the git command line. A ref of the
+// repository is checked out into a scratch directory, which is then indexed like a models directory.
+// Each model records the last commit to change its directory, so that a version whose files haven't
+// changed since it was last indexed can be kept as it is.
+
+use chrono::{DateTime, Utc};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+#[derive(Debug)]
+pub enum RepositoryError {
+    NotARepository(PathBuf),
+    UnknownRef(String),
+    GitFailed(String, String)
+}
+
+impl fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RepositoryError::NotARepository(path) => write!(f, "'{}' is not a git repository", path.display()),
+            RepositoryError::UnknownRef(reference) => write!(f, "'{}' is not a branch, tag or commit of the repository", reference),
+            RepositoryError::GitFailed(command, error) => write!(f, "'git {}' failed: {}", command, error)
+        }
+    }
+}
+
+impl Error for RepositoryError {}
+
+// The last commit to change a directory
+pub struct DirectoryCommit {
+    pub hash: String,
+    pub short_hash: String,
+    pub date: DateTime<Utc>,
+    // Whether the directory has changes that haven't been committed
+    pub dirty: bool
+}
+
+pub fn last_commit(directory: &Path) -> Option<DirectoryCommit> {
+    let log: String = git(directory, &["log", "-1", "--format=%H %h %cI", "--", "."]).ok()?;
+    let fields: Vec<&str> = log.split(' ').collect();
+    let (hash, short_hash, date): (&str, &str, &str) = match fields[..] {
+        [hash, short_hash, date] => (hash, short_hash, date),
+        _ => return None
+    };
+    let status: String = git(directory, &["status", "--porcelain", "--", "."]).ok()?;
+
+    Some(DirectoryCommit {
+        hash: hash.to_string(),
+        short_hash: short_hash.to_string(),
+        date: DateTime::parse_from_rfc3339(date).ok()?.with_timezone(&Utc),
+        dirty: !status.is_empty()
+    })
+}
+
+// The commit a branch, tag or commit of the repository refers to
+pub fn resolve(repository: &PathBuf, reference: &str) -> Result<String, RepositoryError> {
+    if git(repository, &["rev-parse", "--git-dir"]).is_err() {
+        return Err(RepositoryError::NotARepository(repository.clone()));
+    }
+
+    git(repository, &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", reference)])
+        .map_err(|_| RepositoryError::UnknownRef(reference.to_string()))
+}
+
+// The tags of the repository, oldest first
+pub fn tags(repository: &PathBuf) -> Result<Vec<String>, RepositoryError> {
+    if git(repository, &["rev-parse", "--git-dir"]).is_err() {
+        return Err(RepositoryError::NotARepository(repository.clone()));
+    }
+
+    Ok(git(repository, &["tag", "--list", "--sort=creatordate"])?
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+// Check a commit of the repository out into `checkout_path`, replacing anything already there. The
+// checkout shares the repository's objects, and keeps its history for `last_commit`.
+pub fn checkout(repository: &PathBuf, commit: &str, checkout_path: &PathBuf) -> Result<(), Box<dyn Error>> {
+    if checkout_path.exists() {
+        fs::remove_dir_all(checkout_path)?;
+    }
+
+    let repository: PathBuf = fs::canonicalize(repository)?;
+    git(Path::new("."), &["clone", "--quiet", "--shared", "--no-checkout", repository.to_str().ok_or("invalid repository path")?, checkout_path.to_str().ok_or("invalid checkout path")?])?;
+    git(checkout_path, &["checkout", "--quiet", "--detach", commit])?;
+
+    Ok(())
+}
+
+// Run git within a directory, returning its trimmed output
+fn git(directory: &Path, args: &[&str]) -> Result<String, RepositoryError> {
+    let output: Output = Command::new("git")
+        .arg("-C").arg(directory)
+        .args(args)
+        .output()
+        .map_err(|error| RepositoryError::GitFailed(args.join(" "), error.to_string()))?;
+
+    if !output.status.success() {
+        return Err(RepositoryError::GitFailed(args.join(" "), String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
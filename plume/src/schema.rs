@@ -0,0 +1,174 @@
+// ***** Schema *****
+// The database schema is defined by the migrations in `database/migrations/`, which are embedded into
+// the binary. Applied migrations are tracked by sqlx in the `_sqlx_migrations` table.
+
+use sqlx::migrate::{Migrate, Migration, Migrator};
+use sqlx::pool::PoolConnection;
+use sqlx::sqlite::{Sqlite, SqliteConnectOptions, SqlitePool};
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+static MIGRATOR: Migrator = sqlx::migrate!("../database/migrations");
+
+// The tables created by the first migration, which databases used to be created from by hand
+const BASELINE_TABLES: [&str; 13] = [
+    "Models", "Parts", "Instances", "IntRangeParameters", "FloatRangeParameters", "StringLengthParameters", "BoolParameters",
+    "IntListParameters", "IntListItems", "FloatListParameters", "FloatListItems", "StringListParameters", "StringListItems"
+];
+
+#[derive(Debug)]
+pub enum SchemaError {
+    NewerSchema(i64, i64),
+    Untracked,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaError::NewerSchema(version, supported) => {
+                write!(f, "the database schema (version {}) is newer than the latest version supported by plume (version {})", version, supported)
+            }
+            SchemaError::Untracked => {
+                write!(f, "the database has tables but no record of the migrations applied to it, and they don't match the first migration. Bring it up to date by hand, or index into a new database")
+            }
+        }
+    }
+}
+
+impl Error for SchemaError {}
+
+// The version of the latest embedded migration
+pub fn supported_version() -> i64 {
+    MIGRATOR.iter().map(|migration| migration.version).max().unwrap_or(0)
+}
+
+// The version of the latest migration applied to the database, if any have been
+pub async fn current_version(pool: &SqlitePool) -> Result<Option<i64>, Box<dyn Error>> {
+    let tracked: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'")
+        .fetch_one(pool)
+        .await?;
+    if tracked == 0 {
+        return Ok(None);
+    }
+
+    Ok(sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success = TRUE")
+        .fetch_one(pool)
+        .await?)
+}
+
+// Databases created by hand have the tables of the first migration, but no record of it having been
+// applied. The first migration is recorded for them so that only the later ones are run.
+async fn record_baseline(pool: &SqlitePool) -> Result<(), Box<dyn Error>> {
+    let mut tables: Vec<String> = sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table' AND name != 'sqlite_sequence'")
+        .fetch_all(pool)
+        .await?;
+    if tables.is_empty() || tables.iter().any(|table| table == "_sqlx_migrations") {
+        return Ok(());
+    }
+
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('IntRangeParameters')")
+        .fetch_all(pool)
+        .await?;
+    let mut baseline_tables: Vec<&str> = BASELINE_TABLES.to_vec();
+    tables.sort();
+    baseline_tables.sort();
+    if tables != baseline_tables || columns.iter().any(|column| column == "model_id") {
+        Err(SchemaError::Untracked)?;
+    }
+
+    let baseline: &Migration = MIGRATOR.iter().min_by_key(|migration| migration.version).unwrap();
+    let mut connection: PoolConnection<Sqlite> = pool.acquire().await?;
+    connection.ensure_migrations_table().await?;
+    sqlx::query("INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) VALUES (?, ?, TRUE, ?, 0)")
+        .bind(baseline.version)
+        .bind(&*baseline.description)
+        .bind(&*baseline.checksum)
+        .execute(&mut connection)
+        .await?;
+    println!("Recorded the existing tables of the database as schema version {}", baseline.version);
+
+    Ok(())
+}
+
+// Apply any pending migrations, refusing to touch a schema newer than this binary knows about
+pub async fn migrate(pool: &SqlitePool) -> Result<(), Box<dyn Error>> {
+    record_baseline(pool).await?;
+    if let Some(version) = current_version(pool).await? {
+        if version > supported_version() {
+            Err(SchemaError::NewerSchema(version, supported_version()))?;
+        }
+    }
+
+    MIGRATOR.run(pool).await?;
+    Ok(())
+}
+
+// Open the database, creating the file if it doesn't exist yet
+pub async fn open(database_path: &PathBuf) -> Result<SqlitePool, Box<dyn Error>> {
+    let options: SqliteConnectOptions = SqliteConnectOptions::new()
+        .filename(database_path)
+        .create_if_missing(true);
+
+    Ok(SqlitePool::connect_with(options).await?)
+}
+
+// Open the database and bring its schema up to date
+pub async fn connect(database_path: &PathBuf) -> Result<SqlitePool, Box<dyn Error>> {
+    let pool: SqlitePool = open(database_path).await?;
+    migrate(&pool).await?;
+    Ok(pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::Executor;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    // The version of the migration that brings creation dates into ISO-8601 form
+    const MODEL_DATES: i64 = 20230101100000;
+
+    // An in-memory database only lives as long as its connection, so the pool is kept to one
+    async fn memory_pool() -> SqlitePool {
+        SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap()
+    }
+
+    async fn apply(pool: &SqlitePool, migration: &Migration) {
+        pool.execute(&*migration.sql).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn model_dates_reset_non_iso_dates() {
+        let pool: SqlitePool = memory_pool().await;
+        for migration in MIGRATOR.iter().filter(|migration| migration.version < MODEL_DATES) {
+            apply(&pool, migration).await;
+        }
+
+        let dates: [(i64, &str); 4] = [(0, "2022-10-01"), (1, "2022-10-01 12:30:00"), (2, "2459854"), (3, "garbage")];
+        for (model_id, date) in dates {
+            sqlx::query("INSERT INTO Models (model_id, name, creation_date, description, author, image_path, scad_path, version, commit_hash)
+                VALUES (?, ?, ?, '', '', '', '', 'v1', 'abc123')")
+                .bind(model_id)
+                .bind(format!("model{}", model_id))
+                .bind(date)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        apply(&pool, MIGRATOR.iter().find(|migration| migration.version == MODEL_DATES).unwrap()).await;
+
+        let models: Vec<(i64, String, String, Option<String>)> = sqlx::query_as("SELECT model_id, creation_date, modified_date, commit_hash FROM Models ORDER BY model_id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(models, vec![
+            (0, String::from("2022-10-01"), String::from("2022-10-01T00:00:00Z"), Some(String::from("abc123"))),
+            (1, String::from("2022-10-01"), String::from("2022-10-01T12:30:00Z"), Some(String::from("abc123"))),
+            // A Julian day number that SQLite reads as a date is reset all the same
+            (2, String::from("1970-01-01"), String::from("1970-01-01T00:00:00Z"), None),
+            (3, String::from("1970-01-01"), String::from("1970-01-01T00:00:00Z"), None),
+        ]);
+    }
+}
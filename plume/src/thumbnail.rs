@@ -0,0 +1,98 @@
+// ***** Thumbnail *****
+// Renders a preview image for models that ship without one. The first part of the model is rendered
+// with the default value of every parameter, through OpenSCAD's PNG export, at the size and camera of
+// the config. Everything needed is read from the database, so the previews of models that have already
+// been indexed can be rendered again with `plume thumbnails`.
+
+use nest::config::{BUILD_LIBRARY_DIRECTORY, ParakeetConfig};
+use sqlx::sqlite::SqlitePool;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// Where a model's preview is rendered to, within its build directory
+const PREVIEW_DIRECTORY: &str = "preview";
+
+#[derive(Debug)]
+pub enum ThumbnailError {
+    NoParts(String),
+    RenderFailed(String, String)
+}
+
+impl fmt::Display for ThumbnailError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ThumbnailError::NoParts(model_name) => write!(f, "the '{}' model has no parts to render a preview of", model_name),
+            ThumbnailError::RenderFailed(model_name, error) => write!(f, "could not render a preview of the '{}' model: {}", model_name, error)
+        }
+    }
+}
+
+impl Error for ThumbnailError {}
+
+// Render the preview of a model and make it the model's image
+pub async fn render(pool: &SqlitePool, config: &ParakeetConfig, model_id: i64) -> Result<(), Box<dyn Error>> {
+    let mut connection = pool.acquire().await?;
+
+    // The model is marked first, so that a preview that fails to render is tried again by `plume thumbnails`
+    sqlx::query!("UPDATE Models SET image_generated = TRUE WHERE model_id = ?", model_id)
+        .execute(&mut connection)
+        .await?;
+    let model = sqlx::query!("SELECT name, scad_path, build_directory FROM Models WHERE model_id = ?", model_id)
+        .fetch_one(&mut connection)
+        .await?;
+    let part = sqlx::query!("SELECT part_id, name FROM Parts WHERE model_id = ? ORDER BY part_id LIMIT 1", model_id)
+        .fetch_optional(&mut connection)
+        .await?
+        .ok_or_else(|| ThumbnailError::NoParts(model.name.to_string()))?;
+
+    // Default values are stored as JSON, which OpenSCAD reads the same way for every kind of parameter
+    let arguments: Vec<String> = sqlx::query!("SELECT name, default_value FROM Parameters WHERE model_id = ? OR part_id = ? ORDER BY model_id IS NULL, position", model_id, part.part_id)
+        .fetch_all(&mut connection)
+        .await?
+        .into_iter()
+        .map(|parameter| format!("{}={}", parameter.name, parameter.default_value))
+        .collect();
+
+    let preview_directory: PathBuf = Path::new(&model.build_directory).join(PREVIEW_DIRECTORY);
+    fs::create_dir_all(config.build_path.join(&preview_directory))?;
+    let scad_file: PathBuf = config.build_path.join(&preview_directory).join("preview.scad");
+    fs::write(&scad_file, format!("use <{}>;\n{}({});\n", config.build_path.join(&model.scad_path).display(), part.name, arguments.join(", ")))?;
+
+    let image_path: PathBuf = preview_directory.join("preview.png");
+    let (width, height): (u32, u32) = config.thumbnail_dimensions()?;
+    let output: Output = Command::new("openscad")
+        .env("OPENSCADPATH", config.build_path.join(&model.build_directory).join(BUILD_LIBRARY_DIRECTORY))
+        .arg("-o").arg(config.build_path.join(&image_path))
+        .arg(format!("--imgsize={},{}", width, height))
+        .arg(format!("--camera={}", config.thumbnail_camera))
+        .args(["--viewall", "--autocenter", "--render"])
+        .arg(&scad_file)
+        .output()
+        .map_err(|error| ThumbnailError::RenderFailed(model.name.to_string(), format!("could not run openscad ({})", error)))?;
+    if !output.status.success() {
+        let error: String = String::from_utf8_lossy(&output.stderr).lines().last().unwrap_or("").to_string();
+        Err(ThumbnailError::RenderFailed(model.name.to_string(), error))?;
+    }
+
+    let image_path: String = image_path.to_str().ok_or("invalid preview path")?.to_string();
+    sqlx::query!("UPDATE Models SET image_path = ? WHERE model_id = ?", image_path, model_id)
+        .execute(&mut connection)
+        .await?;
+
+    Ok(())
+}
+
+// The current models whose images are rendered previews
+pub async fn generated(pool: &SqlitePool) -> Result<Vec<i64>, Box<dyn Error>> {
+    let mut connection = pool.acquire().await?;
+
+    Ok(sqlx::query!("SELECT model_id FROM Models WHERE image_generated AND current")
+        .fetch_all(&mut connection)
+        .await?
+        .into_iter()
+        .map(|model| model.model_id)
+        .collect())
+}
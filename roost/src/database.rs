@@ -1,10 +1,16 @@
-use rocket::serde::Serialize;
+use chrono::{DateTime, NaiveDate, Utc};
+use rocket::serde::{Deserialize, Serialize};
 use rocket::futures;
+use serde_json::{json, Value};
+use std::cmp::min;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use rocket_db_pools::sqlx::{self, pool::PoolConnection, Sqlite, SqlitePool};
 use rocket_db_pools::{Database, Connection};
 
 use futures::{stream::TryStreamExt, future::TryFutureExt};
-use sqlx::Acquire;
+use rocket::{Build, FromFormField, Rocket, fairing};
+use sqlx::migrate::Migrator;
 
 #[derive(Database)]
 #[database("sqlx")]
@@ -12,90 +18,405 @@ pub struct Db(SqlitePool);
 
 type DbResult<T, E = rocket::response::Debug<sqlx::Error>> = Result<T, E>;
 
+// The schema is defined by the migrations in `database/migrations/`, shared with plume
+static MIGRATOR: Migrator = sqlx::migrate!("../database/migrations");
+
+// Bring the schema up to date on ignition, refusing to launch against a schema newer than roost supports
+pub async fn run_migrations(rocket: Rocket<Build>) -> fairing::Result {
+    let db: &Db = match Db::fetch(&rocket) {
+        Some(db) => db,
+        None => return Err(rocket)
+    };
+
+    // Databases created by hand have no record of the migrations applied to them. plume works out which
+    // they are, so roost leaves that to it rather than failing on tables that already exist.
+    let tables: Vec<String> = sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table' AND name != 'sqlite_sequence'")
+        .fetch_all(&db.0)
+        .await
+        .unwrap_or_default();
+    if !tables.is_empty() && !tables.iter().any(|table| table == "_sqlx_migrations") {
+        println!("The database has tables but no record of the migrations applied to it, run `plume db migrate` to bring it up to date");
+        return Err(rocket);
+    }
+
+    let supported: i64 = MIGRATOR.iter().map(|migration| migration.version).max().unwrap_or(0);
+    let current: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success = TRUE")
+        .fetch_one(&db.0)
+        .await
+        .unwrap_or(None);
+
+    if current.map_or(false, |version| version > supported) {
+        println!("The database schema (version {}) is newer than the latest version supported by roost (version {})", current.unwrap(), supported);
+        return Err(rocket);
+    }
+
+    match MIGRATOR.run(&db.0).await {
+        Ok(_) => Ok(rocket),
+        Err(error) => {
+            println!("Could not migrate the database: {}", error);
+            Err(rocket)
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct DisplayModel {
     pub model_id: i64,
     pub name: String,
-    pub creation_date: String,
+    pub creation_date: NaiveDate,
+    pub modified_date: DateTime<Utc>,
     pub description: String,
     pub author: String,
     pub image_path: String,
+    pub category: Option<String>,
+    pub version: String,
+    pub tags: Vec<String>,
+    pub popularity: i64,
 }
 
-pub async fn get_display_models(db: &Db) -> DbResult<Vec<DisplayModel>> {
-    Ok(sqlx::query!("SELECT model_id, name, creation_date, description, author, image_path, scad_path FROM Models")
-        .fetch(&mut db.0.acquire().await?)
+#[derive(Serialize)]
+pub struct ModelPage {
+    pub models: Vec<DisplayModel>,
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
+}
+
+#[derive(FromFormField, PartialEq)]
+pub enum ModelSort {
+    Name,
+    Date,
+    Modified,
+    Popularity
+}
+
+// The filters of the models list. A model must match the search, be within the category (or one of its
+// subcategories) and have every one of the tags.
+pub struct ModelQuery {
+    pub search: Option<String>,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub sort: ModelSort,
+    pub page: i64,
+    pub per_page: i64,
+}
+
+// Search terms are quoted so that FTS5 doesn't read them as query syntax, and match as prefixes so that
+// results show up while a word is still being typed
+fn search_terms(search: &str) -> Option<String> {
+    let terms: Vec<String> = search.split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() { None } else { Some(terms.join(" ")) }
+}
+
+pub async fn search_models(db: &Db, query: &ModelQuery) -> DbResult<ModelPage> {
+    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
+
+    let search: Option<String> = query.search.as_deref().and_then(search_terms);
+    let tags: String = json!(query.tags.iter().map(|tag| tag.to_lowercase()).collect::<Vec<String>>()).to_string();
+    let sort: &str = match query.sort {
+        ModelSort::Name => "name",
+        ModelSort::Date => "date",
+        ModelSort::Modified => "modified",
+        ModelSort::Popularity => "popularity"
+    };
+    let offset: i64 = (query.page - 1) * query.per_page;
+
+    let total: i64 = sqlx::query!(
+        r#"SELECT COUNT(*) AS "total!: i64" FROM Models m
+        WHERE m.current
+            AND (?1 IS NULL OR m.model_id IN (SELECT rowid FROM ModelSearch WHERE ModelSearch MATCH ?1))
+            AND (?2 IS NULL OR m.category = ?2 OR substr(m.category, 1, length(?2) + 1) = ?2 || '/')
+            AND NOT EXISTS (SELECT 1 FROM json_each(?3) WHERE value NOT IN (SELECT tag FROM ModelTags t WHERE t.model_id = m.model_id))"#,
+        search,
+        query.category,
+        tags
+    )
+        .fetch_one(&mut connection)
+        .map_ok(|count| count.total)
+        .await?;
+
+    let models: Vec<DisplayModel> = sqlx::query!(
+        r#"WITH Matches AS (
+            SELECT m.model_id, m.name, m.creation_date, m.modified_date, m.description, m.author, m.image_path, m.category, m.version,
+                (SELECT json_group_array(tag) FROM (SELECT tag FROM ModelTags t WHERE t.model_id = m.model_id ORDER BY tag)) AS tags,
+                (SELECT COALESCE(SUM(i.usage), 0) FROM Instances i JOIN Parts p ON p.part_id = i.part_id WHERE p.model_id = m.model_id) AS popularity
+            FROM Models m
+            WHERE m.current
+                AND (?1 IS NULL OR m.model_id IN (SELECT rowid FROM ModelSearch WHERE ModelSearch MATCH ?1))
+                AND (?2 IS NULL OR m.category = ?2 OR substr(m.category, 1, length(?2) + 1) = ?2 || '/')
+                AND NOT EXISTS (SELECT 1 FROM json_each(?3) WHERE value NOT IN (SELECT tag FROM ModelTags t WHERE t.model_id = m.model_id))
+        )
+        SELECT model_id AS "model_id!: i64", name AS "name!: String", creation_date AS "creation_date!: NaiveDate",
+            modified_date AS "modified_date!: DateTime<Utc>",
+            description AS "description!: String", author AS "author!: String", image_path AS "image_path!: String",
+            category AS "category?: String", version AS "version!: String", tags AS "tags!: String", popularity AS "popularity!: i64"
+        FROM Matches
+        ORDER BY
+            CASE WHEN ?4 = 'date' THEN creation_date END DESC,
+            CASE WHEN ?4 = 'modified' THEN modified_date END DESC,
+            CASE WHEN ?4 = 'popularity' THEN popularity END DESC,
+            name
+        LIMIT ?5 OFFSET ?6"#,
+        search,
+        query.category,
+        tags,
+        sort,
+        query.per_page,
+        offset
+    )
+        .fetch(&mut connection)
         .map_ok(|model| {
             DisplayModel {
                 model_id: model.model_id,
                 name: model.name,
                 creation_date: model.creation_date,
+                modified_date: model.modified_date,
                 description: model.description,
                 author: model.author,
-                image_path: model.image_path
+                image_path: model.image_path,
+                category: model.category,
+                version: model.version,
+                tags: serde_json::from_str(&model.tags).unwrap_or_default(),
+                popularity: model.popularity
             }
         })
         .try_collect::<Vec<DisplayModel>>()
-        .await?)
+        .await?;
+
+    Ok(ModelPage {
+        models,
+        total,
+        page: query.page,
+        per_page: query.per_page
+    })
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Debug, Clone)]
 pub struct Model {
     pub model_id: i64,
     pub name: String,
     pub author: String,
     pub description: String,
+    pub creation_date: NaiveDate,
+    pub modified_date: DateTime<Utc>,
+    pub version: String,
+    // The git commit the version was indexed from, if any
+    pub commit: Option<String>,
+    // The directory within the build directory that this version of the model was built into
+    pub build_directory: String,
     pub scad_path: String,
+    pub image_path: String,
+    pub gallery: Vec<String>,
+    pub parameters: Vec<Parameter>,
+    pub constraints: Vec<Constraint>,
+    pub conditions: Vec<Condition>,
     pub parts: Vec<Part>,
 }
 
-pub async fn get_model(db: &Db, model_id: i64) -> DbResult<Model> {
+// Models are cached in memory until plume re-indexes the library, which it signals by bumping the
+// generation of the catalog
+#[derive(Default)]
+pub struct ModelCache {
+    models: Mutex<(i64, HashMap<i64, Arc<Model>>)>
+}
+
+pub async fn get_cached_model(db: &Db, cache: &ModelCache, model_id: i64) -> DbResult<Arc<Model>> {
     let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
 
-    let model_info: (String, String, String, String) = sqlx::query!("SELECT name, author, description, scad_path FROM Models WHERE model_id = ?", model_id)
+    let generation: i64 = sqlx::query!("SELECT generation FROM Catalog")
         .fetch_one(&mut connection)
-        .map_ok(|model| (model.name, model.author, model.description, model.scad_path))
+        .map_ok(|catalog| catalog.generation)
         .await?;
 
-    Ok(Model {
-        model_id,
-        name: model_info.0,
-        author: model_info.1,
-        description: model_info.2,
-        scad_path: model_info.3,
-        parts: get_parts(db, model_id).await?
-    })
+    {
+        let mut models = cache.models.lock().unwrap();
+        if models.0 != generation {
+            *models = (generation, HashMap::new());
+        }
+        if let Some(model) = models.1.get(&model_id) {
+            return Ok(model.clone());
+        }
+    }
+
+    let model: Arc<Model> = Arc::new(load_model(&mut connection, model_id).await?);
+    let mut models = cache.models.lock().unwrap();
+    if models.0 == generation {
+        models.1.insert(model_id, model.clone());
+    }
+
+    Ok(model)
 }
 
 #[derive(Serialize)]
-pub struct Part {
-    pub part_id: i64,
-    pub name: String,
-    pub parameters: Vec<Parameter>
+pub struct ModelVersion {
+    pub model_id: i64,
+    pub version: String,
+    pub commit: Option<String>,
+    pub creation_date: NaiveDate,
+    pub modified_date: DateTime<Utc>,
+    pub current: bool,
 }
 
-pub async fn get_parts(db: &Db, model_id: i64) -> DbResult<Vec<Part>> {
+// Every version of the model sharing the given model's name, newest first
+pub async fn get_model_versions(db: &Db, model_id: i64) -> DbResult<Vec<ModelVersion>> {
     let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
 
-    let parts_info: Vec<(i64, String)> = sqlx::query!("SELECT part_id, name FROM Parts WHERE model_id = ?", model_id)
+    let versions: Vec<ModelVersion> = sqlx::query!(
+        r#"SELECT v.model_id AS "model_id!: i64", v.version, v.commit_hash,
+            v.creation_date AS "creation_date!: NaiveDate", v.modified_date AS "modified_date!: DateTime<Utc>", v.current AS "current!: bool"
+        FROM Models m JOIN Models v ON v.name = m.name
+        WHERE m.model_id = ?
+        ORDER BY v.model_id DESC"#,
+        model_id
+    )
         .fetch(&mut connection)
-        .map_ok(|part| (part.part_id, part.name))
-        .try_collect::<Vec<(i64, String)>>()
+        .map_ok(|version| {
+            ModelVersion {
+                model_id: version.model_id,
+                version: version.version,
+                commit: version.commit_hash,
+                creation_date: version.creation_date,
+                modified_date: version.modified_date,
+                current: version.current
+            }
+        })
+        .try_collect::<Vec<ModelVersion>>()
+        .await?;
+
+    Ok(versions)
+}
+
+// The id of a version of the model sharing the given model's name, if that version exists
+pub async fn find_model_version(db: &Db, model_id: i64, version: &str) -> DbResult<Option<i64>> {
+    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
+
+    let version_id: Option<i64> = sqlx::query!(
+        r#"SELECT v.model_id AS "model_id!: i64" FROM Models m JOIN Models v ON v.name = m.name
+        WHERE m.model_id = ? AND v.version = ?"#,
+        model_id,
+        version
+    )
+        .fetch_optional(&mut connection)
+        .map_ok(|found| found.map(|found| found.model_id))
+        .await?;
+
+    Ok(version_id)
+}
+
+// The current version of a model, looked up by name
+pub async fn find_current_model(db: &Db, name: &str) -> DbResult<Option<i64>> {
+    let model_id: Option<i64> = sqlx::query!(r#"SELECT model_id AS "model_id!: i64" FROM Models WHERE name = ? AND current"#, name)
+        .fetch_optional(&mut db.0.acquire().await?)
+        .map_ok(|model| model.map(|model| model.model_id))
+        .await?;
+
+    Ok(model_id)
+}
+
+// The account that uploaded the model of this name first, if it was uploaded at all
+pub async fn get_model_owner(db: &Db, name: &str) -> DbResult<Option<i64>> {
+    let user_id: Option<i64> = sqlx::query!(r#"SELECT user_id AS "user_id!: i64" FROM ModelOwners WHERE name = ?"#, name)
+        .fetch_optional(&mut db.0.acquire().await?)
+        .map_ok(|owner| owner.map(|owner| owner.user_id))
+        .await?;
+
+    Ok(user_id)
+}
+
+// Records the uploader of a model, leaving the owner of a model that already has one as they are
+pub async fn set_model_owner(db: &Db, name: &str, user_id: i64) -> DbResult<()> {
+    sqlx::query!("INSERT INTO ModelOwners (name, user_id) VALUES (?, ?) ON CONFLICT (name) DO NOTHING", name, user_id)
+        .execute(&mut db.0.acquire().await?)
+        .await?;
+
+    Ok(())
+}
+
+// Loads a model in five queries on a single connection: the model, its gallery, its parts, every parameter and every constraint
+async fn load_model(connection: &mut PoolConnection<Sqlite>, model_id: i64) -> DbResult<Model> {
+    let model_info = sqlx::query!(
+        r#"SELECT name, author, description, creation_date AS "creation_date!: NaiveDate", modified_date AS "modified_date!: DateTime<Utc>",
+            version, commit_hash, build_directory, scad_path, image_path
+        FROM Models WHERE model_id = ?"#,
+        model_id
+    )
+        .fetch_one(&mut *connection)
         .await?;
 
-    let mut parts: Vec<Part> = Vec::new();
-    for part in parts_info {
-        parts.push(Part {
-            part_id: part.0,
-            name: part.1,
-            parameters: get_parameters(db, part.0).await?
-        });
+    let gallery: Vec<String> = sqlx::query!("SELECT path FROM ModelImages WHERE model_id = ? ORDER BY position", model_id)
+        .fetch(&mut *connection)
+        .map_ok(|image| image.path)
+        .try_collect::<Vec<String>>()
+        .await?;
+
+    let mut model: Model = Model {
+        model_id,
+        name: model_info.name,
+        author: model_info.author,
+        description: model_info.description,
+        creation_date: model_info.creation_date,
+        modified_date: model_info.modified_date,
+        version: model_info.version,
+        commit: model_info.commit_hash,
+        build_directory: model_info.build_directory,
+        scad_path: model_info.scad_path,
+        image_path: model_info.image_path,
+        gallery,
+        parameters: Vec::new(),
+        constraints: Vec::new(),
+        conditions: Vec::new(),
+        parts: get_parts(connection, model_id).await?
+    };
+
+    // Parameters and constraints without a part are shared by the whole model
+    for (part_id, parameter, condition) in get_model_parameters(connection, model_id).await? {
+        let (parameters, conditions): (&mut Vec<Parameter>, &mut Vec<Condition>) = match model.parts.iter_mut().find(|part| Some(part.part_id) == part_id) {
+            Some(part) => (&mut part.parameters, &mut part.conditions),
+            None => (&mut model.parameters, &mut model.conditions)
+        };
+        parameters.push(parameter);
+        conditions.extend(condition);
+    }
+
+    for (part_id, constraint) in get_model_constraints(connection, model_id).await? {
+        match model.parts.iter_mut().find(|part| Some(part.part_id) == part_id) {
+            Some(part) => part.constraints.push(constraint),
+            None => model.constraints.push(constraint)
+        }
     }
 
-    Ok(parts)
+    Ok(model)
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
+pub struct Part {
+    pub part_id: i64,
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub constraints: Vec<Constraint>,
+    pub conditions: Vec<Condition>
+}
+
+// Parts are returned without their parameters and constraints, which are loaded for the whole model by `load_model`
+async fn get_parts(connection: &mut PoolConnection<Sqlite>, model_id: i64) -> DbResult<Vec<Part>> {
+    Ok(sqlx::query!("SELECT part_id, name FROM Parts WHERE model_id = ?", model_id)
+        .fetch(&mut *connection)
+        .map_ok(|part| {
+            Part {
+                part_id: part.part_id,
+                name: part.name,
+                parameters: Vec::new(),
+                constraints: Vec::new(),
+                conditions: Vec::new()
+            }
+        })
+        .try_collect::<Vec<Part>>()
+        .await?)
+}
+
+#[derive(Serialize, Debug, Clone)]
 pub enum Parameter {
     IntRange(IntRangeParameter),
     FloatRange(FloatRangeParameter),
@@ -103,280 +424,328 @@ pub enum Parameter {
     Bool(BoolParameter),
     IntList(IntListParameter),
     FloatList(FloatListParameter),
-    StringList(StringListParameter)
-}
-
-pub async fn get_parameters(db: &Db, part_id: i64) -> DbResult<Vec<Parameter>> {
-    let mut parameters: Vec<Parameter> = Vec::new();
-
-    parameters.append(
-        &mut get_int_range_parameters(db, part_id).await?
-    );
-    parameters.append(
-        &mut get_float_range_parameters(db, part_id).await?
-    );
-    parameters.append(
-        &mut get_string_length_parameters(db, part_id).await?
-    );
-    parameters.append(
-        &mut get_bool_parameters(db, part_id).await?
-    );
-    parameters.append(
-        &mut get_int_list_parameters(db, part_id).await?
-    );
-    parameters.append(
-        &mut get_float_list_parameters(db, part_id).await?
-    );
-    parameters.append(
-        &mut get_string_list_parameters(db, part_id).await?
-    );
-
-    Ok(parameters)
-}
-
-#[derive(Serialize, Debug)]
+    StringList(StringListParameter),
+    Vector(VectorParameter)
+}
+
+impl Parameter {
+    pub fn parameter_id(&self) -> i64 {
+        match self {
+            Parameter::IntRange(p) => p.parameter_id,
+            Parameter::FloatRange(p) => p.parameter_id,
+            Parameter::StringLength(p) => p.parameter_id,
+            Parameter::Bool(p) => p.parameter_id,
+            Parameter::IntList(p) => p.parameter_id,
+            Parameter::FloatList(p) => p.parameter_id,
+            Parameter::StringList(p) => p.parameter_id,
+            Parameter::Vector(p) => p.parameter_id
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Parameter::IntRange(p) => &p.name,
+            Parameter::FloatRange(p) => &p.name,
+            Parameter::StringLength(p) => &p.name,
+            Parameter::Bool(p) => &p.name,
+            Parameter::IntList(p) => &p.name,
+            Parameter::FloatList(p) => &p.name,
+            Parameter::StringList(p) => &p.name,
+            Parameter::Vector(p) => &p.name
+        }
+    }
+
+    // Only numeric parameters carry a unit
+    pub fn unit(&self) -> Option<&str> {
+        match self {
+            Parameter::IntRange(p) => p.unit.as_deref(),
+            Parameter::FloatRange(p) => p.unit.as_deref(),
+            Parameter::IntList(p) => p.unit.as_deref(),
+            Parameter::FloatList(p) => p.unit.as_deref(),
+            Parameter::Vector(p) => p.unit.as_deref(),
+            _ => None
+        }
+    }
+
+    pub fn default_value(&self) -> Value {
+        match self {
+            Parameter::IntRange(p) => json!(p.default_value),
+            Parameter::FloatRange(p) => json!(p.default_value),
+            Parameter::StringLength(p) => json!(p.default_value),
+            Parameter::Bool(p) => json!(p.default_value),
+            Parameter::IntList(p) => json!(p.default_value),
+            Parameter::FloatList(p) => json!(p.default_value),
+            Parameter::StringList(p) => json!(p.default_value),
+            Parameter::Vector(p) => {
+                if p.element_type == "int" {
+                    json!(p.default_value.iter().map(|component| *component as i64).collect::<Vec<i64>>())
+                } else {
+                    json!(p.default_value)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ParameterDetails {
+    pub label: String,
+    pub description: Option<String>,
+    pub group: Option<String>,
+    pub position: i64
+}
+
+#[derive(Serialize, Debug, Clone)]
 pub struct IntRangeParameter {
     pub parameter_id: i64,
     pub name: String,
+    #[serde(flatten)]
+    pub details: ParameterDetails,
     pub default_value: i64,
     pub lower: i64,
-    pub upper: i64
+    pub upper: i64,
+    pub step: Option<i64>,
+    pub unit: Option<String>
 }
 
-pub async fn get_int_range_parameters(db: &Db, part_id: i64) -> DbResult<Vec<Parameter>> {
-    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
-
-    Ok(sqlx::query!("SELECT parameter_id, name, default_value, lower, upper FROM IntRangeParameters WHERE part_id = ?", part_id)
-        .fetch(&mut connection)
-        .map_ok(|parameter| {
-            Parameter::IntRange(
-                IntRangeParameter {
-                    parameter_id: parameter.parameter_id,
-                    name: parameter.name,
-                    default_value: parameter.default_value,
-                    lower: parameter.lower,
-                    upper: parameter.upper
-                }
-            )
-        })
-        .try_collect::<Vec<Parameter>>()
-        .await?)
-}
-
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct FloatRangeParameter {
     pub parameter_id: i64,
     pub name: String,
+    #[serde(flatten)]
+    pub details: ParameterDetails,
     pub default_value: f64,
     pub lower: f64,
-    pub upper: f64
-}
-
-pub async fn get_float_range_parameters(db: &Db, part_id: i64) -> DbResult<Vec<Parameter>> {
-    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
-
-    Ok(sqlx::query!("SELECT parameter_id, name, default_value, lower, upper FROM FloatRangeParameters WHERE part_id = ?", part_id)
-        .fetch(&mut connection)
-        .map_ok(|parameter| {
-            Parameter::FloatRange(
-                FloatRangeParameter {
-                    parameter_id: parameter.parameter_id,
-                    name: parameter.name,
-                    default_value: parameter.default_value as f64,
-                    lower: parameter.lower as f64,
-                    upper: parameter.upper as f64
-                }
-            )
-        })
-        .try_collect::<Vec<Parameter>>()
-        .await?)
+    pub upper: f64,
+    pub step: Option<f64>,
+    pub precision: Option<i64>,
+    pub unit: Option<String>
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct StringLengthParameter {
     pub parameter_id: i64,
     pub name: String,
+    #[serde(flatten)]
+    pub details: ParameterDetails,
     pub default_value: String,
     pub length: i64,
 }
 
-pub async fn get_string_length_parameters(db: &Db, part_id: i64) -> DbResult<Vec<Parameter>> {
-    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
-
-    Ok(sqlx::query!("SELECT parameter_id, name, default_value, length FROM StringLengthParameters WHERE part_id = ?", part_id)
-        .fetch(&mut connection)
-        .map_ok(|parameter| {
-            Parameter::StringLength(
-                StringLengthParameter{
-                    parameter_id: parameter.parameter_id,
-                    name: parameter.name,
-                    default_value: parameter.default_value,
-                    length: parameter.length,
-                }
-            )
-        })
-        .try_collect::<Vec<Parameter>>()
-        .await?)
-}
-
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct BoolParameter {
     pub parameter_id: i64,
     pub name: String,
+    #[serde(flatten)]
+    pub details: ParameterDetails,
     pub default_value: bool,
 }
 
-pub async fn get_bool_parameters(db: &Db, part_id: i64) -> DbResult<Vec<Parameter>> {
-    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
-
-    Ok(sqlx::query!("SELECT parameter_id, name, default_value FROM BoolParameters WHERE part_id = ?", part_id)
-        .fetch(&mut connection)
-        .map_ok(|parameter| {
-            Parameter::Bool(
-                BoolParameter{
-                    parameter_id: parameter.parameter_id,
-                    name: parameter.name,
-                    default_value: parameter.default_value,
-                }
-            )
-        })
-        .try_collect::<Vec<Parameter>>()
-        .await?)
-}
-
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct IntListParameter {
     pub parameter_id: i64,
     pub name: String,
+    #[serde(flatten)]
+    pub details: ParameterDetails,
     pub default_value: i64,
     pub items: Vec<i64>,
+    pub unit: Option<String>
 }
 
-pub async fn get_int_list_parameters(db: &Db, part_id: i64) -> DbResult<Vec<Parameter>> {
-    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
-
-    let parameters_info: Vec<(i64, String, i64)> = sqlx::query!("SELECT parameter_id, name, default_value FROM IntListParameters WHERE part_id = ?", part_id)
-        .fetch(&mut connection)
-        .map_ok(|parameter| (parameter.parameter_id, parameter.name, parameter.default_value))
-        .try_collect::<Vec<(i64, String, i64)>>()
-        .await?;
-
-    let mut parameters: Vec<Parameter> = Vec::new();
-    for parameter in parameters_info {
-        parameters.push(
-            Parameter::IntList(
-                IntListParameter {
-                    parameter_id: parameter.0,
-                    name: parameter.1,
-                    default_value: parameter.2,
-                    items: get_int_list_items(db, parameter.0).await?
-
-                }
-            )
-        );
-    }
-
-    Ok(parameters)
-}
-
-pub async fn get_int_list_items(db: &Db, parameter_id: i64) -> DbResult<Vec<i64>> {
-    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
-
-    Ok(sqlx::query!("SELECT value FROM IntListItems WHERE parameter_id = ?", parameter_id)
-        .fetch(&mut connection)
-        .map_ok(|item| item.value)
-        .try_collect::<Vec<i64>>()
-        .await?)
-}
-
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct FloatListParameter {
     pub parameter_id: i64,
     pub name: String,
+    #[serde(flatten)]
+    pub details: ParameterDetails,
     pub default_value: f64,
     pub items: Vec<f64>,
+    pub unit: Option<String>
 }
 
-pub async fn get_float_list_parameters(db: &Db, part_id: i64) -> DbResult<Vec<Parameter>> {
-    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
-
-    let parameters_info: Vec<(i64, String, f64)> = sqlx::query!("SELECT parameter_id, name, default_value FROM FloatListParameters WHERE part_id = ?", part_id)
-        .fetch(&mut connection)
-        .map_ok(|parameter| (parameter.parameter_id, parameter.name, parameter.default_value as f64))
-        .try_collect::<Vec<(i64, String, f64)>>()
-        .await?;
-
-    let mut parameters: Vec<Parameter> = Vec::new();
-    for parameter in parameters_info {
-        parameters.push(
-            Parameter::FloatList(
-                FloatListParameter {
-                    parameter_id: parameter.0,
-                    name: parameter.1,
-                    default_value: parameter.2,
-                    items: get_float_list_items(db, parameter.0).await?
-                }
-            )
-        );
-    }
-
-    Ok(parameters)
-}
-
-pub async fn get_float_list_items(db: &Db, parameter_id: i64) -> DbResult<Vec<f64>> {
-    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
-
-    Ok(sqlx::query!("SELECT value FROM FloatListItems WHERE parameter_id = ?", parameter_id)
-        .fetch(&mut connection)
-        .map_ok(|item| item.value as f64)
-        .try_collect::<Vec<f64>>()
-        .await?)
-}
-
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct StringListParameter {
     pub parameter_id: i64,
     pub name: String,
+    #[serde(flatten)]
+    pub details: ParameterDetails,
     pub default_value: String,
     pub items: Vec<String>,
 }
 
-pub async fn get_string_list_parameters(db: &Db, part_id: i64) -> DbResult<Vec<Parameter>> {
-    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
+#[derive(Serialize, Debug, Clone)]
+pub struct VectorParameter {
+    pub parameter_id: i64,
+    pub name: String,
+    #[serde(flatten)]
+    pub details: ParameterDetails,
+    pub element_type: String,
+    pub default_value: Vec<f64>,
+    pub lower: Vec<f64>,
+    pub upper: Vec<f64>,
+    pub unit: Option<String>
+}
 
-    let parameters_info: Vec<(i64, String, String)> = sqlx::query!("SELECT parameter_id, name, default_value FROM StringListParameters WHERE part_id = ?", part_id)
-        .fetch(&mut connection)
-        .map_ok(|parameter| (parameter.parameter_id, parameter.name, parameter.default_value))
-        .try_collect::<Vec<(i64, String, String)>>()
+// A row of the Parameters table along with all of its restrictions, as (kind, position, value)
+struct ParameterRow {
+    parameter_id: i64,
+    kind: String,
+    name: String,
+    details: ParameterDetails,
+    unit: Option<String>,
+    condition: Option<String>,
+    default_value: Value,
+    part_id: Option<i64>,
+    restrictions: Vec<(String, i64, Value)>
+}
+
+impl ParameterRow {
+    // The values of a kind of restriction, in order of position
+    fn restrictions<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = &'a Value> + 'a {
+        self.restrictions.iter()
+            .filter(move |restriction| restriction.0 == kind)
+            .map(|restriction| &restriction.2)
+    }
+
+    fn restriction<'a>(&'a self, kind: &'a str) -> Option<&'a Value> {
+        self.restrictions(kind).next()
+    }
+
+    fn into_parameter(self) -> Option<Parameter> {
+        let parameter_id: i64 = self.parameter_id;
+        let details: ParameterDetails = self.details.clone();
+        let name: String = self.name.to_string();
+        let unit: Option<String> = self.unit.clone();
+
+        Some(match self.kind.as_str() {
+            "int_range" => Parameter::IntRange(IntRangeParameter {
+                parameter_id, name, details, unit,
+                default_value: self.default_value.as_i64()?,
+                lower: self.restriction("lower")?.as_i64()?,
+                upper: self.restriction("upper")?.as_i64()?,
+                step: self.restriction("step").and_then(Value::as_i64)
+            }),
+            "float_range" => Parameter::FloatRange(FloatRangeParameter {
+                parameter_id, name, details, unit,
+                default_value: self.default_value.as_f64()?,
+                lower: self.restriction("lower")?.as_f64()?,
+                upper: self.restriction("upper")?.as_f64()?,
+                step: self.restriction("step").and_then(Value::as_f64),
+                precision: self.restriction("precision").and_then(Value::as_i64)
+            }),
+            "string_length" => Parameter::StringLength(StringLengthParameter {
+                parameter_id, name, details,
+                default_value: self.default_value.as_str()?.to_string(),
+                length: self.restriction("length")?.as_i64()?
+            }),
+            "bool" => Parameter::Bool(BoolParameter {
+                parameter_id, name, details,
+                default_value: self.default_value.as_bool()?
+            }),
+            "int_list" => Parameter::IntList(IntListParameter {
+                parameter_id, name, details, unit,
+                default_value: self.default_value.as_i64()?,
+                items: self.restrictions("allowed").filter_map(Value::as_i64).collect()
+            }),
+            "float_list" => Parameter::FloatList(FloatListParameter {
+                parameter_id, name, details, unit,
+                default_value: self.default_value.as_f64()?,
+                items: self.restrictions("allowed").filter_map(Value::as_f64).collect()
+            }),
+            "string_list" => Parameter::StringList(StringListParameter {
+                parameter_id, name, details,
+                default_value: self.default_value.as_str()?.to_string(),
+                items: self.restrictions("allowed").filter_map(Value::as_str).map(String::from).collect()
+            }),
+            "int_vector" | "float_vector" => Parameter::Vector(VectorParameter {
+                parameter_id, name, details, unit,
+                element_type: self.kind.trim_end_matches("_vector").to_string(),
+                default_value: self.default_value.as_array()?.iter().filter_map(Value::as_f64).collect(),
+                lower: self.restrictions("lower").filter_map(Value::as_f64).collect(),
+                upper: self.restrictions("upper").filter_map(Value::as_f64).collect()
+            }),
+            _ => return None
+        })
+    }
+}
+
+// Load every parameter of a model, both shared and belonging to its parts, in a single query.
+// Parameters are returned in the order the author listed them in, along with their owning part, if any.
+async fn get_model_parameters(connection: &mut PoolConnection<Sqlite>, model_id: i64) -> DbResult<Vec<(Option<i64>, Parameter, Option<Condition>)>> {
+    let rows = sqlx::query!(r#"SELECT p.parameter_id, p.kind, p.name, p.label, p.description, p.section, p.position, p.unit, p.condition, p.default_value AS "default_value!", p.part_id,
+            r.kind AS "restriction_kind?", r.position AS "restriction_position?", r.value AS "restriction_value?"
+        FROM Parameters p LEFT JOIN ParameterRestrictions r ON r.parameter_id = p.parameter_id
+        WHERE p.model_id = ? OR p.part_id IN (SELECT part_id FROM Parts WHERE model_id = ?)
+        ORDER BY p.position, p.parameter_id, r.position"#, model_id, model_id)
+        .fetch_all(&mut *connection)
         .await?;
 
-    let mut parameters: Vec<Parameter> = Vec::new();
-    for parameter in parameters_info {
-        parameters.push(
-            Parameter::StringList(
-                StringListParameter {
-                    parameter_id: parameter.0,
-                    name: parameter.1,
-                    default_value: parameter.2,
-                    items: get_string_list_items(db, parameter.0).await?
-                }
-            )
-        );
+    // The restrictions of a parameter are on consecutive rows
+    let mut parameter_rows: Vec<ParameterRow> = Vec::new();
+    for row in rows {
+        if parameter_rows.last().map(|parameter| parameter.parameter_id) != Some(row.parameter_id) {
+            parameter_rows.push(ParameterRow {
+                parameter_id: row.parameter_id,
+                kind: row.kind,
+                name: row.name,
+                details: ParameterDetails {
+                    label: row.label,
+                    description: row.description,
+                    group: row.section,
+                    position: row.position
+                },
+                unit: row.unit,
+                condition: row.condition,
+                default_value: serde_json::from_str(&row.default_value).unwrap_or(Value::Null),
+                part_id: row.part_id,
+                restrictions: Vec::new()
+            });
+        }
+
+        if let (Some(kind), Some(position), Some(value)) = (row.restriction_kind, row.restriction_position, row.restriction_value) {
+            parameter_rows.last_mut().unwrap().restrictions.push((kind, position, serde_json::from_str(&value).unwrap_or(Value::Null)));
+        }
     }
 
-    Ok(parameters)
+    Ok(parameter_rows.into_iter()
+        .filter_map(|row| {
+            let part_id: Option<i64> = row.part_id;
+            let condition: Option<Condition> = row.condition.as_ref().map(|expression| Condition {
+                parameter_id: row.parameter_id,
+                expression: expression.to_string()
+            });
+            row.into_parameter().map(|parameter| (part_id, parameter, condition))
+        })
+        .collect())
 }
 
-pub async fn get_string_list_items(db: &Db, parameter_id: i64) -> DbResult<Vec<String>> {
-    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
+#[derive(Serialize, Debug, Clone)]
+pub struct Constraint {
+    pub expression: String,
+    pub message: String
+}
 
-    Ok(sqlx::query!("SELECT value FROM StringListItems WHERE parameter_id = ?", parameter_id)
-        .fetch(&mut connection)
-        .map_ok(|item| item.value)
-        .try_collect::<Vec<String>>()
+// Every constraint of a model along with its owning part, if any
+async fn get_model_constraints(connection: &mut PoolConnection<Sqlite>, model_id: i64) -> DbResult<Vec<(Option<i64>, Constraint)>> {
+    Ok(sqlx::query!("SELECT expression, message, part_id FROM Constraints WHERE model_id = ? OR part_id IN (SELECT part_id FROM Parts WHERE model_id = ?) ORDER BY constraint_id", model_id, model_id)
+        .fetch(&mut *connection)
+        .map_ok(|constraint| {
+            (constraint.part_id, Constraint {
+                expression: constraint.expression,
+                message: constraint.message
+            })
+        })
+        .try_collect::<Vec<(Option<i64>, Constraint)>>()
         .await?)
 }
 
+// A parameter with a condition only applies while its condition holds
+#[derive(Serialize, Debug, Clone)]
+pub struct Condition {
+    pub parameter_id: i64,
+    pub expression: String
+}
+
 #[derive(Clone, Debug)]
 pub struct Instance {
     pub part_id: i64,
@@ -413,6 +782,10 @@ pub async fn find_least_valuable_instance(db: &Db, deletion_buffer: i64) -> DbRe
         .try_collect::<Vec<Instance>>()
         .await?;
 
+    if instances.is_empty() {
+        return Ok(instances);
+    }
+
     instances.sort_by_key(|instance| instance.usage.unwrap());
 
     let lowest_usage= instances[0].usage.unwrap();
@@ -420,7 +793,8 @@ pub async fn find_least_valuable_instance(db: &Db, deletion_buffer: i64) -> DbRe
 
     instances.sort_by_key(|instance| instance.age.unwrap());
 
-    Ok(Vec::from(&instances[0..deletion_buffer as usize]))
+    // There may be fewer instances of the lowest usage than would be deleted
+    Ok(Vec::from(&instances[..min(deletion_buffer as usize, instances.len())]))
 }
 
 pub async fn remove_instance(db: &Db, path: &str) -> DbResult<()> {
@@ -446,3 +820,276 @@ pub async fn increment_instance_usage(db: &Db, path: String) -> DbResult<()> {
 
     Ok(())
 }
+
+// A saved parameter set, with the values keyed by parameter name
+#[derive(Clone, Debug)]
+pub struct SavedConfig {
+    pub config_id: String,
+    pub model_name: String,
+    pub version: String,
+    pub part_name: String,
+    pub unit: String,
+    pub values: Value,
+    pub creation_date: DateTime<Utc>
+}
+
+pub async fn create_saved_config(db: &Db, config: &SavedConfig) -> DbResult<()> {
+    let values: String = config.values.to_string();
+    sqlx::query!("INSERT INTO SavedConfigs (config_id, model_name, version, part_name, unit, parameter_values, creation_date) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        config.config_id,
+        config.model_name,
+        config.version,
+        config.part_name,
+        config.unit,
+        values,
+        config.creation_date
+    )
+        .execute(&mut db.0.acquire().await?)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_saved_config(db: &Db, config_id: &str) -> DbResult<Option<SavedConfig>> {
+    let config: Option<SavedConfig> = sqlx::query!(
+        r#"SELECT config_id, model_name, version, part_name, unit, parameter_values, creation_date AS "creation_date!: DateTime<Utc>"
+        FROM SavedConfigs WHERE config_id = ?"#,
+        config_id
+    )
+        .fetch_optional(&mut db.0.acquire().await?)
+        .map_ok(|config| config.map(|config| SavedConfig {
+            config_id: config.config_id,
+            model_name: config.model_name,
+            version: config.version,
+            part_name: config.part_name,
+            unit: config.unit,
+            values: serde_json::from_str(&config.parameter_values).unwrap_or(json!({})),
+            creation_date: config.creation_date
+        }))
+        .await?;
+
+    Ok(config)
+}
+
+// The version of a model that a saved config is restored with: the version it was saved with while that is
+// still indexed, and otherwise the current version
+pub async fn find_config_model(db: &Db, model_name: &str, version: &str) -> DbResult<Option<i64>> {
+    let model_id: Option<i64> = sqlx::query!(
+        r#"SELECT model_id AS "model_id!: i64" FROM Models WHERE name = ?
+        ORDER BY version = ? DESC, current DESC, model_id DESC
+        LIMIT 1"#,
+        model_name,
+        version
+    )
+        .fetch_optional(&mut db.0.acquire().await?)
+        .map_ok(|model| model.map(|model| model.model_id))
+        .await?;
+
+    Ok(model_id)
+}
+
+// Roles are ordered, so that a role can be compared against the least role a route requires
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Author,
+    Admin
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Author => "author",
+            Role::Admin => "admin"
+        }
+    }
+
+    fn from_str(role: &str) -> Role {
+        match role {
+            "admin" => Role::Admin,
+            "author" => Role::Author,
+            _ => Role::Viewer
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct User {
+    pub user_id: i64,
+    pub username: String,
+    pub role: Role,
+    pub creation_date: DateTime<Utc>
+}
+
+// The first account to be registered is made an admin, so that there is always someone to hand out roles.
+// Returns `None` when the username is already taken.
+pub async fn create_user(db: &Db, username: &str, password_hash: &str) -> DbResult<Option<User>> {
+    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
+
+    let creation_date: DateTime<Utc> = Utc::now();
+    let created: u64 = sqlx::query!(
+        "INSERT INTO Users (username, password_hash, role, creation_date)
+        VALUES (?, ?, CASE WHEN EXISTS (SELECT 1 FROM Users) THEN 'viewer' ELSE 'admin' END, ?)
+        ON CONFLICT (username) DO NOTHING",
+        username,
+        password_hash,
+        creation_date
+    )
+        .execute(&mut connection)
+        .await?
+        .rows_affected();
+
+    if created == 0 {
+        return Ok(None);
+    }
+    find_user(&mut connection, username).await.map(|user| user.map(|user| user.0))
+}
+
+// A user along with their password hash, looked up by username regardless of case
+async fn find_user(connection: &mut PoolConnection<Sqlite>, username: &str) -> DbResult<Option<(User, String)>> {
+    let user: Option<(User, String)> = sqlx::query!(
+        r#"SELECT user_id AS "user_id!: i64", username, password_hash, role, creation_date AS "creation_date!: DateTime<Utc>"
+        FROM Users WHERE username = ?"#,
+        username
+    )
+        .fetch_optional(connection)
+        .map_ok(|user| user.map(|user| (User {
+            user_id: user.user_id,
+            username: user.username,
+            role: Role::from_str(&user.role),
+            creation_date: user.creation_date
+        }, user.password_hash)))
+        .await?;
+
+    Ok(user)
+}
+
+pub async fn get_user_credentials(db: &Db, username: &str) -> DbResult<Option<(User, String)>> {
+    find_user(&mut db.0.acquire().await?, username).await
+}
+
+pub async fn get_user(db: &Db, user_id: i64) -> DbResult<Option<User>> {
+    let user: Option<User> = sqlx::query!(
+        r#"SELECT user_id AS "user_id!: i64", username, role, creation_date AS "creation_date!: DateTime<Utc>"
+        FROM Users WHERE user_id = ?"#,
+        user_id
+    )
+        .fetch_optional(&mut db.0.acquire().await?)
+        .map_ok(|user| user.map(|user| User {
+            user_id: user.user_id,
+            username: user.username,
+            role: Role::from_str(&user.role),
+            creation_date: user.creation_date
+        }))
+        .await?;
+
+    Ok(user)
+}
+
+pub async fn get_users(db: &Db) -> DbResult<Vec<User>> {
+    let users: Vec<User> = sqlx::query!(
+        r#"SELECT user_id AS "user_id!: i64", username, role, creation_date AS "creation_date!: DateTime<Utc>"
+        FROM Users ORDER BY user_id"#
+    )
+        .fetch(&mut db.0.acquire().await?)
+        .map_ok(|user| User {
+            user_id: user.user_id,
+            username: user.username,
+            role: Role::from_str(&user.role),
+            creation_date: user.creation_date
+        })
+        .try_collect::<Vec<User>>()
+        .await?;
+
+    Ok(users)
+}
+
+pub async fn set_user_role(db: &Db, user_id: i64, role: Role) -> DbResult<Option<User>> {
+    let role: &str = role.as_str();
+    sqlx::query!("UPDATE Users SET role = ? WHERE user_id = ?", role, user_id)
+        .execute(&mut db.0.acquire().await?)
+        .await?;
+
+    get_user(db, user_id).await
+}
+
+// An API token as it is listed, the token itself is only ever known to the client it was created for
+#[derive(Serialize, Clone, Debug)]
+pub struct ApiToken {
+    pub token_id: i64,
+    pub name: String,
+    pub creation_date: DateTime<Utc>,
+    pub last_used: Option<DateTime<Utc>>
+}
+
+pub async fn create_api_token(db: &Db, user_id: i64, name: &str, token_hash: &str) -> DbResult<ApiToken> {
+    let creation_date: DateTime<Utc> = Utc::now();
+    let token_id: i64 = sqlx::query!(
+        "INSERT INTO ApiTokens (user_id, name, token_hash, creation_date) VALUES (?, ?, ?, ?)",
+        user_id,
+        name,
+        token_hash,
+        creation_date
+    )
+        .execute(&mut db.0.acquire().await?)
+        .await?
+        .last_insert_rowid();
+
+    Ok(ApiToken {
+        token_id,
+        name: name.to_string(),
+        creation_date,
+        last_used: None
+    })
+}
+
+pub async fn get_api_tokens(db: &Db, user_id: i64) -> DbResult<Vec<ApiToken>> {
+    let tokens: Vec<ApiToken> = sqlx::query!(
+        r#"SELECT token_id AS "token_id!: i64", name, creation_date AS "creation_date!: DateTime<Utc>", last_used AS "last_used: DateTime<Utc>"
+        FROM ApiTokens WHERE user_id = ? ORDER BY token_id"#,
+        user_id
+    )
+        .fetch(&mut db.0.acquire().await?)
+        .map_ok(|token| ApiToken {
+            token_id: token.token_id,
+            name: token.name,
+            creation_date: token.creation_date,
+            last_used: token.last_used
+        })
+        .try_collect::<Vec<ApiToken>>()
+        .await?;
+
+    Ok(tokens)
+}
+
+// Returns whether the user had a token with that id to remove
+pub async fn remove_api_token(db: &Db, user_id: i64, token_id: i64) -> DbResult<bool> {
+    let removed: u64 = sqlx::query!("DELETE FROM ApiTokens WHERE token_id = ? AND user_id = ?", token_id, user_id)
+        .execute(&mut db.0.acquire().await?)
+        .await?
+        .rows_affected();
+
+    Ok(removed > 0)
+}
+
+// The user a token belongs to, recording that the token has been used
+pub async fn find_token_user(db: &Db, token_hash: &str) -> DbResult<Option<User>> {
+    let mut connection: PoolConnection<Sqlite> = db.0.acquire().await?;
+
+    let user_id: Option<i64> = sqlx::query!(r#"SELECT user_id AS "user_id!: i64" FROM ApiTokens WHERE token_hash = ?"#, token_hash)
+        .fetch_optional(&mut connection)
+        .map_ok(|token| token.map(|token| token.user_id))
+        .await?;
+
+    let last_used: DateTime<Utc> = Utc::now();
+    sqlx::query!("UPDATE ApiTokens SET last_used = ? WHERE token_hash = ?", last_used, token_hash)
+        .execute(&mut connection)
+        .await?;
+
+    match user_id {
+        Some(user_id) => get_user(db, user_id).await,
+        None => Ok(None)
+    }
+}
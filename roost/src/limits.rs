@@ -0,0 +1,291 @@
+// ***** Limits *****
+// Rate limits on the requests that can render parts, so that a single client can't take up the CPU or
+// churn the cache. Each client has a token bucket for its requests, checked by the fairing before the
+// request is routed, and another for the parts that had to be rendered for it, checked through the
+// `RenderQuota` guard once it is known that the part isn't cached. Clients are told when to try again
+// through a 429 response with `Retry-After`. Clients are told apart by their account where they are
+// signed in, and by their IP address otherwise.
+
+use crate::database;
+use nest::config::ParakeetConfig;
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use rocket::{Build, Data, Rocket};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// The routes that count towards the request limit
+const LIMITED_ROUTES: [&str; 3] = ["/api/generate/", "/api/preview/", "/api/bundle/"];
+
+// Buckets that have filled back up are forgotten once there are more clients than this, checking at most
+// once per sweep interval so that busy periods don't go through every bucket on every request
+const MAX_CLIENTS: usize = 10000;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy)]
+struct Rate {
+    capacity: f64,
+    per_second: f64
+}
+
+impl Rate {
+    fn new((requests, seconds): (u32, u32)) -> Rate {
+        Rate {
+            capacity: requests as f64,
+            per_second: requests as f64 / seconds as f64
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant
+}
+
+impl Bucket {
+    fn refill(&mut self, rate: Rate, now: Instant) {
+        self.tokens = (self.tokens + now.duration_since(self.updated).as_secs_f64() * rate.per_second).min(rate.capacity);
+        self.updated = now;
+    }
+}
+
+struct Clients {
+    buckets: HashMap<String, Bucket>,
+    swept: Instant
+}
+
+struct Buckets {
+    rate: Rate,
+    clients: Mutex<Clients>
+}
+
+impl Buckets {
+    fn new(rate: Rate) -> Buckets {
+        Buckets { rate, clients: Mutex::new(Clients { buckets: HashMap::new(), swept: Instant::now() }) }
+    }
+
+    // Take a token from the client's bucket, or find out how long it is until there is one
+    fn take(&self, client: &str) -> Result<(), Duration> {
+        self.take_at(client, Instant::now())
+    }
+
+    fn take_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.buckets.len() > MAX_CLIENTS && now.duration_since(clients.swept) >= SWEEP_INTERVAL {
+            clients.buckets.retain(|_, bucket| {
+                bucket.refill(self.rate, now);
+                bucket.tokens < self.rate.capacity
+            });
+            clients.swept = now;
+        }
+
+        let bucket: &mut Bucket = clients.buckets.entry(client.to_string()).or_insert(Bucket { tokens: self.rate.capacity, updated: now });
+        bucket.refill(self.rate, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate.per_second))
+        }
+    }
+}
+
+pub struct RateLimits {
+    requests: Buckets,
+    renders: Buckets
+}
+
+// The key a client's buckets are kept under, cached on the request. Signed in clients are told apart by
+// their account, once their session or token has been checked, so that made up tokens can't be used to
+// get around the limits. Everyone else is told apart by their IP address. The `User` guard caches its
+// lookup on the request, so the route's own guards don't look the account up again.
+struct ClientKey(String);
+
+async fn client_key<'r>(request: &'r Request<'_>) -> &'r str {
+    let key: &ClientKey = request.local_cache_async(async {
+        match (request.guard::<database::User>().await, request.client_ip()) {
+            (Outcome::Success(user), _) => ClientKey(format!("user:{}", user.user_id)),
+            (_, Some(ip)) => ClientKey(format!("ip:{}", ip)),
+            (_, None) => ClientKey(String::from("unknown"))
+        }
+    }).await;
+    &key.0
+}
+
+// How long the client has to wait when the request was over the request limit, cached on the request
+struct RequestLimited(Option<Duration>);
+
+pub struct RateLimiting;
+
+#[rocket::async_trait]
+impl Fairing for RateLimiting {
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate limiting",
+            kind: Kind::Ignite | Kind::Request
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let rates: Result<((u32, u32), (u32, u32)), _> = match rocket.state::<ParakeetConfig>() {
+            Some(config) => config.request_rate().and_then(|request_rate| Ok((request_rate, config.render_rate()?))),
+            None => return Err(rocket)
+        };
+
+        match rates {
+            Ok((request_rate, render_rate)) => Ok(rocket.manage(RateLimits {
+                requests: Buckets::new(Rate::new(request_rate)),
+                renders: Buckets::new(Rate::new(render_rate))
+            })),
+            Err(error) => {
+                println!("Could not set up rate limiting: {}", error);
+                Err(rocket)
+            }
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        let path: &str = request.uri().path().as_str();
+        if !LIMITED_ROUTES.iter().any(|route| path.starts_with(route)) {
+            return;
+        }
+
+        if let Some(limits) = request.rocket().state::<RateLimits>() {
+            let limited: Option<Duration> = limits.requests.take(client_key(request).await).err();
+            request.local_cache(|| RequestLimited(limited));
+        }
+    }
+}
+
+// A client's allowance of parts rendered for it. Requests that were over the request limit fail this
+// guard, and are answered by the 429 catcher.
+pub struct RenderQuota<'r> {
+    client: String,
+    limits: &'r RateLimits
+}
+
+impl<'r> RenderQuota<'r> {
+    pub fn take_render(&self) -> Result<(), TooManyRequests> {
+        self.limits.renders.take(&self.client).map_err(TooManyRequests)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RenderQuota<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if request.local_cache(|| RequestLimited(None)).0.is_some() {
+            return Outcome::Failure((Status::TooManyRequests, ()));
+        }
+
+        match request.rocket().state::<RateLimits>() {
+            Some(limits) => Outcome::Success(RenderQuota { client: client_key(request).await.to_string(), limits }),
+            None => Outcome::Failure((Status::InternalServerError, ()))
+        }
+    }
+}
+
+// Answers a request that was over a limit with the number of seconds until it can be made again
+pub struct TooManyRequests(pub Duration);
+
+impl TooManyRequests {
+    // The wait of a request that the fairing found to be over the request limit
+    pub fn of_request(request: &Request<'_>) -> TooManyRequests {
+        TooManyRequests(request.local_cache(|| RequestLimited(None)).0.unwrap_or_default())
+    }
+}
+
+impl<'r> Responder<'r, 'static> for TooManyRequests {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let seconds: u64 = self.0.as_secs_f64().ceil().max(1.0) as u64;
+        Response::build_from(Json(json!({ "errors": [format!("too many requests, try again in {} seconds", seconds)] })).respond_to(request)?)
+            .status(Status::TooManyRequests)
+            .raw_header("Retry-After", seconds.to_string())
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::{Client, LocalRequest};
+
+    fn seconds(start: Instant, seconds: f64) -> Instant {
+        start + Duration::from_secs_f64(seconds)
+    }
+
+    #[test]
+    fn burst_up_to_capacity() {
+        let buckets: Buckets = Buckets::new(Rate::new((3, 60)));
+        let start: Instant = Instant::now();
+        for _ in 0..3 {
+            assert!(buckets.take_at("client", start).is_ok());
+        }
+        assert!(buckets.take_at("client", start).is_err());
+        // Other clients have buckets of their own
+        assert!(buckets.take_at("other", start).is_ok());
+    }
+
+    #[test]
+    fn refill_over_time() {
+        let buckets: Buckets = Buckets::new(Rate::new((2, 10)));
+        let start: Instant = Instant::now();
+        assert!(buckets.take_at("client", start).is_ok());
+        assert!(buckets.take_at("client", start).is_ok());
+        assert!(buckets.take_at("client", seconds(start, 4.0)).is_err());
+        // One token comes back every 5 seconds
+        assert!(buckets.take_at("client", seconds(start, 5.0)).is_ok());
+        assert!(buckets.take_at("client", seconds(start, 5.0)).is_err());
+        // A bucket never holds more than its capacity, however long it is left
+        assert!(buckets.take_at("client", seconds(start, 1000.0)).is_ok());
+        assert!(buckets.take_at("client", seconds(start, 1000.0)).is_ok());
+        assert!(buckets.take_at("client", seconds(start, 1000.0)).is_err());
+    }
+
+    #[test]
+    fn wait_until_next_token() {
+        let buckets: Buckets = Buckets::new(Rate::new((1, 10)));
+        let start: Instant = Instant::now();
+        assert!(buckets.take_at("client", start).is_ok());
+        let wait: Duration = buckets.take_at("client", seconds(start, 2.5)).unwrap_err();
+        assert!((wait.as_secs_f64() - 7.5).abs() < 1e-6, "{:?}", wait);
+    }
+
+    #[test]
+    fn sweep_forgets_full_buckets() {
+        let buckets: Buckets = Buckets::new(Rate::new((1, 1)));
+        let start: Instant = Instant::now();
+        for client in 0..=MAX_CLIENTS {
+            buckets.take_at(&client.to_string(), start).unwrap();
+        }
+        // Not swept again before the interval is up, even though there are too many clients
+        buckets.take_at("new", start).unwrap();
+        assert_eq!(buckets.clients.lock().unwrap().buckets.len(), MAX_CLIENTS + 2);
+
+        buckets.take_at("late", start + SWEEP_INTERVAL).unwrap();
+        assert_eq!(buckets.clients.lock().unwrap().buckets.len(), 1);
+    }
+
+    fn retry_after(client: &Client, milliseconds: u64) -> String {
+        let request: LocalRequest = client.get("/");
+        let response: Response = TooManyRequests(Duration::from_millis(milliseconds)).respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::TooManyRequests);
+        response.headers().get_one("Retry-After").unwrap().to_string()
+    }
+
+    #[test]
+    fn retry_after_whole_seconds() {
+        let client: Client = Client::tracked(rocket::build()).unwrap();
+        assert_eq!(retry_after(&client, 7500), "8");
+        assert_eq!(retry_after(&client, 2000), "2");
+        // Clients are never told to retry straight away
+        assert_eq!(retry_after(&client, 200), "1");
+        assert_eq!(retry_after(&client, 0), "1");
+    }
+}
@@ -1,140 +1,927 @@
 mod manager;
 mod database;
+mod users;
+mod limits;
+mod upload;
 
 #[macro_use]
 extern crate rocket;
 
-use rocket::fs::{FileServer, NamedFile};
-use rocket::serde::{Serialize, json::Json};
-use serde_json::Value;
+use rocket::fairing::AdHoc;
+use rocket::data::{Data, Limits, ToByteUnit};
+use rocket::form::Form;
+use rocket::fs::{FileServer, NamedFile, Options, TempFile};
+use rocket::http::{CookieJar, Header, Status};
+use rocket::response::status::{BadRequest, Custom, Unauthorized};
+use rocket::serde::{Deserialize, Serialize, json::Json};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
-use std::fs::canonicalize;
 use std::path::PathBuf;
-use rocket::State;
+use std::sync::Arc;
+use rocket::{Request, State};
+use rocket::figment::{Figment, Profile};
+use rocket::figment::providers::{Env, Format, Serialized, Toml};
+use chrono::Utc;
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use nest::config::{BUILD_LIBRARY_DIRECTORY, Overrides, ParakeetConfig, instance_preview_path, profile_from_env};
+use nest::constraint;
 use rocket_db_pools::{Database, Connection};
 
-#[get("/models")]
-async fn get_models(db: &database::Db) -> Json<Vec<database::DisplayModel>> {
-    Json(database::get_display_models(db).await.expect("Could not load models from database"))
+// Models are listed a page at a time, filtered by a search over their name, description and author,
+// a category and any number of tags, e.g. `/models?search=clamp&tag=printable&tag=tool&sort=date&page=2`
+#[get("/models?<search>&<category>&<tag>&<sort>&<page>&<per_page>")]
+async fn get_models(db: &database::Db, search: Option<String>, category: Option<String>, tag: Vec<String>, sort: Option<database::ModelSort>, page: Option<i64>, per_page: Option<i64>) -> Json<database::ModelPage> {
+    let query: database::ModelQuery = database::ModelQuery {
+        search,
+        category,
+        tags: tag,
+        sort: sort.unwrap_or(database::ModelSort::Name),
+        page: page.unwrap_or(1).max(1),
+        per_page: per_page.unwrap_or(24).clamp(1, 100)
+    };
+    Json(database::search_models(db, &query).await.expect("Could not load models from database"))
 }
 
 #[get("/models/<id>")]
-async fn get_model(db: &database::Db, id: i64) -> Json<database::Model> {
-    Json(database::get_model(db, id).await.expect(&format!("Could not load model {} from database", id)))
+async fn get_model(db: &database::Db, cache: &State<database::ModelCache>, id: i64) -> Json<database::Model> {
+    let model: Arc<database::Model> = database::get_cached_model(db, cache, id).await.expect(&format!("Could not load model {} from database", id));
+    Json(model.as_ref().clone())
+}
+
+#[get("/models/<id>/versions")]
+async fn get_model_versions(db: &database::Db, id: i64) -> Json<Vec<database::ModelVersion>> {
+    Json(database::get_model_versions(db, id).await.expect(&format!("Could not load the versions of model {} from database", id)))
+}
+
+// The model to generate from, which is another version of the given model when `?version=` is used
+async fn load_version(db: &database::Db, cache: &database::ModelCache, model_id: i64, version: Option<&str>) -> Result<Arc<database::Model>, BadRequest<Json<Value>>> {
+    let model: Arc<database::Model> = database::get_cached_model(db, cache, model_id).await.expect(&format!("Could not load model {} from database", model_id));
+    let version: &str = match version {
+        Some(version) if version != model.version => version,
+        _ => return Ok(model)
+    };
+
+    match database::find_model_version(db, model_id, version).await.expect(&format!("Could not load the versions of model {} from database", model_id)) {
+        Some(version_id) => Ok(database::get_cached_model(db, cache, version_id).await.expect(&format!("Could not load model {} from database", version_id))),
+        None => Err(BadRequest(Some(Json(json!({ "errors": [format!("'{}' has no version '{}'", model.name, version)] })))))
+    }
 }
 
 #[derive(Serialize)]
 struct GenerateInfo {
     filename: String,
-    dimensions: (f64, f64, f64)
-}
-
-#[post("/generate/<model_id>/<part_id>", data = "<params>")]
-async fn generate_part(db: &database::Db, model_id: i64, part_id: i64, params: Json<Value>, state: &State<manager::ParakeetConfig>) -> Json<GenerateInfo> {
-    let model: database::Model = database::get_model(db, model_id).await.expect(&format!("Could not load model {} from database", model_id));
-    for part in model.parts {
-       if part.part_id == part_id {
-           let parameters: Vec<(String, manager::ParamType)> = part.parameters.iter()
-               .map(|parameter: &database::Parameter | {
-                   let entry: (String, manager::ParamType);
-                   match parameter {
-                       database::Parameter::IntRange(p) => entry = (p.name.to_string(), manager::ParamType::IntParam(params.0[&p.parameter_id.to_string()].as_i64().unwrap())),
-                       database::Parameter::IntList(p) => entry = (p.name.to_string(), manager::ParamType::IntParam(params.0[&p.parameter_id.to_string()].as_i64().unwrap())),
-                       database::Parameter::FloatRange(p) => entry = (p.name.to_string(), manager::ParamType::FloatParam(params.0[&p.parameter_id.to_string()].as_f64().unwrap())),
-                       database::Parameter::FloatList(p) => entry = (p.name.to_string(), manager::ParamType::FloatParam(params.0[&p.parameter_id.to_string()].as_f64().unwrap())),
-                       database::Parameter::StringLength(p) => entry = (p.name.to_string(), manager::ParamType::StringParam(params.0[&p.parameter_id.to_string()].as_str().unwrap().to_string())),
-                       database::Parameter::StringList(p) => entry = (p.name.to_string(), manager::ParamType::StringParam(params.0[&p.parameter_id.to_string()].as_str().unwrap().to_string())),
-                       database::Parameter::Bool(p) => entry = (p.name.to_string(), manager::ParamType::BoolParam(params.0[&p.parameter_id.to_string()].as_bool().unwrap())),
-                   }
-                   entry
-               }).collect();
-
-           let mut stl_instance: manager::STLInstance = manager::STLInstance {
-               model_id,
-               part_id,
-               parameters,
-               command_string: String::new()
-           };
-
-           let command_string: String = stl_instance.gen_command_string(part.name, state.build_path.join(model.scad_path).to_str().unwrap().to_string());
-
-           let path: String = stl_instance.get_identifier();
-           let exists: bool = stl_instance.does_stl_exist(&state.build_path);
-           let enough_space: bool = stl_instance.is_enough_space(&state.build_path, state.model_limit).expect(&format!("Could not read 'stls/' directory in {}", &state.build_path.to_str().unwrap()));
-
-           if !exists && enough_space {
-               stl_instance.create_stl(&state.build_path)
-                   .expect("Could not create part instance locally");
-               database::create_instance(db, database::Instance {
-                   part_id,
-                   path: path.to_string(),
-                   command_string,
-                   usage: None,
-                   age: None
-               })
-                   .await
-                   .expect(&format!("Could not create part instance with path {} in database", path.to_string()));
-           } else if !exists && !enough_space {
-               let least_valuable: Vec<database::Instance> = database::find_least_valuable_instance(db, 5)
-                   .await
-                   .expect("Could not find 'least valuable' instance in database");
-
-               for instance in least_valuable {
-                   fs::remove_file(&state.build_path.join(&instance.path))
-                       .expect(&format!("Could not delete file at {}", &instance.path));
-                   database::remove_instance(db, &instance.path)
-                       .await
-                       .expect(&format!("Could not remove instance with path {} from database", &instance.path));
-               }
-
-               stl_instance.create_stl(&state.build_path)
-                   .expect("Could not create part instance locally");
-               database::create_instance(db, database::Instance {
-                   part_id,
-                   path: path.to_string(),
-                   command_string,
-                   usage: None,
-                   age: None
-               })
-                   .await
-                   .expect(&format!("Could not create part instance with path {} in database", path.to_string()));
-           } else {
-               database::increment_instance_usage(db, path.to_string())
-                   .await
-                   .expect(&format!("Could not read part instance with path {} in database", path.to_string()))
-           }
-
-           return Json(GenerateInfo {
+    dimensions: (f64, f64, f64),
+    unit: String
+}
+
+// Look up the value provided for a parameter, keyed by its id
+fn get_value<'a>(params: &'a Value, parameter_id: i64, name: &str) -> Result<&'a Value, manager::ParameterError> {
+    params.get(parameter_id.to_string()).ok_or(manager::ParameterError::InvalidValue(name.to_string()))
+}
+
+fn get_i64(params: &Value, parameter_id: i64, name: &str) -> Result<i64, manager::ParameterError> {
+    get_value(params, parameter_id, name)?.as_i64().ok_or(manager::ParameterError::InvalidValue(name.to_string()))
+}
+
+fn get_f64(params: &Value, parameter_id: i64, name: &str) -> Result<f64, manager::ParameterError> {
+    get_value(params, parameter_id, name)?.as_f64().ok_or(manager::ParameterError::InvalidValue(name.to_string()))
+}
+
+fn get_string(params: &Value, parameter_id: i64, name: &str) -> Result<String, manager::ParameterError> {
+    Ok(get_value(params, parameter_id, name)?.as_str().ok_or(manager::ParameterError::InvalidValue(name.to_string()))?.to_string())
+}
+
+// Collect the provided value of a parameter into its SCAD type, validating it against its restrictions
+fn collect_parameter(parameter: &database::Parameter, params: &Value) -> Result<(String, manager::ParamType), manager::ParameterError> {
+    Ok(match parameter {
+        database::Parameter::IntRange(p) => {
+            let value: i64 = get_i64(params, p.parameter_id, &p.name)?;
+            (p.name.to_string(), manager::ParamType::IntParam(manager::snap_int(&p.name, value, p.lower, p.upper, p.step)?))
+        },
+        database::Parameter::IntList(p) => {
+            let value: i64 = get_i64(params, p.parameter_id, &p.name)?;
+            if !p.items.contains(&value) { return Err(manager::ParameterError::NotAllowed(p.name.to_string())) }
+            (p.name.to_string(), manager::ParamType::IntParam(value))
+        },
+        database::Parameter::FloatRange(p) => {
+            let value: f64 = get_f64(params, p.parameter_id, &p.name)?;
+            (p.name.to_string(), manager::ParamType::FloatParam(manager::snap_float(&p.name, value, p.lower, p.upper, p.step, p.precision)?))
+        },
+        database::Parameter::FloatList(p) => {
+            let value: f64 = get_f64(params, p.parameter_id, &p.name)?;
+            if !p.items.contains(&value) { return Err(manager::ParameterError::NotAllowed(p.name.to_string())) }
+            (p.name.to_string(), manager::ParamType::FloatParam(value))
+        },
+        database::Parameter::StringLength(p) => {
+            let value: String = get_string(params, p.parameter_id, &p.name)?;
+            if value.chars().count() as i64 > p.length { return Err(manager::ParameterError::OutOfRange(p.name.to_string())) }
+            (p.name.to_string(), manager::ParamType::StringParam(value))
+        },
+        database::Parameter::StringList(p) => {
+            let value: String = get_string(params, p.parameter_id, &p.name)?;
+            if !p.items.contains(&value) { return Err(manager::ParameterError::NotAllowed(p.name.to_string())) }
+            (p.name.to_string(), manager::ParamType::StringParam(value))
+        },
+        database::Parameter::Bool(p) => {
+            let value: bool = get_value(params, p.parameter_id, &p.name)?.as_bool().ok_or(manager::ParameterError::InvalidValue(p.name.to_string()))?;
+            (p.name.to_string(), manager::ParamType::BoolParam(value))
+        },
+        database::Parameter::Vector(p) => {
+            let values: &Vec<Value> = get_value(params, p.parameter_id, &p.name)?.as_array().ok_or(manager::ParameterError::InvalidValue(p.name.to_string()))?;
+            if values.len() != p.default_value.len() { return Err(manager::ParameterError::InvalidValue(p.name.to_string())) }
+
+            let mut components: Vec<manager::ParamType> = Vec::new();
+            for (position, component) in values.iter().enumerate() {
+                if p.element_type == "int" {
+                    let value: i64 = component.as_i64().ok_or(manager::ParameterError::InvalidValue(p.name.to_string()))?;
+                    components.push(manager::ParamType::IntParam(manager::snap_int(&p.name, value, p.lower[position] as i64, p.upper[position] as i64, None)?));
+                } else {
+                    let value: f64 = component.as_f64().ok_or(manager::ParameterError::InvalidValue(p.name.to_string()))?;
+                    components.push(manager::ParamType::FloatParam(manager::snap_float(&p.name, value, p.lower[position], p.upper[position], None, None)?));
+                }
+            }
+            (p.name.to_string(), manager::ParamType::VectorParam(components))
+        }
+    })
+}
+
+fn collect_parameters(parameters: &[database::Parameter], params: &Value) -> Result<Vec<(String, manager::ParamType)>, manager::ParameterError> {
+    parameters.iter().map(|parameter| collect_parameter(parameter, params)).collect()
+}
+
+fn constraint_value(value: &manager::ParamType) -> constraint::Value {
+    match value {
+        manager::ParamType::BoolParam(value) => constraint::Value::Bool(*value),
+        manager::ParamType::IntParam(value) => constraint::Value::Number(*value as f64),
+        manager::ParamType::FloatParam(value) => constraint::Value::Number(*value),
+        manager::ParamType::StringParam(value) => constraint::Value::Str(value.to_string()),
+        manager::ParamType::VectorParam(components) => constraint::Value::Vector(components.iter()
+            .map(|component| match component {
+                manager::ParamType::IntParam(value) => *value as f64,
+                manager::ParamType::FloatParam(value) => *value,
+                _ => 0.0
+            })
+            .collect())
+    }
+}
+
+// Convert the values of length parameters from the requested unit into the unit they were declared in,
+// integers are rounded to the nearest whole value of their own unit
+fn convert_units(parameters: &[&database::Parameter], params: &Value, unit: &str) -> Result<Value, manager::ParameterError> {
+    let scale: f64 = manager::length_unit_scale(unit).ok_or(manager::ParameterError::UnknownUnit(unit.to_string()))?;
+    let mut converted: Value = params.clone();
+
+    for parameter in parameters {
+        let parameter_scale: f64 = match parameter.unit().and_then(manager::length_unit_scale) {
+            Some(parameter_scale) => parameter_scale,
+            None => continue
+        };
+        let integer: bool = match parameter {
+            database::Parameter::Vector(p) => p.element_type == "int",
+            database::Parameter::IntRange(_) | database::Parameter::IntList(_) => true,
+            _ => false
+        };
+        let convert = |value: &Value| -> Value {
+            match value.as_f64() {
+                Some(value) if integer => json!((value * scale / parameter_scale).round() as i64),
+                Some(value) => json!(value * scale / parameter_scale),
+                None => value.clone()
+            }
+        };
+
+        if let Some(value) = converted.get_mut(parameter.parameter_id().to_string()) {
+            *value = match value.as_array() {
+                Some(components) => Value::Array(components.iter().map(convert).collect()),
+                None => convert(value)
+            };
+        }
+    }
+
+    Ok(converted)
+}
+
+// Parameters whose condition doesn't hold fall back to their default values, then every
+// constraint of the model and part is checked against the resulting values
+fn apply_constraints(model: &database::Model, part: &database::Part, parameters: &mut Vec<(String, manager::ParamType)>) -> Result<(), manager::ParameterError> {
+    let evaluation_error = |error: constraint::ConstraintError| manager::ParameterError::ConstraintsViolated(vec![error.to_string()]);
+    let values: HashMap<String, constraint::Value> = parameters.iter()
+        .map(|parameter| (parameter.0.to_string(), constraint_value(&parameter.1)))
+        .collect();
+
+    let all_parameters: Vec<&database::Parameter> = model.parameters.iter().chain(part.parameters.iter()).collect();
+    for condition in model.conditions.iter().chain(part.conditions.iter()) {
+        let expression: constraint::Expression = constraint::Expression::parse(&condition.expression).map_err(evaluation_error)?;
+        if expression.is_satisfied(&values).map_err(evaluation_error)? {
+            continue
+        }
+
+        if let Some(parameter) = all_parameters.iter().find(|parameter| parameter.parameter_id() == condition.parameter_id) {
+            let defaults: Value = json!({ (parameter.parameter_id().to_string()): parameter.default_value() });
+            let default: (String, manager::ParamType) = collect_parameter(parameter, &defaults)?;
+            for entry in parameters.iter_mut() {
+                if entry.0 == default.0 {
+                    entry.1 = default.1;
+                    break
+                }
+            }
+        }
+    }
+
+    let values: HashMap<String, constraint::Value> = parameters.iter()
+        .map(|parameter| (parameter.0.to_string(), constraint_value(&parameter.1)))
+        .collect();
+
+    let mut violated: Vec<String> = Vec::new();
+    for model_constraint in model.constraints.iter().chain(part.constraints.iter()) {
+        let expression: constraint::Expression = constraint::Expression::parse(&model_constraint.expression).map_err(evaluation_error)?;
+        if !expression.is_satisfied(&values).map_err(evaluation_error)? {
+            violated.push(model_constraint.message.to_string());
+        }
+    }
+
+    if violated.is_empty() { Ok(()) } else { Err(manager::ParameterError::ConstraintsViolated(violated)) }
+}
+
+// Every failed validation is reported back as a list of messages: { "errors": [ "..." ] }
+fn bad_request(error: manager::ParameterError) -> BadRequest<Json<Value>> {
+    BadRequest(Some(Json(json!({ "errors": error.messages() }))))
+}
+
+// The routes that render parts also refuse requests once the client is over its render limit
+#[derive(Responder)]
+enum GenerateError {
+    Invalid(BadRequest<Json<Value>>),
+    Limited(limits::TooManyRequests),
+    Failed(Custom<Json<Value>>)
+}
+
+impl From<BadRequest<Json<Value>>> for GenerateError {
+    fn from(error: BadRequest<Json<Value>>) -> Self {
+        GenerateError::Invalid(error)
+    }
+}
+
+// A part that couldn't be rendered, read or evicted fails the request with the reason, rather than a bare 500
+fn render_error(error: impl fmt::Display) -> GenerateError {
+    GenerateError::Failed(Custom(Status::InternalServerError, Json(json!({ "errors": [error.to_string()] }))))
+}
+
+// Shared parameters are passed to the part's module ahead of its own parameters. Parts that aren't cached
+// yet count towards the client's render limit.
+async fn instantiate_part(db: &database::Db, state: &ParakeetConfig, quota: &limits::RenderQuota<'_>, model: &database::Model, part: &database::Part, shared_params: &Value, part_params: &Value) -> Result<manager::STLInstance, GenerateError> {
+    let model_id: i64 = model.model_id;
+    let part_id: i64 = part.part_id;
+    let mut parameters: Vec<(String, manager::ParamType)> = collect_parameters(&model.parameters, shared_params).map_err(bad_request)?;
+    parameters.extend(collect_parameters(&part.parameters, part_params).map_err(bad_request)?);
+    apply_constraints(model, part, &mut parameters).map_err(bad_request)?;
+
+    let mut stl_instance: manager::STLInstance = manager::STLInstance {
+        model_id,
+        part_id,
+        parameters,
+        command_string: String::new()
+    };
+
+    let command_string: String = stl_instance.gen_command_string(part.name.to_string(), state.build_path.join(&model.scad_path).to_str().unwrap().to_string());
+    let library_path: PathBuf = state.build_path.join(&model.build_directory).join(BUILD_LIBRARY_DIRECTORY);
+
+    let path: String = stl_instance.get_identifier();
+    let exists: bool = stl_instance.does_stl_exist(&state.build_path);
+    let enough_space: bool = stl_instance.is_enough_space(&state.build_path, state.model_limit).expect(&format!("Could not read 'stls/' directory in {}", &state.build_path.to_str().unwrap()));
+    if !exists {
+        quota.take_render().map_err(GenerateError::Limited)?;
+    }
+
+    if !exists && enough_space {
+        stl_instance.create_stl(&state.build_path, &library_path).map_err(render_error)?;
+        database::create_instance(db, database::Instance {
+            part_id,
+            path: path.to_string(),
+            command_string,
+            usage: None,
+            age: None
+        })
+            .await
+            .expect(&format!("Could not create part instance with path {} in database", path.to_string()));
+    } else if !exists && !enough_space {
+        let least_valuable: Vec<database::Instance> = database::find_least_valuable_instance(db, 5)
+            .await
+            .expect("Could not find 'least valuable' instance in database");
+
+        for instance in least_valuable {
+            fs::remove_file(&state.build_path.join(&instance.path))
+                .map_err(|error| render_error(format!("could not delete part instance {} ({})", &instance.path, error)))?;
+            let preview_path: PathBuf = state.build_path.join(instance_preview_path(&instance.path));
+            if preview_path.exists() {
+                fs::remove_file(&preview_path)
+                    .map_err(|error| render_error(format!("could not delete part preview {} ({})", instance_preview_path(&instance.path), error)))?;
+            }
+            database::remove_instance(db, &instance.path)
+                .await
+                .expect(&format!("Could not remove instance with path {} from database", &instance.path));
+        }
+
+        stl_instance.create_stl(&state.build_path, &library_path).map_err(render_error)?;
+        database::create_instance(db, database::Instance {
+            part_id,
+            path: path.to_string(),
+            command_string,
+            usage: None,
+            age: None
+        })
+            .await
+            .expect(&format!("Could not create part instance with path {} in database", path.to_string()));
+    } else {
+        database::increment_instance_usage(db, path.to_string())
+            .await
+            .expect(&format!("Could not read part instance with path {} in database", path.to_string()))
+    }
+
+    Ok(stl_instance)
+}
+
+// Length values may be provided in another unit with `?unit=inch`, the dimensions are then reported in that unit too
+// Another version of the model is generated with `?version=`, the part being the one of the same name in that version
+#[post("/generate/<model_id>/<part_id>?<unit>&<version>", data = "<params>")]
+async fn generate_part(db: &database::Db, cache: &State<database::ModelCache>, quota: limits::RenderQuota<'_>, model_id: i64, part_id: i64, unit: Option<String>, version: Option<String>, params: Json<Value>, state: &State<ParakeetConfig>) -> Result<Json<GenerateInfo>, GenerateError> {
+    let unit: String = unit.unwrap_or(String::from(manager::DEFAULT_LENGTH_UNIT));
+    let requested: Arc<database::Model> = database::get_cached_model(db, cache, model_id).await.expect(&format!("Could not load model {} from database", model_id));
+    let part_name: Option<&str> = requested.parts.iter().find(|part| part.part_id == part_id).map(|part| part.name.as_str());
+    let model: Arc<database::Model> = load_version(db, cache, model_id, version.as_deref()).await?;
+    for part in &model.parts {
+       if Some(part.name.as_str()) == part_name {
+           let parameters: Vec<&database::Parameter> = model.parameters.iter().chain(part.parameters.iter()).collect();
+           let converted: Value = convert_units(&parameters, &params.0, &unit).map_err(bad_request)?;
+           let stl_instance: manager::STLInstance = instantiate_part(db, state, &quota, &model, part, &converted, &converted).await?;
+
+           let scale: f64 = manager::length_unit_scale(&unit).unwrap();
+           let dimensions: (f64, f64, f64) = stl_instance.get_dimensions(&state.build_path).expect("Could not determine dimensions of the model");
+           return Ok(Json(GenerateInfo {
                filename: stl_instance.get_identifier(),
-               dimensions: stl_instance.get_dimensions(&state.build_path).expect("Could not determine dimensions of the model")
-           })
+               dimensions: (dimensions.0 / scale, dimensions.1 / scale, dimensions.2 / scale),
+               unit
+           }))
        }
     }
 
-    Json(GenerateInfo {
+    Ok(Json(GenerateInfo {
         filename: String::from(""),
-        dimensions: (0.0, 0.0, 0.0)
+        dimensions: (0.0, 0.0, 0.0),
+        unit
+    }))
+}
+
+// A PNG preview of a part, for clients that can't show the .stl itself such as link previews and emails.
+// The values are those `/generate` takes, as JSON in `?values=`, and any that are left out take their
+// default value. Previews are rendered at the thumbnail size and camera of the config, and are cached
+// for as long as the .stl instance they are rendered from.
+#[get("/preview/<model_id>/<part_id>?<values>&<unit>&<version>")]
+async fn preview_part(db: &database::Db, cache: &State<database::ModelCache>, quota: limits::RenderQuota<'_>, model_id: i64, part_id: i64, values: Option<String>, unit: Option<String>, version: Option<String>, state: &State<ParakeetConfig>) -> Result<NamedFile, GenerateError> {
+    let unit: String = unit.unwrap_or(String::from(manager::DEFAULT_LENGTH_UNIT));
+    let values: Value = match values {
+        Some(values) => serde_json::from_str(&values).map_err(|_| bad_request(manager::ParameterError::InvalidValue(String::from("values"))))?,
+        None => json!({})
+    };
+    let requested: Arc<database::Model> = database::get_cached_model(db, cache, model_id).await.expect(&format!("Could not load model {} from database", model_id));
+    let part_name: Option<&str> = requested.parts.iter().find(|part| part.part_id == part_id).map(|part| part.name.as_str());
+    let model: Arc<database::Model> = load_version(db, cache, model_id, version.as_deref()).await?;
+    let part: &database::Part = model.parts.iter()
+        .find(|part| Some(part.name.as_str()) == part_name)
+        .ok_or_else(|| BadRequest(Some(Json(json!({ "errors": [format!("'{}' has no part {}", model.name, part_id)] })))))?;
+
+    let parameters: Vec<&database::Parameter> = model.parameters.iter().chain(part.parameters.iter()).collect();
+    let converted: Value = convert_units(&parameters, &values, &unit).map_err(bad_request)?;
+    let mut params: serde_json::Map<String, Value> = parameters.iter()
+        .map(|parameter| (parameter.parameter_id().to_string(), parameter.default_value()))
+        .collect();
+    if let Some(converted) = converted.as_object() {
+        params.extend(converted.clone());
+    }
+    let params: Value = Value::Object(params);
+
+    let stl_instance: manager::STLInstance = instantiate_part(db, state, &quota, &model, part, &params, &params).await?;
+    if !stl_instance.does_preview_exist(&state.build_path) {
+        stl_instance.create_preview(&state.build_path, state.thumbnail_dimensions().expect("Invalid thumbnail size in config"), &state.thumbnail_camera)
+            .map_err(render_error)?;
+    }
+
+    Ok(NamedFile::open(state.build_path.join(stl_instance.get_preview_identifier())).await.expect("Could not open part preview"))
+}
+
+// The length of the ids configs are saved under
+const CONFIG_ID_LENGTH: usize = 10;
+
+#[derive(Deserialize)]
+struct ConfigRequest {
+    model_id: i64,
+    part_id: i64,
+    // The values as `/generate` takes them, keyed by parameter id
+    values: Value,
+    unit: Option<String>
+}
+
+#[derive(Serialize)]
+struct ConfigInfo {
+    config_id: String,
+    model_id: i64,
+    part_id: i64,
+    // The version the config was saved with, which differs from that of the model once it is no longer indexed
+    saved_version: String,
+    unit: String,
+    values: Value,
+    // Parameters that had a value saved but no longer exist
+    missing: Vec<String>,
+    creation_date: String
+}
+
+// Save a parameter set under a short id, once it has been checked the same way `/generate` checks it
+#[post("/configs", data = "<request>")]
+async fn save_config(db: &database::Db, cache: &State<database::ModelCache>, request: Json<ConfigRequest>) -> Result<Json<Value>, BadRequest<Json<Value>>> {
+    let unit: String = request.unit.clone().unwrap_or(String::from(manager::DEFAULT_LENGTH_UNIT));
+    let model: Arc<database::Model> = database::get_cached_model(db, cache, request.model_id).await.expect(&format!("Could not load model {} from database", request.model_id));
+    let part: &database::Part = model.parts.iter()
+        .find(|part| part.part_id == request.part_id)
+        .ok_or_else(|| BadRequest(Some(Json(json!({ "errors": [format!("'{}' has no part {}", model.name, request.part_id)] })))))?;
+
+    let parameters: Vec<&database::Parameter> = model.parameters.iter().chain(part.parameters.iter()).collect();
+    let converted: Value = convert_units(&parameters, &request.values, &unit).map_err(bad_request)?;
+    let mut collected: Vec<(String, manager::ParamType)> = collect_parameters(&model.parameters, &converted).map_err(bad_request)?;
+    collected.extend(collect_parameters(&part.parameters, &converted).map_err(bad_request)?);
+    apply_constraints(&model, part, &mut collected).map_err(bad_request)?;
+
+    let values: serde_json::Map<String, Value> = parameters.iter()
+        .filter_map(|parameter| {
+            let value: &Value = request.values.get(parameter.parameter_id().to_string())?;
+            Some((parameter.name().to_string(), value.clone()))
+        })
+        .collect();
+    let config_id: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(CONFIG_ID_LENGTH)
+        .map(char::from)
+        .collect();
+
+    database::create_saved_config(db, &database::SavedConfig {
+        config_id: config_id.to_string(),
+        model_name: model.name.to_string(),
+        version: model.version.to_string(),
+        part_name: part.name.to_string(),
+        unit,
+        values: Value::Object(values),
+        creation_date: Utc::now()
+    })
+        .await
+        .expect(&format!("Could not save config for model {}", model.model_id));
+
+    Ok(Json(json!({ "config_id": config_id })))
+}
+
+// Restore a saved config against the models as they are indexed now, with the values keyed by parameter id
+#[get("/configs/<config_id>")]
+async fn get_config(db: &database::Db, cache: &State<database::ModelCache>, config_id: String) -> Option<Json<ConfigInfo>> {
+    let config: database::SavedConfig = database::get_saved_config(db, &config_id).await.expect(&format!("Could not load config {} from database", config_id))?;
+    let model_id: i64 = database::find_config_model(db, &config.model_name, &config.version).await.expect(&format!("Could not load the versions of '{}' from database", config.model_name))?;
+    let model: Arc<database::Model> = database::get_cached_model(db, cache, model_id).await.expect(&format!("Could not load model {} from database", model_id));
+    let part: &database::Part = model.parts.iter().find(|part| part.name == config.part_name)?;
+
+    let parameters: Vec<&database::Parameter> = model.parameters.iter().chain(part.parameters.iter()).collect();
+    let mut values: serde_json::Map<String, Value> = serde_json::Map::new();
+    let mut missing: Vec<String> = Vec::new();
+    for (name, value) in config.values.as_object().into_iter().flatten() {
+        match parameters.iter().find(|parameter| parameter.name() == name) {
+            Some(parameter) => { values.insert(parameter.parameter_id().to_string(), value.clone()); },
+            None => missing.push(name.to_string())
+        }
+    }
+
+    Some(Json(ConfigInfo {
+        config_id: config.config_id,
+        model_id: model.model_id,
+        part_id: part.part_id,
+        saved_version: config.version,
+        unit: config.unit,
+        values: Value::Object(values),
+        missing,
+        creation_date: config.creation_date.to_rfc3339()
+    }))
+}
+
+#[derive(Deserialize)]
+struct Credentials {
+    username: String,
+    password: String
+}
+
+fn account_error(error: users::AccountError) -> BadRequest<Json<Value>> {
+    BadRequest(Some(Json(json!({ "errors": [error.to_string()] }))))
+}
+
+// Register an account and sign in with it. New accounts are viewers, apart from the first which is an admin.
+#[post("/users", data = "<credentials>")]
+async fn register(db: &database::Db, cookies: &CookieJar<'_>, credentials: Json<Credentials>) -> Result<Json<database::User>, BadRequest<Json<Value>>> {
+    users::validate_credentials(&credentials.username, &credentials.password).map_err(account_error)?;
+    let password_hash: String = users::hash_password(&credentials.password).expect("Could not hash password");
+
+    let user: database::User = database::create_user(db, &credentials.username, &password_hash)
+        .await
+        .expect(&format!("Could not create user '{}' in database", credentials.username))
+        .ok_or_else(|| account_error(users::AccountError::UsernameTaken(credentials.username.to_string())))?;
+    users::sign_in(cookies, &user);
+
+    Ok(Json(user))
+}
+
+#[post("/login", data = "<credentials>")]
+async fn login(db: &database::Db, cookies: &CookieJar<'_>, credentials: Json<Credentials>) -> Result<Json<database::User>, Unauthorized<Json<Value>>> {
+    let unauthorized = || Unauthorized(Some(Json(json!({ "errors": [users::AccountError::InvalidCredentials.to_string()] }))));
+    let (user, password_hash): (database::User, String) = database::get_user_credentials(db, &credentials.username)
+        .await
+        .expect(&format!("Could not load user '{}' from database", credentials.username))
+        .ok_or_else(unauthorized)?;
+    if !users::verify_password(&credentials.password, &password_hash) {
+        return Err(unauthorized());
+    }
+    users::sign_in(cookies, &user);
+
+    Ok(Json(user))
+}
+
+#[post("/logout")]
+fn logout(cookies: &CookieJar<'_>) -> Status {
+    users::sign_out(cookies);
+    Status::NoContent
+}
+
+#[get("/users/me")]
+fn get_current_user(user: database::User) -> Json<database::User> {
+    Json(user)
+}
+
+#[get("/users")]
+async fn get_users(db: &database::Db, _admin: users::Admin) -> Json<Vec<database::User>> {
+    Json(database::get_users(db).await.expect("Could not load users from database"))
+}
+
+#[derive(Deserialize)]
+struct RoleRequest {
+    role: database::Role
+}
+
+// Admins can't change their own role, so that there is always at least one admin left
+#[put("/users/<user_id>/role", data = "<request>")]
+async fn set_user_role(db: &database::Db, admin: users::Admin, user_id: i64, request: Json<RoleRequest>) -> Result<Option<Json<database::User>>, BadRequest<Json<Value>>> {
+    if admin.0.user_id == user_id {
+        return Err(BadRequest(Some(Json(json!({ "errors": ["admins can't change their own role"] })))));
+    }
+
+    Ok(database::set_user_role(db, user_id, request.role)
+        .await
+        .expect(&format!("Could not update the role of user {} in database", user_id))
+        .map(Json))
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    name: String
+}
+
+// Create an API token for the signed in user. The token is only ever returned here, it is sent by clients
+// as `Authorization: Bearer <token>`.
+#[post("/tokens", data = "<request>")]
+async fn create_token(db: &database::Db, user: database::User, request: Json<TokenRequest>) -> Json<Value> {
+    let token: String = users::generate_token();
+    let api_token: database::ApiToken = database::create_api_token(db, user.user_id, &request.name, &users::hash_token(&token))
+        .await
+        .expect(&format!("Could not create API token for user {} in database", user.user_id));
+
+    Json(json!({
+        "token_id": api_token.token_id,
+        "name": api_token.name,
+        "creation_date": api_token.creation_date,
+        "token": token
+    }))
+}
+
+#[get("/tokens")]
+async fn get_tokens(db: &database::Db, user: database::User) -> Json<Vec<database::ApiToken>> {
+    Json(database::get_api_tokens(db, user.user_id).await.expect(&format!("Could not load API tokens of user {} from database", user.user_id)))
+}
+
+#[delete("/tokens/<token_id>")]
+async fn revoke_token(db: &database::Db, user: database::User, token_id: i64) -> Option<Status> {
+    let removed: bool = database::remove_api_token(db, user.user_id, token_id)
+        .await
+        .expect(&format!("Could not remove API token {} from database", token_id));
+
+    if removed { Some(Status::NoContent) } else { None }
+}
+
+#[derive(FromForm)]
+struct ModelUpload<'r> {
+    // Each file is named after its path within the model, e.g. "info.json" or "lib/hinge.scad"
+    files: Vec<TempFile<'r>>,
+    category: Option<String>
+}
+
+fn upload_error(error: upload::UploadError) -> Custom<Json<Value>> {
+    let status: Status = match error {
+        upload::UploadError::NotOwner(_) => Status::Forbidden,
+        _ => Status::BadRequest
+    };
+    Custom(status, Json(json!({ "errors": [error.to_string()] })))
+}
+
+// Publish the staged model, answering with the id of its new current version. A model that is already
+// there may only be replaced by the author who uploaded it or by an admin, and becomes theirs if it is new.
+async fn publish_model(db: &database::Db, state: &ParakeetConfig, author: &database::User, staging: upload::Staging, category: Option<&str>) -> Result<Json<Value>, Custom<Json<Value>>> {
+    let name: String = staging.name().map_err(upload_error)?;
+    let owner: Option<i64> = database::get_model_owner(db, &name).await.expect("Could not load model owner from database");
+    let exists: bool = database::find_current_model(db, &name).await.expect(&format!("Could not load model '{}' from database", name)).is_some();
+    let allowed: bool = match owner {
+        Some(owner) => owner == author.user_id,
+        None => !exists
+    };
+    if !allowed && author.role < database::Role::Admin {
+        return Err(upload_error(upload::UploadError::NotOwner(name)));
+    }
+
+    let name: String = staging.publish(&state.plume_path, category).await.map_err(upload_error)?;
+    if owner.is_none() {
+        database::set_model_owner(db, &name, author.user_id).await.expect("Could not record model owner in database");
+    }
+    let model_id: Option<i64> = database::find_current_model(db, &name).await.expect(&format!("Could not load model '{}' from database", name));
+
+    Ok(Json(json!({ "model_id": model_id, "name": name })))
+}
+
+// Authors publish a model by uploading its files as a multipart form. A model of the same name is replaced
+// by the upload where it is theirs to replace, otherwise it is added to `category`, or to the top of the
+// models directory.
+#[post("/models", format = "multipart/form-data", data = "<upload>")]
+async fn upload_model(db: &database::Db, state: &State<ParakeetConfig>, author: users::Author, mut upload: Form<ModelUpload<'_>>) -> Result<Json<Value>, Custom<Json<Value>>> {
+    let staging: upload::Staging = upload::Staging::new().expect("Could not create upload directory");
+    for file in upload.files.iter_mut() {
+        let name: String = file.raw_name()
+            .map(|name| name.dangerous_unsafe_unsanitized_raw().as_str().to_string())
+            .unwrap_or_default();
+        let path: PathBuf = staging.file_path(&name).map_err(upload_error)?;
+        file.copy_to(&path).await.expect(&format!("Could not store uploaded file '{}'", name));
+    }
+
+    publish_model(db, state, &author.0, staging, upload.category.as_deref()).await
+}
+
+// The same as a ZIP archive of the model directory, e.g. `curl --data-binary @clamp.zip -H "Content-Type: application/zip"`
+#[post("/models?<category>", format = "application/zip", data = "<archive>")]
+async fn upload_model_archive(db: &database::Db, state: &State<ParakeetConfig>, author: users::Author, category: Option<String>, archive: Data<'_>, limits: &Limits) -> Result<Json<Value>, Custom<Json<Value>>> {
+    let archive = archive.open(limits.get("file").unwrap_or(16.mebibytes())).into_bytes().await.expect("Could not read uploaded archive");
+    if !archive.is_complete() {
+        return Err(upload_error(upload::UploadError::TooLarge));
+    }
+
+    let staging: upload::Staging = upload::Staging::new().expect("Could not create upload directory");
+    staging.extract_archive(&archive).map_err(upload_error)?;
+
+    publish_model(db, state, &author.0, staging, category.as_deref()).await
+}
+
+#[derive(Responder)]
+#[response(content_type = "application/zip")]
+struct Bundle {
+    archive: fs::File,
+    disposition: Header<'static>
+}
+
+// Expects the parameter values of every part keyed by part id, with the values of any shared
+// parameters provided once: { "shared": { "<parameter_id>": value }, "<part_id>": { "<parameter_id>": value } }
+// Another version of the model is bundled with `?version=`, in which case the part ids are those of that version
+#[post("/bundle/<model_id>?<version>", data = "<params>")]
+async fn bundle_model(db: &database::Db, cache: &State<database::ModelCache>, quota: limits::RenderQuota<'_>, model_id: i64, version: Option<String>, params: Json<Value>, state: &State<ParakeetConfig>) -> Result<Bundle, GenerateError> {
+    let model: Arc<database::Model> = load_version(db, cache, model_id, version.as_deref()).await?;
+
+    let mut bundle: manager::Bundle = manager::Bundle::new().map_err(render_error)?;
+    let mut filenames: Vec<String> = Vec::new();
+    let mut manifest_parts: Vec<Value> = Vec::new();
+    for part in &model.parts {
+        let part_params: &Value = &params.0[&part.part_id.to_string()];
+        let stl_instance: manager::STLInstance = instantiate_part(db, state, &quota, &model, part, &params.0["shared"], part_params).await?;
+
+        // Parts sharing a name are told apart by their id, so that no file of the archive is overwritten
+        let mut filename: String = format!("{}.stl", part.name);
+        if filenames.contains(&filename) {
+            filename = format!("{}-{}.stl", part.name, part.part_id);
+        }
+
+        // Each .stl is added straight away, as rendering the next part may evict it from the cache
+        bundle.add_file(&filename, &state.build_path.join(stl_instance.get_identifier()))
+            .map_err(|error| render_error(format!("could not read part instance {} ({})", stl_instance.get_identifier(), error)))?;
+        filenames.push(filename.to_string());
+        manifest_parts.push(json!({
+            "name": part.name,
+            "file": filename,
+            "parameters": stl_instance.parameters.iter()
+                .map(|parameter| (parameter.0.to_string(), json!(parameter.1)))
+                .collect::<serde_json::Map<String, Value>>()
+        }));
+    }
+
+    let manifest: Value = json!({
+        "model": model.name,
+        "version": model.version,
+        "commit": model.commit,
+        "author": model.author,
+        "parts": manifest_parts
+    });
+
+    Ok(Bundle {
+        archive: bundle.finish(&manifest).map_err(render_error)?,
+        disposition: Header::new("Content-Disposition", format!("attachment; filename=\"{}.zip\"", model.name))
     })
 }
 
-// FIXME: Shouldn't really have to resort to this hack
-#[get["/<_id>"]]
-async fn pass(_id: i64, state: &State<manager::ParakeetConfig>) -> Option<NamedFile> {
-    NamedFile::open(&state.build_path.join("index.html")).await.ok()
+// Requests over the request limit are turned away by the `RenderQuota` guard before they reach their route
+#[catch(429)]
+fn too_many_requests(request: &Request<'_>) -> limits::TooManyRequests {
+    limits::TooManyRequests::of_request(request)
+}
+
+#[derive(Responder)]
+struct AppShell {
+    file: NamedFile,
+    cache_control: Header<'static>
+}
+
+// The extensions of the files a build directory holds, which are never client-side routes
+const ASSET_EXTENSIONS: [&str; 16] = ["html", "js", "mjs", "css", "map", "json", "webmanifest", "txt", "ico", "png", "jpg", "jpeg", "gif", "svg", "woff2", "stl"];
+
+// Client-side routes are answered with the app itself. The fallback ranks below the API and the files of the
+// build directory, so it never shadows them, and paths of missing assets (e.g. `/static/main.js`) are still a
+// 404 rather than the app. Routes may otherwise hold dots, e.g. `/models/clamp.v2`. The app is always
+// revalidated, so that a new build is picked up straight away.
+#[get("/<path..>", rank = 20)]
+async fn app(path: PathBuf, state: &State<ParakeetConfig>) -> Option<AppShell> {
+    let asset: bool = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => ASSET_EXTENSIONS.contains(&extension.to_lowercase().as_str()),
+        None => false
+    };
+    if path.starts_with("api") || asset {
+        return None;
+    }
+
+    Some(AppShell {
+        file: NamedFile::open(&state.build_path.join("index.html")).await.ok()?,
+        cache_control: Header::new("Cache-Control", "no-cache")
+    })
 }
 
 #[rocket::main]
 async fn main() -> Result<(), rocket::Error> {
-    let config: manager::ParakeetConfig = confy::load("parakeet", None).expect("Could not load config file");
+    let overrides: Overrides = Overrides::from_env().expect("Could not read config overrides");
+    let config: ParakeetConfig = ParakeetConfig::load(profile_from_env().as_deref(), overrides).expect("Could not load config file");
+
+    // Uploaded models are larger than Rocket's default limits allow, so the limits are raised ahead of
+    // Rocket.toml and the ROCKET_* variables, which can still set them otherwise. The database is always
+    // the one plume indexes into, so its path comes from the parakeet config.
+    // Session cookies are encrypted with Rocket's `secret_key`, which has to be set (e.g. with `ROCKET_SECRET_KEY`)
+    // outside of debug builds, where a random key is generated on every launch instead.
+    // The profile (`debug` or `release` by build, or `ROCKET_PROFILE`) picks which Rocket.toml table applies.
+    let figment: Figment = Figment::from(rocket::Config::default())
+        .merge(("limits.file", "16 MiB"))
+        .merge(("limits.data-form", "32 MiB"))
+        .merge(Toml::file(Env::var_or("ROCKET_CONFIG", "Rocket.toml")).nested())
+        .merge(Env::prefixed("ROCKET_").ignore(&["PROFILE"]).global())
+        .merge(Serialized::global("databases.sqlx.url", config.database_path.to_str().expect("Database path is not valid unicode")))
+        .select(Profile::from_env_or("ROCKET_PROFILE", rocket::Config::DEFAULT_PROFILE));
 
-    let _rocket = rocket::build()
-        .mount("/", routes![pass])
-        .mount("/", FileServer::from(&config.build_path))
-        .mount("/api", routes![get_models, get_model, generate_part])
+    let _rocket = rocket::custom(figment)
+        // The root is served by `app` too, rather than as the index of the build directory
+        .mount("/", FileServer::new(&config.build_path, Options::None))
+        .mount("/", routes![app])
+        .mount("/api", routes![get_models, get_model, get_model_versions, generate_part, preview_part, bundle_model, save_config, get_config])
+        .mount("/api", routes![register, login, logout, get_current_user, get_users, set_user_role, create_token, get_tokens, revoke_token])
+        .mount("/api", routes![upload_model, upload_model_archive])
+        .register("/api", catchers![too_many_requests])
         .attach(database::Db::init())
+        .attach(AdHoc::try_on_ignite("Database migrations", database::run_migrations))
+        .attach(limits::RateLimiting)
         .manage(config)
+        .manage(database::ModelCache::default())
         .launch()
         .await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn details() -> database::ParameterDetails {
+        database::ParameterDetails { label: String::new(), description: None, group: None, position: 0 }
+    }
+
+    fn float(parameter_id: i64, unit: Option<&str>) -> database::Parameter {
+        database::Parameter::FloatRange(database::FloatRangeParameter {
+            parameter_id,
+            name: format!("p{}", parameter_id),
+            details: details(),
+            default_value: 0.0,
+            lower: -1000.0,
+            upper: 1000.0,
+            step: None,
+            precision: None,
+            unit: unit.map(String::from)
+        })
+    }
+
+    fn int(parameter_id: i64, unit: Option<&str>) -> database::Parameter {
+        database::Parameter::IntRange(database::IntRangeParameter {
+            parameter_id,
+            name: format!("p{}", parameter_id),
+            details: details(),
+            default_value: 0,
+            lower: -1000,
+            upper: 1000,
+            step: None,
+            unit: unit.map(String::from)
+        })
+    }
+
+    fn vector(parameter_id: i64, element_type: &str, unit: Option<&str>) -> database::Parameter {
+        database::Parameter::Vector(database::VectorParameter {
+            parameter_id,
+            name: format!("p{}", parameter_id),
+            details: details(),
+            element_type: element_type.to_string(),
+            default_value: vec![0.0, 0.0],
+            lower: vec![-1000.0, -1000.0],
+            upper: vec![1000.0, 1000.0],
+            unit: unit.map(String::from)
+        })
+    }
+
+    fn convert(parameter: database::Parameter, value: Value, unit: &str) -> Value {
+        let converted: Value = convert_units(&[&parameter], &json!({ "0": value }), unit).unwrap();
+        converted["0"].clone()
+    }
+
+    #[test]
+    fn unit_pairs() {
+        // (requested unit, parameter unit, value, converted value)
+        let cases: [(&str, &str, f64, f64); 4] = [
+            ("mm", "mm", 25.4, 25.4),
+            ("inch", "inch", 2.0, 2.0),
+            ("inch", "mm", 2.0, 50.8),
+            ("mm", "inch", 50.8, 2.0)
+        ];
+        for (requested, declared, value, expected) in cases {
+            let converted: f64 = convert(float(0, Some(declared)), json!(value), requested).as_f64().unwrap();
+            assert!((converted - expected).abs() < 1e-9, "{} {} in {}: expected {} but found {}", value, requested, declared, expected, converted);
+        }
+    }
+
+    #[test]
+    fn integers_are_rounded() {
+        // (requested unit, parameter unit, value, converted value)
+        let cases: [(&str, &str, i64, i64); 4] = [
+            ("mm", "mm", 30, 30),
+            ("inch", "inch", 3, 3),
+            ("inch", "mm", 2, 51),
+            ("mm", "inch", 30, 1)
+        ];
+        for (requested, declared, value, expected) in cases {
+            assert_eq!(convert(int(0, Some(declared)), json!(value), requested), json!(expected), "{} {} in {}", value, requested, declared);
+        }
+    }
+
+    #[test]
+    fn vectors_convert_each_component() {
+        assert_eq!(convert(vector(0, "float", Some("mm")), json!([1.0, 0.5]), "inch"), json!([25.4, 12.7]));
+        assert_eq!(convert(vector(0, "int", Some("mm")), json!([1, 2]), "inch"), json!([25, 51]));
+        assert_eq!(convert(vector(0, "int", Some("inch")), json!([1, 2]), "inch"), json!([1, 2]));
+    }
+
+    #[test]
+    fn other_parameters_are_left_alone() {
+        assert_eq!(convert(float(0, None), json!(2.0), "inch"), json!(2.0));
+        assert_eq!(convert(float(0, Some("degrees")), json!(90.0), "inch"), json!(90.0));
+        assert_eq!(convert(int(0, Some("count")), json!(4), "inch"), json!(4));
+    }
+
+    #[test]
+    fn default_unit() {
+        // Requests without a unit are in millimetres, which leaves millimetre parameters as they are
+        assert_eq!(manager::DEFAULT_LENGTH_UNIT, "mm");
+        assert_eq!(convert(float(0, Some("mm")), json!(12.5), manager::DEFAULT_LENGTH_UNIT), json!(12.5));
+        assert_eq!(convert(float(0, Some("inch")), json!(25.4), manager::DEFAULT_LENGTH_UNIT), json!(1.0));
+    }
+
+    #[test]
+    fn unknown_unit() {
+        let parameter: database::Parameter = float(0, Some("mm"));
+        assert!(matches!(convert_units(&[&parameter], &json!({ "0": 1.0 }), "cm"), Err(manager::ParameterError::UnknownUnit(unit)) if unit == "cm"));
+    }
+}
@@ -1,29 +1,18 @@
 use std::error::Error;
-use std::{fmt, fs};
+use std::{env, fmt, fs, io};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Output};
+use std::process::{Child, Command, Output, Stdio};
 use fs::read_to_string;
-use std::fs::ReadDir;
-use rocket::serde::{Serialize, Deserialize};
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ParakeetConfig {
-    pub models_path: PathBuf,
-    pub build_path: PathBuf,
-    pub database_path: PathBuf,
-    pub model_limit: i64,
-}
-
-impl ::std::default::Default for ParakeetConfig {
-    fn default() -> Self {
-        Self {
-            models_path: PathBuf::new(),
-            build_path: PathBuf::new(),
-            database_path: PathBuf::new(),
-            model_limit: 100
-        }
-    }
-}
+use std::fs::{File, OpenOptions, ReadDir};
+use std::io::{Seek, SeekFrom, Write};
+use nest::config::instance_preview_path;
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use rocket::serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use zip::write::{FileOptions, ZipWriter};
+use zip::CompressionMethod;
 
 #[derive(Debug)]
 enum InstanceError {
@@ -43,11 +32,134 @@ impl fmt::Display for InstanceError {
 impl Error for InstanceError {}
 
 #[derive(Debug)]
+pub enum ParameterError {
+    InvalidValue(String),
+    OutOfRange(String),
+    NotAllowed(String),
+    ConstraintsViolated(Vec<String>),
+    UnknownUnit(String)
+}
+
+impl ParameterError {
+    // Constraint violations are reported individually rather than as one joined message
+    pub fn messages(&self) -> Vec<String> {
+        match self {
+            ParameterError::ConstraintsViolated(messages) => messages.to_vec(),
+            _ => vec![self.to_string()]
+        }
+    }
+}
+
+impl fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParameterError::InvalidValue(name) => write!(f, "invalid value provided for '{}'", name),
+            ParameterError::OutOfRange(name) => write!(f, "value provided for '{}' is outside of its range", name),
+            ParameterError::NotAllowed(name) => write!(f, "value provided for '{}' is not one of its allowed values", name),
+            ParameterError::ConstraintsViolated(messages) => write!(f, "{}", messages.join("; ")),
+            ParameterError::UnknownUnit(unit) => write!(f, "unknown unit '{}', expected either 'mm' or 'inch'", unit)
+        }
+    }
+}
+
+impl Error for ParameterError {}
+
+// The unit values are taken to be in when a request doesn't name one
+pub const DEFAULT_LENGTH_UNIT: &str = "mm";
+
+// The length units values can be provided in, as their size in millimetres, which is also the unit of the STLs
+pub fn length_unit_scale(unit: &str) -> Option<f64> {
+    match unit {
+        "mm" => Some(1.0),
+        "inch" => Some(25.4),
+        _ => None
+    }
+}
+
+// Validate an integer against its range, snapping it to the nearest step from the lower bound
+pub fn snap_int(name: &str, value: i64, lower: i64, upper: i64, step: Option<i64>) -> Result<i64, ParameterError> {
+    if value < lower || value > upper {
+        return Err(ParameterError::OutOfRange(name.to_string()))
+    }
+
+    match step {
+        Some(step) => {
+            let snapped: i64 = lower + ((value - lower) as f64 / step as f64).round() as i64 * step;
+            if snapped > upper { Ok(snapped - step) } else { Ok(snapped) }
+        },
+        None => Ok(value)
+    }
+}
+
+// Validate a float against its range, snapping it to the nearest step from the lower bound and rounding it to a precision
+pub fn snap_float(name: &str, value: f64, lower: f64, upper: f64, step: Option<f64>, precision: Option<i64>) -> Result<f64, ParameterError> {
+    if value < lower || value > upper {
+        return Err(ParameterError::OutOfRange(name.to_string()))
+    }
+
+    let mut snapped: f64 = value;
+    if let Some(step) = step {
+        snapped = lower + ((value - lower) / step).round() * step;
+        if snapped > upper { snapped -= step }
+    }
+
+    // Without an explicit precision, round to that of the step to discard any floating point error
+    if let Some(decimals) = precision.or(step.map(decimal_places)) {
+        let factor: f64 = 10_f64.powi(decimals as i32);
+        snapped = (snapped * factor).round() / factor;
+    }
+
+    Ok(snapped)
+}
+
+fn decimal_places(value: f64) -> i64 {
+    match value.to_string().split_once('.') {
+        Some((_, fraction)) => fraction.len() as i64,
+        None => 0
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
 pub enum ParamType {
     BoolParam(bool),
     IntParam(i64),
     FloatParam(f64),
     StringParam(String),
+    VectorParam(Vec<ParamType>),
+}
+
+impl ParamType {
+    // Joins the components of a vector parameter with the provided separator
+    fn join_components(components: &Vec<ParamType>, separator: &str) -> String {
+        components.iter()
+            .map(|component| match component {
+                ParamType::BoolParam(value) => value.to_string(),
+                ParamType::IntParam(value) => value.to_string(),
+                ParamType::FloatParam(value) => value.to_string(),
+                ParamType::StringParam(value) => scad_string(value),
+                ParamType::VectorParam(values) => format!("[{}]", ParamType::join_components(values, separator))
+            })
+            .collect::<Vec<String>>()
+            .join(separator)
+    }
+}
+
+// A string literal in OpenSCAD's syntax
+fn scad_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Run a command with the given SCAD source written to its standard input, without going through a shell
+fn run_with_input(mut command: Command, input: &str) -> Result<Output, Box<dyn Error>> {
+    let mut child: Child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Dropping stdin once it has been written closes it, so that openscad sees the end of the source
+    child.stdin.take().unwrap().write_all(input.as_bytes())?;
+    Ok(child.wait_with_output()?)
 }
 
 pub struct STLInstance {
@@ -68,7 +180,9 @@ impl STLInstance {
             } else if let ParamType::BoolParam(value) = &parameter.1 {
                 parameter_string.push_str(&format!("{}={}, ", parameter.0, value))
             } else if let ParamType::StringParam(value) = &parameter.1 {
-                parameter_string.push_str(&format!("{}={}, ", parameter.0, value))
+                parameter_string.push_str(&format!("{}={}, ", parameter.0, scad_string(value)))
+            } else if let ParamType::VectorParam(values) = &parameter.1 {
+                parameter_string.push_str(&format!("{}=[{}], ", parameter.0, ParamType::join_components(values, ", ")))
             }
         }
         parameter_string = parameter_string[0..&parameter_string.len() - 2].to_string();
@@ -80,48 +194,59 @@ impl STLInstance {
         self.command_string.to_string()
     }
 
-    pub fn create_stl(&self, build_path: &PathBuf) -> Result<(), Box<dyn Error>> {
+    // `library_path` is the shared library that plume copied into the build directory of the model's version
+    pub fn create_stl(&self, build_path: &PathBuf, library_path: &PathBuf) -> Result<(), Box<dyn Error>> {
         let stl_path: PathBuf = Path::join(build_path, &self.get_identifier());
 
-        Command::new("sh")
-            .arg("-c")
-            .arg(format!("echo \"{}\" | openscad -o {} /dev/stdin", &self.command_string, stl_path.to_str().unwrap()))
-            .output()?;
+        // Models may `include` or `use` files from the shared library without a relative path
+        let mut command: Command = Command::new("openscad");
+        command
+            .env("OPENSCADPATH", library_path)
+            .arg("-o").arg(&stl_path)
+            .arg("/dev/stdin");
+        let output: Output = run_with_input(command, &self.command_string)?;
+        if !output.status.success() || !stl_path.exists() {
+            Err(InstanceError::ScadError(self.get_identifier()))?;
+        }
 
         Ok(())
     }
 
-    pub fn get_identifier(&self) -> String {
-        let mut value_string = String::new();
-        for parameter in &self.parameters {
-            if let ParamType::IntParam(value) = &parameter.1 {
-                if value_string.is_empty() {
-                    value_string.push_str(&value.to_string())
-                } else {
-                    value_string.push_str(&format!("-{}", value))
-                }
-            } else if let ParamType::FloatParam(value) = &parameter.1 {
-                if value_string.is_empty() {
-                    value_string.push_str(&value.to_string())
-                } else {
-                    value_string.push_str(&format!("-{}", value))
-                }
-            } else if let ParamType::BoolParam(value) = &parameter.1 {
-                if value_string.is_empty() {
-                    value_string.push_str(&value.to_string())
-                } else {
-                    value_string.push_str(&format!("-{}", value))
-                }
-            } else if let ParamType::StringParam(value) = &parameter.1 {
-                if value_string.is_empty() {
-                    value_string.push_str(&value)
-                } else {
-                    value_string.push_str(&format!("-{}", value))
-                }
-            }
+    // Render a preview image of the .stl instance, which must already exist, at the given size and camera
+    pub fn create_preview(&self, build_path: &PathBuf, size: (u32, u32), camera: &str) -> Result<(), Box<dyn Error>> {
+        let stl_path: PathBuf = Path::join(build_path, &self.get_identifier());
+        let preview_path: PathBuf = Path::join(build_path, &self.get_preview_identifier());
+        if let Some(preview_directory) = preview_path.parent() {
+            fs::create_dir_all(preview_directory)?;
         }
 
-        format!("stls/{}-{}_{}.stl", self.model_id, self.part_id, value_string)
+        let mut command: Command = Command::new("openscad");
+        command
+            .arg("-o").arg(&preview_path)
+            .arg(format!("--imgsize={},{}", size.0, size.1))
+            .arg(format!("--camera={}", camera))
+            .args(["--viewall", "--autocenter", "--render", "/dev/stdin"]);
+        let output: Output = run_with_input(command, &format!("import({});", scad_string(&stl_path.to_string_lossy())))?;
+        if !output.status.success() || !preview_path.exists() {
+            Err(InstanceError::ScadError(self.get_preview_identifier()))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_preview_identifier(&self) -> String {
+        instance_preview_path(&self.get_identifier())
+    }
+
+    pub fn does_preview_exist(&self, build_path: &PathBuf) -> bool {
+        Path::join(build_path, &self.get_preview_identifier()).exists()
+    }
+
+    // The path of the .stl instance within the build directory. The values are hashed rather than written
+    // into the file name, where strings could hold separators or path components, e.g. "../".
+    pub fn get_identifier(&self) -> String {
+        let values: String = serde_json::to_string(&self.parameters).unwrap();
+        format!("stls/{}-{}_{:x}.stl", self.model_id, self.part_id, Sha256::digest(values.as_bytes()))
     }
 
     pub fn get_dimensions(&self, build_path: &PathBuf) -> Result<(f64, f64, f64), Box<dyn Error>> {
@@ -172,3 +297,146 @@ impl STLInstance {
         false
     }
 }
+
+// A zip archive of a model's .stl files alongside a manifest of their parameters. The archive is written
+// to a temporary file as the parts are rendered, rather than held in memory, and the file is then streamed
+// to the client. It is unlinked as soon as it is created, so it goes away once the response is sent.
+pub struct Bundle {
+    archive: ZipWriter<File>,
+    options: FileOptions
+}
+
+impl Bundle {
+    pub fn new() -> Result<Bundle, Box<dyn Error>> {
+        let id: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(12)
+            .map(char::from)
+            .collect();
+        let path: PathBuf = env::temp_dir().join(format!("roost-bundle-{}.zip", id));
+        let file: File = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        fs::remove_file(&path)?;
+
+        Ok(Bundle {
+            archive: ZipWriter::new(file),
+            options: FileOptions::default().compression_method(CompressionMethod::Deflated)
+        })
+    }
+
+    // Parts must be added as soon as they are rendered, as rendering a later part may evict an earlier one
+    pub fn add_file(&mut self, filename: &str, stl_path: &Path) -> Result<(), Box<dyn Error>> {
+        self.archive.start_file(filename, self.options)?;
+        io::copy(&mut File::open(stl_path)?, &mut self.archive)?;
+        Ok(())
+    }
+
+    // Add the manifest and return the finished archive, ready to be read from the start
+    pub fn finish(mut self, manifest: &Value) -> Result<File, Box<dyn Error>> {
+        self.archive.start_file("manifest.json", self.options)?;
+        self.archive.write_all(serde_json::to_string_pretty(manifest)?.as_bytes())?;
+
+        let mut file: File = self.archive.finish()?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "expected {} but found {}", expected, actual);
+    }
+
+    #[test]
+    fn snap_int_steps() {
+        assert_eq!(snap_int("n", 5, 0, 10, Some(3)).unwrap(), 6);
+        assert_eq!(snap_int("n", 4, 0, 10, Some(3)).unwrap(), 3);
+        assert_eq!(snap_int("n", 7, 1, 10, Some(2)).unwrap(), 7);
+        assert_eq!(snap_int("n", 7, 0, 10, None).unwrap(), 7);
+    }
+
+    #[test]
+    fn snap_int_negative() {
+        assert_eq!(snap_int("n", -7, -10, 10, Some(5)).unwrap(), -5);
+        assert_eq!(snap_int("n", -8, -10, 10, Some(5)).unwrap(), -10);
+        assert_eq!(snap_int("n", -3, -10, -1, None).unwrap(), -3);
+    }
+
+    #[test]
+    fn snap_int_bounds() {
+        assert_eq!(snap_int("n", 0, 0, 10, Some(4)).unwrap(), 0);
+        // The nearest step past the upper bound falls back to the one below it
+        assert_eq!(snap_int("n", 10, 0, 10, Some(4)).unwrap(), 8);
+        assert_eq!(snap_int("n", 10, 0, 10, Some(5)).unwrap(), 10);
+        assert!(matches!(snap_int("n", -1, 0, 10, Some(5)), Err(ParameterError::OutOfRange(_))));
+        assert!(matches!(snap_int("n", 11, 0, 10, None), Err(ParameterError::OutOfRange(_))));
+    }
+
+    #[test]
+    fn snap_float_steps() {
+        assert_eq!(snap_float("x", 0.37, 0.0, 1.0, Some(0.1), None).unwrap(), 0.4);
+        // 0.3 / 0.1 isn't exactly 3, which rounding to the step's precision hides
+        assert_eq!(snap_float("x", 0.3, 0.0, 1.0, Some(0.1), None).unwrap(), 0.3);
+        assert_eq!(snap_float("x", 0.7, 0.1, 1.0, Some(0.2), None).unwrap(), 0.7);
+        assert_close(snap_float("x", 0.37, 0.0, 1.0, None, None).unwrap(), 0.37);
+    }
+
+    #[test]
+    fn snap_float_negative() {
+        assert_eq!(snap_float("x", -0.6, -1.0, 1.0, Some(0.25), None).unwrap(), -0.5);
+        assert_eq!(snap_float("x", -0.94, -1.0, 0.0, Some(0.1), None).unwrap(), -0.9);
+        assert_eq!(snap_float("x", -1.234, -2.0, 0.0, None, Some(1)).unwrap(), -1.2);
+    }
+
+    #[test]
+    fn snap_float_bounds() {
+        assert_eq!(snap_float("x", 0.0, 0.0, 1.0, Some(0.3), None).unwrap(), 0.0);
+        assert_eq!(snap_float("x", 1.0, 0.0, 1.0, Some(0.3), None).unwrap(), 0.9);
+        assert_eq!(snap_float("x", 1.0, 0.0, 1.0, Some(0.4), None).unwrap(), 0.8);
+        assert_eq!(snap_float("x", 1.0, 0.0, 1.0, Some(0.5), None).unwrap(), 1.0);
+        assert!(matches!(snap_float("x", -0.01, 0.0, 1.0, Some(0.1), None), Err(ParameterError::OutOfRange(_))));
+        assert!(matches!(snap_float("x", 1.01, 0.0, 1.0, None, None), Err(ParameterError::OutOfRange(_))));
+    }
+
+    #[test]
+    fn snap_float_precision() {
+        assert_eq!(snap_float("x", 1.23456, 0.0, 2.0, None, Some(2)).unwrap(), 1.23);
+        assert_eq!(snap_float("x", 1.6, 0.0, 2.0, None, Some(0)).unwrap(), 2.0);
+        // An explicit precision takes the place of the step's
+        assert_eq!(snap_float("x", 0.3, 0.0, 1.0, Some(0.25), Some(1)).unwrap(), 0.3);
+    }
+
+    #[test]
+    fn step_decimal_places() {
+        assert_eq!(decimal_places(0.1), 1);
+        assert_eq!(decimal_places(0.25), 2);
+        assert_eq!(decimal_places(-0.5), 1);
+        assert_eq!(decimal_places(5.0), 0);
+        assert_eq!(decimal_places(0.0000001), 7);
+    }
+
+    fn identifier(values: Vec<&str>) -> String {
+        let parameters: Vec<(String, ParamType)> = values.iter().enumerate()
+            .map(|(index, value)| (format!("p{}", index), ParamType::StringParam(value.to_string())))
+            .collect();
+        STLInstance {model_id: 1, part_id: 2, parameters, command_string: String::new()}.get_identifier()
+    }
+
+    #[test]
+    fn identifier_stays_in_stls() {
+        for value in ["../../etc/passwd", "a/b", "..", "a b\\c"] {
+            let path: String = identifier(vec![value]);
+            assert!(path.starts_with("stls/1-2_") && path.ends_with(".stl"), "{}", path);
+            assert!(!path["stls/".len()..].contains('/') && !path.contains(".."), "{}", path);
+        }
+    }
+
+    #[test]
+    fn identifier_distinguishes_values() {
+        assert_ne!(identifier(vec!["a-b", "c"]), identifier(vec!["a", "b-c"]));
+        assert_ne!(identifier(vec!["1"]), identifier(vec!["2"]));
+        assert_eq!(identifier(vec!["a", "b"]), identifier(vec!["a", "b"]));
+    }
+}
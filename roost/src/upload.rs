@@ -0,0 +1,197 @@
+// ***** Upload *****
+// Models uploaded by authors, either as separate files or as a ZIP archive. The files are staged in a
+// scratch directory of their own, which is then handed to `plume add`. plume checks the model by the same
+// rules it indexes every other model by, copies it into the models directory and indexes just that model.
+// The staging directory is removed again once the upload has been handled, whatever the outcome.
+
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use rocket::tokio::task;
+use serde_json::Value;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Output};
+use zip::ZipArchive;
+
+// The most that the files of an archive may add up to once they are extracted
+const MAX_EXTRACTED_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum UploadError {
+    InvalidPath(String),
+    NoInfo,
+    InvalidInfo(String),
+    InvalidArchive(String),
+    TooLarge,
+    NotOwner(String),
+    Rejected(String)
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UploadError::InvalidPath(path) => write!(f, "'{}' is not a valid file name within a model", path),
+            UploadError::NoInfo => write!(f, "the upload has no info.json"),
+            UploadError::InvalidInfo(error) => write!(f, "invalid info.json: {}", error),
+            UploadError::InvalidArchive(error) => write!(f, "invalid archive: {}", error),
+            UploadError::TooLarge => write!(f, "the upload is too large"),
+            UploadError::NotOwner(name) => write!(f, "the model '{}' can only be replaced by the author who uploaded it or an admin", name),
+            UploadError::Rejected(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl Error for UploadError {}
+
+pub struct Staging {
+    root: PathBuf
+}
+
+impl Staging {
+    pub fn new() -> io::Result<Staging> {
+        let id: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(12)
+            .map(char::from)
+            .collect();
+        let root: PathBuf = env::temp_dir().join(format!("roost-upload-{}", id));
+        fs::create_dir_all(root.join("model"))?;
+
+        Ok(Staging { root })
+    }
+
+    // Where a file of the model is staged. Files may be in subdirectories of the model, but never outside of it.
+    pub fn file_path(&self, name: &str) -> Result<PathBuf, UploadError> {
+        let relative: &Path = Path::new(name);
+        let valid: bool = !name.is_empty() && relative.components().all(|component| match component {
+            Component::Normal(part) => !part.to_string_lossy().starts_with('.'),
+            _ => false
+        });
+        if !valid {
+            return Err(UploadError::InvalidPath(name.to_string()));
+        }
+
+        let path: PathBuf = self.root.join("model").join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|error| UploadError::Rejected(error.to_string()))?;
+        }
+        Ok(path)
+    }
+
+    // Extract a ZIP archive of the model. An archive holding a single directory with the model in it is
+    // extracted as if it held the model itself.
+    pub fn extract_archive(&self, archive: &[u8]) -> Result<(), UploadError> {
+        let invalid = |error: zip::result::ZipError| UploadError::InvalidArchive(error.to_string());
+        let mut archive: ZipArchive<Cursor<&[u8]>> = ZipArchive::new(Cursor::new(archive)).map_err(invalid)?;
+
+        let mut names: Vec<PathBuf> = Vec::new();
+        let mut size: u64 = 0;
+        for index in 0..archive.len() {
+            let file = archive.by_index(index).map_err(invalid)?;
+            let name: PathBuf = file.enclosed_name()
+                .ok_or_else(|| UploadError::InvalidPath(file.name().to_string()))?
+                .to_path_buf();
+            size += file.size();
+            if !file.is_dir() {
+                names.push(name);
+            }
+        }
+        if size > MAX_EXTRACTED_SIZE {
+            return Err(UploadError::TooLarge);
+        }
+
+        let top_level: Option<PathBuf> = match names.first().and_then(|name| name.components().next()) {
+            Some(Component::Normal(directory)) => Some(PathBuf::from(directory)),
+            _ => None
+        };
+        let prefix: Option<PathBuf> = top_level.filter(|directory| names.iter().all(|name| name.starts_with(directory) && name != directory));
+
+        // The sizes an archive declares can't be trusted, so what is actually extracted is counted too
+        let mut remaining: u64 = MAX_EXTRACTED_SIZE;
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).map_err(invalid)?;
+            if file.is_dir() {
+                continue;
+            }
+            let name: PathBuf = file.enclosed_name().unwrap().to_path_buf();
+            let name: PathBuf = match &prefix {
+                Some(prefix) => name.strip_prefix(prefix).unwrap().to_path_buf(),
+                None => name
+            };
+            let path: PathBuf = self.file_path(&name.to_string_lossy())?;
+
+            let mut contents: Vec<u8> = Vec::new();
+            (&mut file).take(remaining + 1).read_to_end(&mut contents).map_err(|error| UploadError::InvalidArchive(error.to_string()))?;
+            remaining = remaining.checked_sub(contents.len() as u64).ok_or(UploadError::TooLarge)?;
+            fs::write(&path, contents).map_err(|error| UploadError::Rejected(error.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    // The name of the staged model, as given by its info.json
+    pub fn name(&self) -> Result<String, UploadError> {
+        let info_path: PathBuf = self.root.join("model").join("info.json");
+        if !info_path.is_file() {
+            return Err(UploadError::NoInfo);
+        }
+        let info: Value = serde_json::from_str(&fs::read_to_string(&info_path).map_err(|error| UploadError::InvalidInfo(error.to_string()))?)
+            .map_err(|error| UploadError::InvalidInfo(error.to_string()))?;
+        let name: String = info["name"].as_str()
+            .ok_or_else(|| UploadError::InvalidInfo(String::from("\"name\" must be a string")))?
+            .to_string();
+        if name.trim().is_empty() || name.starts_with('.') || name.contains('/') || name.contains('\\') {
+            return Err(UploadError::InvalidInfo(format!("'{}' is not a valid name", name)));
+        }
+
+        Ok(name)
+    }
+
+    // Check the model with `plume add` and add it to the models directory, returning the name of the model.
+    // plume is run from `plume_path`, which is looked up on the PATH unless it is a path.
+    pub async fn publish(&self, plume_path: &Path, category: Option<&str>) -> Result<String, UploadError> {
+        let name: String = self.name()?;
+
+        // New models are added to the models directory under a directory named after them
+        let directory: PathBuf = self.root.join(&name);
+        fs::rename(self.root.join("model"), &directory).map_err(|error| UploadError::Rejected(error.to_string()))?;
+
+        let mut command: Command = Command::new(plume_path);
+        command.arg("add").arg(&directory).arg("--json");
+        if let Some(category) = category {
+            command.arg("--category").arg(category);
+        }
+        // Adding a model renders its preview, which would hold up one of the async workers
+        let output: Output = task::spawn_blocking(move || command.output())
+            .await
+            .map_err(|error| UploadError::Rejected(error.to_string()))?
+            .map_err(|error| UploadError::Rejected(format!("could not run plume ({})", error)))?;
+
+        // plume reports the outcome as a JSON object on its last line, after any warnings, unless it failed
+        // before it got as far as adding the model
+        let stdout: String = String::from_utf8_lossy(&output.stdout).to_string();
+        let last_line: &str = stdout.lines().last().unwrap_or("");
+        let result: Value = serde_json::from_str(last_line).unwrap_or(Value::Null);
+        if output.status.success() && result["path"].is_string() {
+            return Ok(name);
+        }
+        let error: String = match result["error"].as_str() {
+            Some(error) => error.to_string(),
+            None if !last_line.is_empty() => last_line.to_string(),
+            None => String::from_utf8_lossy(&output.stderr).lines().last().unwrap_or("plume could not add the model").to_string()
+        };
+
+        // The staging directory means nothing to the author
+        Err(UploadError::Rejected(error.replace(&format!("{}/", self.root.display()), "")))
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
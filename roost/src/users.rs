@@ -0,0 +1,164 @@
+// ***** Users *****
+// Local accounts for roost. Browsers sign in with a username and password and are then kept signed in
+// by a private (encrypted) session cookie, while other clients send an API token with every request.
+// Either way the `User` request guard resolves the account, and the `Author` and `Admin` guards also
+// require the account to have at least that role.
+
+use crate::database;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::{SaltString, rand_core::OsRng};
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use rocket::http::{Cookie, CookieJar, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fmt;
+
+// The private cookie holding the id of the signed in user
+const SESSION_COOKIE: &str = "session";
+
+const MIN_USERNAME_LENGTH: usize = 3;
+const MAX_USERNAME_LENGTH: usize = 32;
+const MIN_PASSWORD_LENGTH: usize = 8;
+const TOKEN_LENGTH: usize = 40;
+
+#[derive(Debug)]
+pub enum AccountError {
+    InvalidUsername(String),
+    WeakPassword,
+    UsernameTaken(String),
+    InvalidCredentials
+}
+
+impl fmt::Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AccountError::InvalidUsername(username) => write!(f, "'{}' is not a valid username, usernames are {} to {} letters, numbers, '-' or '_'", username, MIN_USERNAME_LENGTH, MAX_USERNAME_LENGTH),
+            AccountError::WeakPassword => write!(f, "passwords must be at least {} characters long", MIN_PASSWORD_LENGTH),
+            AccountError::UsernameTaken(username) => write!(f, "the username '{}' is already taken", username),
+            AccountError::InvalidCredentials => write!(f, "incorrect username or password")
+        }
+    }
+}
+
+impl Error for AccountError {}
+
+pub fn validate_credentials(username: &str, password: &str) -> Result<(), AccountError> {
+    let length: usize = username.chars().count();
+    if length < MIN_USERNAME_LENGTH || length > MAX_USERNAME_LENGTH || !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(AccountError::InvalidUsername(username.to_string()));
+    }
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AccountError::WeakPassword);
+    }
+
+    Ok(())
+}
+
+// Passwords are hashed with Argon2 and a random salt, both of which are kept in the PHC string it returns
+pub fn hash_password(password: &str) -> Result<String, Box<dyn Error>> {
+    let salt: SaltString = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|error| error.to_string())?
+        .to_string())
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    match PasswordHash::new(password_hash) {
+        Ok(parsed) => Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok(),
+        Err(_) => false
+    }
+}
+
+// A new random API token. Tokens are long enough that a plain hash of them is safe to store.
+pub fn generate_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+pub fn sign_in(cookies: &CookieJar<'_>, user: &database::User) {
+    cookies.add_private(Cookie::new(SESSION_COOKIE, user.user_id.to_string()));
+}
+
+pub fn sign_out(cookies: &CookieJar<'_>) {
+    cookies.remove_private(Cookie::named(SESSION_COOKIE));
+}
+
+// The account a request was made with, resolved once per request and cached on it, so that the guards of
+// a route and the rate limits share a single lookup. An `Err` is a database error.
+struct ResolvedUser(Result<Option<database::User>, ()>);
+
+async fn resolve_user(request: &Request<'_>) -> ResolvedUser {
+    let db: &database::Db = match request.guard::<&database::Db>().await {
+        Outcome::Success(db) => db,
+        _ => return ResolvedUser(Err(()))
+    };
+
+    let session: Option<i64> = request.cookies()
+        .get_private(SESSION_COOKIE)
+        .and_then(|cookie| cookie.value().parse::<i64>().ok());
+    let token: Option<&str> = request.headers()
+        .get_one("Authorization")
+        .and_then(|authorization| authorization.strip_prefix("Bearer "));
+
+    let user: Result<Option<database::User>, _> = match (session, token) {
+        (Some(user_id), _) => database::get_user(db, user_id).await,
+        (None, Some(token)) => database::find_token_user(db, &hash_token(token.trim())).await,
+        (None, None) => Ok(None)
+    };
+    ResolvedUser(user.map_err(|_| ()))
+}
+
+// The user is looked up again on every request, so that role changes and removed accounts apply straight away
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for database::User {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let resolved: &ResolvedUser = request.local_cache_async(resolve_user(request)).await;
+        match &resolved.0 {
+            Ok(Some(user)) => Outcome::Success(user.clone()),
+            Ok(None) => Outcome::Failure((Status::Unauthorized, ())),
+            Err(_) => Outcome::Failure((Status::InternalServerError, ()))
+        }
+    }
+}
+
+async fn require_role(request: &Request<'_>, role: database::Role) -> Outcome<database::User, ()> {
+    match request.guard::<database::User>().await {
+        Outcome::Success(user) if user.role >= role => Outcome::Success(user),
+        Outcome::Success(_) => Outcome::Failure((Status::Forbidden, ())),
+        outcome => outcome
+    }
+}
+
+pub struct Author(pub database::User);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Author {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        require_role(request, database::Role::Author).await.map(Author)
+    }
+}
+
+pub struct Admin(pub database::User);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        require_role(request, database::Role::Admin).await.map(Admin)
+    }
+}
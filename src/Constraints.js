@@ -0,0 +1,146 @@
+// Evaluates the constraint expressions attached to a model, following the same grammar as roost
+// so that parameters can be hidden while their condition doesn't hold
+
+const OPERATORS = ["&&", "||", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "!"];
+
+const PRECEDENCE = [["||"], ["&&"], ["==", "!="], ["<", "<=", ">", ">="], ["+", "-"], ["*", "/", "%"]];
+
+function tokenize(source) {
+    let tokens = [];
+    let i = 0;
+    while (i < source.length) {
+        const c = source[i];
+        if (/\s/.test(c)) {
+            i++;
+        } else if (/[0-9.]/.test(c)) {
+            const start = i;
+            while (i < source.length && /[0-9.]/.test(source[i])) i++;
+            tokens.push({number: Number(source.slice(start, i))});
+        } else if (/[A-Za-z_]/.test(c)) {
+            const start = i;
+            while (i < source.length && /\w/.test(source[i])) i++;
+            tokens.push({identifier: source.slice(start, i)});
+        } else if (c === '"' || c === "'") {
+            const end = source.indexOf(c, i + 1);
+            if (end === -1) throw new Error("unterminated string");
+            tokens.push({string: source.slice(i + 1, end)});
+            i = end + 1;
+        } else if ("()[]".includes(c)) {
+            tokens.push({bracket: c});
+            i++;
+        } else {
+            const operator = OPERATORS.find((operator) => source.startsWith(operator, i));
+            if (!operator) throw new Error("unexpected character '" + c + "'");
+            tokens.push({operator: operator});
+            i += operator.length;
+        }
+    }
+    return tokens;
+}
+
+function parse(source) {
+    const tokens = tokenize(source);
+    let position = 0;
+
+    const binary = (level) => {
+        if (level === PRECEDENCE.length) return unary();
+        let left = binary(level + 1);
+        while (position < tokens.length && PRECEDENCE[level].includes(tokens[position].operator)) {
+            const operator = tokens[position++].operator;
+            left = {operator: operator, left: left, right: binary(level + 1)};
+        }
+        return left;
+    };
+
+    const unary = () => {
+        const token = tokens[position];
+        if (token && (token.operator === "!" || token.operator === "-")) {
+            position++;
+            return {operator: token.operator, operand: unary()};
+        }
+        let node = primary();
+        while (position < tokens.length && tokens[position].bracket === "[") {
+            position++;
+            node = {vector: node, index: binary(0)};
+            position++;
+        }
+        return node;
+    };
+
+    const primary = () => {
+        const token = tokens[position++];
+        if (!token) throw new Error("unexpected end of expression");
+        if (token.number !== undefined) return {literal: token.number};
+        if (token.string !== undefined) return {literal: token.string};
+        if (token.identifier !== undefined) {
+            if (token.identifier === "true" || token.identifier === "false") {
+                return {literal: token.identifier === "true"};
+            }
+            return {parameter: token.identifier};
+        }
+        if (token.bracket === "(") {
+            const node = binary(0);
+            position++;
+            return node;
+        }
+        throw new Error("unexpected token");
+    };
+
+    return binary(0);
+}
+
+function evaluate(node, values) {
+    if (node.literal !== undefined) return node.literal;
+    if (node.parameter !== undefined) return values[node.parameter];
+    if (node.vector !== undefined) return evaluate(node.vector, values)[evaluate(node.index, values)];
+    if (node.operand !== undefined) {
+        const operand = evaluate(node.operand, values);
+        return node.operator === "!" ? !operand : -operand;
+    }
+
+    const left = evaluate(node.left, values);
+    const right = evaluate(node.right, values);
+    switch (node.operator) {
+        case "||": return left || right;
+        case "&&": return left && right;
+        case "==": return left === right;
+        case "!=": return left !== right;
+        case "<": return left < right;
+        case "<=": return left <= right;
+        case ">": return left > right;
+        case ">=": return left >= right;
+        case "+": return left + right;
+        case "-": return left - right;
+        case "*": return left * right;
+        case "/": return left / right;
+        default: return left % right;
+    }
+}
+
+function getParameterId(parameter) {
+    return Object.values(parameter)[0].parameter_id;
+}
+
+function getParameterName(parameter) {
+    return Object.values(parameter)[0].name;
+}
+
+// Returns the ids of every parameter whose condition doesn't hold for the current values
+export function getInactiveParameters(parameters, conditions, formValues) {
+    let values = {};
+    for (let i = 0; i < parameters.length; i++) {
+        values[getParameterName(parameters[i])] = formValues[getParameterId(parameters[i])];
+    }
+
+    let inactive = [];
+    for (let i = 0; i < conditions.length; i++) {
+        try {
+            if (evaluate(parse(conditions[i].expression), values) !== true) {
+                inactive.push(conditions[i].parameter_id);
+            }
+        } catch (error) {
+            // Roost is the final judge of an expression, so an unreadable one leaves the parameter shown
+        }
+    }
+    return inactive;
+}
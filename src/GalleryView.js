@@ -7,6 +7,12 @@ import {
     CardHeader,
     CardMedia,
     CardContent,
+    Chip,
+    MenuItem,
+    Pagination,
+    Select,
+    Stack,
+    TextField,
     Typography,
     Grid
 } from '@mui/material';
@@ -31,11 +37,61 @@ function ModelCard(props) {
                     </Typography>
                 </CardContent>
             </CardActionArea>
+            {props.tags.length > 0 &&
+                <Stack direction="row" spacing={1} padding={1} flexWrap="wrap">
+                    {props.tags.map((tag) => {
+                        return <Chip key={tag} label={tag} size="small" onClick={() => props.onTagClick(tag)} />;
+                    })}
+                </Stack>
+            }
         </Card>
     );
 }
 
+// Changing any filter starts again from the first page
+function SearchBar(props) {
+    const setFilter = (filter) => {
+        props.setQuery({...props.query, ...filter, page: 1});
+    }
+
+    return (
+        <Stack direction="row" spacing={2} justifyContent="center" alignItems="center" padding={2}>
+            <TextField
+                label="Search"
+                size="small"
+                defaultValue={props.query.search}
+                onKeyDown={(event) => {
+                    if (event.key === "Enter") {
+                        setFilter({search: event.target.value});
+                    }
+                }}
+            />
+            <Select size="small" value={props.query.sort} onChange={(event) => setFilter({sort: event.target.value})}>
+                <MenuItem value="name">Name</MenuItem>
+                <MenuItem value="date">Newest</MenuItem>
+                <MenuItem value="modified">Recently updated</MenuItem>
+                <MenuItem value="popularity">Most popular</MenuItem>
+            </Select>
+            {props.query.tags.map((tag) => {
+                return (
+                    <Chip
+                        key={tag}
+                        label={tag}
+                        color="primary"
+                        onDelete={() => setFilter({tags: props.query.tags.filter((other) => other !== tag)})}
+                    />
+                );
+            })}
+        </Stack>
+    );
+}
+
 function Gallery(props) {
+    const onTagClick = (tag) => {
+        if (!props.query.tags.includes(tag)) {
+            props.setQuery({...props.query, tags: props.query.tags.concat([tag]), page: 1});
+        }
+    }
     return (
         <>
             <div className="Gallery-div">
@@ -46,13 +102,15 @@ function Gallery(props) {
                     justifyContent="center"
                 >
                     {props.models.map(model => (
-                        <Grid item>
+                        <Grid item key={model.model_id}>
                             <ModelCard
                                 id={model.model_id}
                                 name={model.name}
                                 date={model.creation_date}
                                 image_path={model.image_path}
                                 description={model.description}
+                                tags={model.tags}
+                                onTagClick={onTagClick}
                             />
                         </Grid>
                     ))}
@@ -74,7 +132,18 @@ class GalleryView extends React.Component {
                 <div className="Title-div">
                     <h1 className="Title-heading">🦜 Guillaume's Parakeet 🦜</h1>
                 </div>
-                <Gallery models={this.props.models}/>
+                <SearchBar query={this.props.query} setQuery={this.props.setQuery}/>
+                <Gallery models={this.props.models.models} query={this.props.query} setQuery={this.props.setQuery}/>
+                {this.props.models.total > this.props.models.per_page &&
+                    <Stack alignItems="center" padding={2}>
+                        <Pagination
+                            count={Math.ceil(this.props.models.total / this.props.models.per_page)}
+                            page={this.props.query.page}
+                            onChange={(_event, page) => this.props.setQuery({...this.props.query, page: page})}
+                            color="primary"
+                        />
+                    </Stack>
+                }
             </div>
         );
     }
@@ -1,5 +1,5 @@
 import React from "react";
-import {Button, Container, ListItem, Pagination, Stack, Typography} from "@mui/material";
+import {Button, Container, ImageList, ImageListItem, ListItem, MenuItem, Pagination, Select, Stack, Typography} from "@mui/material";
 
 export function TimeSinceUpdate(props) {
     const updateHours = (props.updateTime.getHours().toString().length === 2 ? props.updateTime.getHours() : "0" + props.updateTime.getHours());
@@ -12,10 +12,26 @@ export function TimeSinceUpdate(props) {
 
 export function ModelDimensions(props) {
     return (
-        <Typography>X: {props.dimensions[0]}, Y: {props.dimensions[1]}, Z: {props.dimensions[2]} (mm)</Typography>
+        <Typography>X: {props.dimensions[0]}, Y: {props.dimensions[1]}, Z: {props.dimensions[2]} ({props.unit})</Typography>
     )
 }
 
+export function ModelGallery(props) {
+    if (props.images.length > 0) {
+        return (
+            <ImageList cols={3} gap={8}>
+                {props.images.map((image) => {
+                    return (
+                        <ImageListItem key={image}>
+                            <img src={image} alt={props.name} loading="lazy" />
+                        </ImageListItem>
+                    );
+                })}
+            </ImageList>
+        )
+    }
+}
+
 export function PartPagination(props) {
     if (props.numberOfParts !== 1) {
         return (
@@ -27,7 +43,33 @@ export function PartPagination(props) {
                 pt={1}
             >
                 <Typography><b>Parts: </b></Typography>
-                <Pagination count={props.numberOfParts} onChange={props.handleChange} color="primary" />
+                <Pagination count={props.numberOfParts} page={props.page} onChange={props.handleChange} color="primary" />
+            </Stack>
+        )
+    }
+}
+
+// Every version of a model is a model of its own, so picking a version switches to that model
+export function VersionSelect(props) {
+    if (props.versions.length > 1) {
+        return (
+            <Stack
+                direction="row"
+                alignItems="center"
+                justifyContent="center"
+                spacing={2}
+                pt={1}
+            >
+                <Typography><b>Version: </b></Typography>
+                <Select size="small" value={props.modelId} onChange={props.handleChange}>
+                    {props.versions.map((version) => {
+                        return (
+                            <MenuItem key={version.model_id} value={version.model_id}>
+                                {version.version} ({version.creation_date}){version.current ? "" : " - no longer indexed"}
+                            </MenuItem>
+                        );
+                    })}
+                </Select>
             </Stack>
         )
     }
@@ -40,3 +82,31 @@ export function ButtonDownload(props) {
        </Button>
     )
 }
+
+// Links to a rendered image of the part as configured, which can be shared where the viewer can't be shown
+export function ButtonPreviewImage(props) {
+    const query = new URLSearchParams({values: JSON.stringify(props.values)});
+    return (
+        <Button variant="outlined" href={'/api/preview/' + props.modelId + '/' + props.partId + '?' + query} target="_blank">
+            Preview image
+        </Button>
+    )
+}
+
+export function ButtonShare(props) {
+    return (
+        <Button variant="outlined" onClick={props.onClick}>
+            Share
+        </Button>
+    )
+}
+
+export function ButtonDownloadBundle(props) {
+    if (props.numberOfParts !== 1) {
+        return (
+            <Button variant="outlined" onClick={props.onClick}>
+                Download all parts (ZIP)
+            </Button>
+        )
+    }
+}
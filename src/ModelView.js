@@ -1,4 +1,4 @@
-import { useParams } from 'react-router-dom';
+import { useNavigate, useParams } from 'react-router-dom';
 import { RenderParam } from './ParameterElements';
 import {
     RenderSTL,
@@ -6,7 +6,8 @@ import {
     Axes,
     GridPlane,
 } from "./CanvasElements";
-import {ButtonDownload, ModelDimensions, PartPagination, TimeSinceUpdate} from "./ModelInfo"
+import {getInactiveParameters} from "./Constraints";
+import {ButtonDownload, ButtonDownloadBundle, ButtonPreviewImage, ButtonShare, ModelDimensions, ModelGallery, PartPagination, TimeSinceUpdate, VersionSelect} from "./ModelInfo"
 import {
     CheckAutoRotate,
     CheckAxes,
@@ -32,24 +33,50 @@ import {
 import {Canvas} from "@react-three/fiber";
 import {AccessTime, Straighten} from "@mui/icons-material";
 
+// Parameters arrive in the author's order, consecutive parameters of the same group are shown under its heading
+function groupParameters(parameters) {
+    let groups = [];
+    for (let i = 0; i < parameters.length; i++) {
+        const group = Object.values(parameters[i])[0].group;
+        if (groups.length === 0 || groups[groups.length - 1].name !== group) {
+            groups.push({name: group, parameters: []});
+        }
+        groups[groups.length - 1].parameters.push(parameters[i]);
+    }
+    return groups;
+}
+
 function ParamView(props) {
+    const parameters = props.part.parameters.filter((parameter) => {
+        return !props.inactive.includes(Object.values(parameter)[0].parameter_id);
+    });
+
     return (
         <>
             <div style={{width: "100%"}}>
                 <Typography variant="h6" className="Module-subtitle"><b>{props.part.name}</b></Typography>
-                <div style={{width: "100%"}}>
-                    {props.part.parameters.map((parameter) => {
-                        return (
-                            RenderParam(parameter, props.formValues, props.setFormValues, props.onStlChange)
-                        );
-                    })}
-                </div>
+                {groupParameters(parameters).map((group, index) => {
+                    return (
+                        <div style={{width: "100%"}} key={index}>
+                            {group.name && <Typography variant="subtitle1"><b>{group.name}</b></Typography>}
+                            {group.parameters.map((parameter) => {
+                                const description = Object.values(parameter)[0].description;
+                                return (
+                                    <div key={Object.values(parameter)[0].parameter_id}>
+                                        {RenderParam(parameter, props.formValues, props.setFormValues, props.onStlChange)}
+                                        {description && <Typography variant="caption" color="text.secondary">{description}</Typography>}
+                                    </div>
+                                );
+                            })}
+                        </div>
+                    );
+                })}
             </div>
         </>
     )
 }
 
-function genStl(model_id, part_id, formValues, setStl, setDimensions, setCameraReset=null, ) {
+function genStl(model_id, part_id, formValues, setStl, setDimensions, setUnit, setErrors, setCameraReset=null, ) {
     const url = '/api/generate/' + model_id + '/' + part_id;
     const request = new Request(url, {
         method: 'POST',
@@ -62,46 +89,134 @@ function genStl(model_id, part_id, formValues, setStl, setDimensions, setCameraR
     fetch(request)
         .then(resp => resp.json())
         .then(json => {
+            if (json["errors"]) {
+                setErrors(json["errors"]);
+                return;
+            }
+            setErrors([]);
             setStl(json["filename"]);
             setDimensions(json["dimensions"])
+            setUnit(json["unit"]);
             if (setCameraReset) {
                 setCameraReset(true)
             }
         });
 }
 
+function genBundle(model, committedValues) {
+    let values = {shared: committedValues[0]};
+    for (let i = 0; i < model.parts.length; i++) {
+        values[model.parts[i].part_id] = committedValues[i];
+    }
+
+    const url = '/api/bundle/' + model.model_id;
+    const request = new Request(url, {
+        method: 'POST',
+        body: JSON.stringify(values),
+        headers: new Headers({
+            'Content-Type': 'application/json'
+        })
+    });
+
+    fetch(request)
+        .then(resp => {
+            if (!resp.ok) {
+                return resp.json().then(json => alert(json["errors"].join("\n")));
+            }
+            return resp.blob().then(blob => {
+                const link = document.createElement('a');
+                link.href = URL.createObjectURL(blob);
+                link.download = model.name + '.zip';
+                link.click();
+                URL.revokeObjectURL(link.href);
+            });
+        });
+}
+
+// Save the values of a part and copy a link that opens the model with them
+function shareConfig(model, part_id, values) {
+    const request = new Request('/api/configs', {
+        method: 'POST',
+        body: JSON.stringify({model_id: model.model_id, part_id: part_id, values: values}),
+        headers: new Headers({
+            'Content-Type': 'application/json'
+        })
+    });
+
+    fetch(request)
+        .then(resp => resp.json())
+        .then(json => {
+            if (json["errors"]) {
+                alert(json["errors"].join("\n"));
+                return;
+            }
+            const link = window.location.origin + '/' + model.model_id + '?config=' + json["config_id"];
+            navigator.clipboard.writeText(link)
+                .then(() => alert("Copied a link to this configuration: " + link))
+                .catch(() => alert("Link to this configuration: " + link));
+        });
+}
+
+function getDefaultValues(parameters) {
+    let current_values = {};
+    for (let j = 0; j < parameters.length; j++) {
+        if (parameters[j].IntRange) {
+            current_values[parameters[j].IntRange.parameter_id] = parameters[j].IntRange.default_value;
+        } else if (parameters[j].FloatRange) {
+            current_values[parameters[j].FloatRange.parameter_id] = parameters[j].FloatRange.default_value;
+        } else if (parameters[j].StringLength) {
+            current_values[parameters[j].StringLength.parameter_id] = parameters[j].StringLength.default_value;
+        } else if (parameters[j].Bool) {
+            current_values[parameters[j].Bool.parameter_id] = parameters[j].Bool.default_value;
+        } else if (parameters[j].IntList) {
+            current_values[parameters[j].IntList.parameter_id] = parameters[j].IntList.default_value;
+        } else if (parameters[j].FloatList) {
+            current_values[parameters[j].FloatList.parameter_id] = parameters[j].FloatList.default_value;
+        } else if (parameters[j].Vector) {
+            current_values[parameters[j].Vector.parameter_id] = parameters[j].Vector.default_value;
+        } else {
+            current_values[parameters[j].StringList.parameter_id] = parameters[j].StringList.default_value;
+        }
+    }
+    return current_values;
+}
+
 function ModelView(props) {
+    // Shared parameters are part of every part's values, but are only displayed once
+    const shared_values = getDefaultValues(props.model.parameters);
     let default_values = [];
     for (let i = 0; i < props.model.parts.length; i++) {
-        let current_values = {};
-        let parameters = props.model.parts[i].parameters;
-        for (let j = 0; j < parameters.length; j++) {
-            if (parameters[j].IntRange) {
-                current_values[parameters[j].IntRange.parameter_id] = parameters[j].IntRange.default_value;
-            } else if (parameters[j].FloatRange) {
-                current_values[parameters[j].FloatRange.parameter_id] = parameters[j].FloatRange.default_value;
-            } else if (parameters[j].StringLength) {
-                current_values[parameters[j].StringLength.parameter_id] = parameters[j].StringLength.default_value;
-            } else if (parameters[j].Bool) {
-                current_values[parameters[j].Bool.parameter_id] = parameters[j].Bool.default_value;
-            } else if (parameters[j].IntList) {
-                current_values[parameters[j].IntList.parameter_id] = parameters[j].IntList.default_value;
-            } else if (parameters[j].FloatList) {
-                current_values[parameters[j].FloatList.parameter_id] = parameters[j].FloatList.default_value;
-            } else {
-                current_values[parameters[j].StringList.parameter_id] = parameters[j].StringList.default_value;
+        default_values.push({
+            ...shared_values,
+            ...getDefaultValues(props.model.parts[i].parameters)
+        });
+    }
+
+    // A saved config replaces the defaults of its part, and of the shared parameters
+    let initial_values = default_values;
+    let initial_part = 0;
+    if (props.config) {
+        initial_part = Math.max(props.model.parts.findIndex((part) => part.part_id === props.config.part_id), 0);
+        initial_values = default_values.map((values, index) => {
+            let restored = {...values};
+            for (const [parameter_id, value] of Object.entries(props.config.values)) {
+                if (parameter_id in shared_values || index === initial_part) {
+                    restored[parameter_id] = value;
+                }
             }
-        }
-        default_values.push(current_values);
+            return restored;
+        });
     }
 
-    const [partIndex, setPartIndex] = useState(0);
+    const [partIndex, setPartIndex] = useState(initial_part);
 
-    const [formValues, setFormValues] = useState(default_values[partIndex]);
-    const [committedValues, setCommittedValues] = useState(default_values);
+    const [formValues, setFormValues] = useState(initial_values[partIndex]);
+    const [committedValues, setCommittedValues] = useState(initial_values);
 
     const [stl, setStl] = useState("");
     const [dimensions, setDimensions] = useState([0.0, 0.0, 0.0])
+    const [unit, setUnit] = useState("mm");
+    const [errors, setErrors] = useState([]);
 
     const [autoRotate, setAutoRotate] = useState(true);
     const [axes, setAxes] = useState(false);
@@ -111,13 +226,24 @@ function ModelView(props) {
 
     const [updateTime, setUpdateTime] = useState((new Date()));
 
+    const [versions, setVersions] = useState([]);
+    const navigate = useNavigate();
+
+    useEffect(() => {
+        fetch('/api/models/' + props.model.model_id + '/versions')
+            .then(resp => resp.json())
+            .then(json => setVersions(json));
+    }, [props.model.model_id])
+
     useEffect(() => {
         genStl(
             props.model.model_id,
             props.model.parts[partIndex].part_id,
             committedValues[partIndex],
             setStl,
-            setDimensions
+            setDimensions,
+            setUnit,
+            setErrors
         );
         setUpdateTime((new Date()));
     }, [committedValues])
@@ -129,6 +255,8 @@ function ModelView(props) {
             committedValues[partIndex],
             setStl,
             setDimensions,
+            setUnit,
+            setErrors,
             setCameraReset
         );
         setUpdateTime((new Date()));
@@ -139,15 +267,37 @@ function ModelView(props) {
         setPartIndex(value - 1);
     }
 
+    const onVersionChange = (event) => {
+        navigate('/' + event.target.value);
+    }
+
     const onStlChange = (index, value) => {
         let newValues = [...committedValues]
-        newValues.splice(partIndex, 1, {
-            ...newValues[partIndex],
-            [index]: value
-        })
+        if (index in shared_values) {
+            newValues = newValues.map((values) => ({...values, [index]: value}));
+        } else {
+            newValues.splice(partIndex, 1, {
+                ...newValues[partIndex],
+                [index]: value
+            })
+        }
         setCommittedValues(newValues);
     }
 
+    const inactive = getInactiveParameters(
+        props.model.parameters.concat(props.model.parts[partIndex].parameters),
+        props.model.conditions.concat(props.model.parts[partIndex].conditions),
+        formValues
+    );
+
+    const onShare = () => {
+        shareConfig(props.model, props.model.parts[partIndex].part_id, committedValues[partIndex]);
+    }
+
+    const onBundleDownload = () => {
+        genBundle(props.model, committedValues);
+    }
+
     const onAutoRotateChange = (event) => {
         setAutoRotate(event.target.checked);
     }
@@ -197,23 +347,59 @@ function ModelView(props) {
                             <ListItem>
                                 <div>
                                     <Typography className="Description-text">{props.model.description}</Typography>
+                                    <ModelGallery images={props.model.gallery} name={props.model.name} />
+                                    <VersionSelect
+                                        versions={versions}
+                                        modelId={props.model.model_id}
+                                        handleChange={onVersionChange}
+                                    />
                                     {/* TODO: Actually implement multi-part support */}
                                     <PartPagination
                                         numberOfParts={props.model.parts.length}
+                                        page={partIndex + 1}
                                         handleChange={onPartChange}
                                     />
                                 </div>
                             </ListItem>
                             <Divider />
+                            {props.model.parameters.length > 0 &&
+                                <>
+                                    <ListItem>
+                                        <ParamView
+                                            part={{name: "Shared", parameters: props.model.parameters}}
+                                            formValues={formValues}
+                                            setFormValues={setFormValues}
+                                            inactive={inactive}
+                                            onStlChange={onStlChange}
+                                        />
+                                    </ListItem>
+                                    <Divider />
+                                </>
+                            }
                             <ListItem>
                                 <ParamView
                                     part={props.model.parts[partIndex]}
                                     formValues={formValues}
                                     setFormValues={setFormValues}
+                                    inactive={inactive}
                                     onStlChange={onStlChange}
                                 />
                             </ListItem>
                             <Divider />
+                            {errors.length > 0 &&
+                                <>
+                                    <ListItem>
+                                        <div>
+                                            {errors.map((error) => {
+                                                return (
+                                                    <Typography color="error" key={error}>{error}</Typography>
+                                                );
+                                            })}
+                                        </div>
+                                    </ListItem>
+                                    <Divider />
+                                </>
+                            }
                             <ListItem>
                                 <Stack
                                     direction="row"
@@ -248,14 +434,26 @@ function ModelView(props) {
                                             <Straighten />
                                         </ListItemIcon>
                                         <ListItemText>
-                                            <ModelDimensions dimensions={dimensions} />
+                                            <ModelDimensions dimensions={dimensions} unit={unit} />
                                         </ListItemText>
                                     </ListItem>
                                 </List>
                             </ListItem>
                             <Divider />
                             <ListItem>
-                                <ButtonDownload stl={stl}/>
+                                <Stack direction="row" spacing={2}>
+                                    <ButtonDownload stl={stl}/>
+                                    <ButtonPreviewImage
+                                        modelId={props.model.model_id}
+                                        partId={props.model.parts[partIndex].part_id}
+                                        values={committedValues[partIndex]}
+                                    />
+                                    <ButtonShare onClick={onShare} />
+                                    <ButtonDownloadBundle
+                                        numberOfParts={props.model.parts.length}
+                                        onClick={onBundleDownload}
+                                    />
+                                </Stack>
                             </ListItem>
                         </List>
                     </Paper>
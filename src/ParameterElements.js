@@ -10,12 +10,19 @@ import {
     Checkbox
 } from "@mui/material";
 
+// Parameters are shown by their label, along with their unit if they declare one
+function label(parameter) {
+    const name = parameter.label || parameter.name;
+    return parameter.unit ? name + " (" + parameter.unit + ")" : name;
+}
+
 // A slider, input combination that represents the IntRangeRestriction
 export function IntRange(parameter, formValues, setFormValues, onStlChange) {
-    const name = parameter.name;
+    const name = label(parameter);
     const default_value = parameter.default_value;
     const minimum = parameter.lower;
     const maximum = parameter.upper;
+    const step = parameter.step || 1;
     const index = parameter.parameter_id;
 
     let value = formValues[index];
@@ -79,7 +86,7 @@ export function IntRange(parameter, formValues, setFormValues, onStlChange) {
             <Grid item xs>
                 <Slider
                     value={typeof value === 'number' ? value : 0}
-                    step={1}
+                    step={step}
                     min={minimum}
                     max={maximum}
                     onChange={handleSliderChange}
@@ -94,8 +101,7 @@ export function IntRange(parameter, formValues, setFormValues, onStlChange) {
                     onChange={handleInputChange}
                     onBlur={handleBlur}
                     inputProps={{
-                        // FIXME: Dynamically change the step, maybe `floor(max/10)`
-                        step: 1,
+                        step: step,
                         min: minimum,
                         max: maximum,
                         type: 'number',
@@ -111,10 +117,11 @@ export function IntRange(parameter, formValues, setFormValues, onStlChange) {
 
 // A slider, input combination that represents the FloatRangeRestriction
 export function FloatRange(parameter, formValues, setFormValues, onStlChange) {
-    const name = parameter.name;
+    const name = label(parameter);
     const default_value = parameter.default_value;
     const minimum = parameter.lower;
     const maximum = parameter.upper;
+    const step = parameter.step || 0.1;
     const index = parameter.parameter_id;
 
     let value = formValues[index];
@@ -173,7 +180,7 @@ export function FloatRange(parameter, formValues, setFormValues, onStlChange) {
             <Grid item xs>
                 <Slider
                     value={typeof value === 'number' ? value : 0}
-                    step={0.1}
+                    step={step}
                     min={minimum}
                     max={maximum}
                     onChange={handleSliderChange}
@@ -188,8 +195,7 @@ export function FloatRange(parameter, formValues, setFormValues, onStlChange) {
                     onChange={handleInputChange}
                     onBlur={handleBlur}
                     inputProps={{
-                        // FIXME: Dynamically change the step, maybe `floor(max/10)`
-                        step: 0.05,
+                        step: step,
                         min: minimum,
                         max: maximum,
                         type: 'number',
@@ -204,7 +210,7 @@ export function FloatRange(parameter, formValues, setFormValues, onStlChange) {
 
 // A TextField used to represent the StringLengthRestriction
 export function StringLength(parameter, formValues, setFormValues, onStlChange) {
-    const name = parameter.name;
+    const name = label(parameter);
     const length = parameter.length;
     const index = parameter.parameter_id;
 
@@ -284,7 +290,7 @@ function ListRestriction(name, default_value, allowed, index, formValues, setFor
 
 // A ListRestriction for integer parameters
 export function IntList(parameter, formValues, setFormValues, onStlChange) {
-    const name = parameter.name;
+    const name = label(parameter);
     const default_value = parameter.default_value;
     const allowed = parameter.items;
     const index = parameter.parameter_id;
@@ -294,7 +300,7 @@ export function IntList(parameter, formValues, setFormValues, onStlChange) {
 
 // A ListRestriction for float parameters
 export function FloatList(parameter, formValues, setFormValues, onStlChange) {
-    const name = parameter.name;
+    const name = label(parameter);
     const default_value = parameter.default_value;
     const allowed = parameter.items;
     const index = parameter.parameter_id;
@@ -304,7 +310,7 @@ export function FloatList(parameter, formValues, setFormValues, onStlChange) {
 
 // A ListRestriction for string parameters
 export function StringList(parameter, formValues, setFormValues, onStlChange) {
-    const name = parameter.name;
+    const name = label(parameter);
     const default_value = parameter.default_value;
     const allowed = parameter.items;
     const index = parameter.parameter_id;
@@ -314,7 +320,7 @@ export function StringList(parameter, formValues, setFormValues, onStlChange) {
 
 // A checkbox that represents the BoolRestriction
 export function BoolCheck(parameter, formValues, setFormValues, onStlChange) {
-    const name = parameter.name;
+    const name = label(parameter);
     const default_value = parameter.default_value;
     const index = parameter.parameter_id;
 
@@ -343,6 +349,61 @@ export function BoolCheck(parameter, formValues, setFormValues, onStlChange) {
     );
 }
 
+// A row of inputs that represents a vector parameter, one for each component
+export function VectorInputs(parameter, formValues, setFormValues, onStlChange) {
+    const name = label(parameter);
+    const step = (parameter.element_type === "int" ? 1 : 0.05);
+    const index = parameter.parameter_id;
+
+    let value = formValues[index];
+
+    const handleInputChange = (position) => (event) => {
+        if (event.target.value === "") {
+            return;
+        }
+
+        let component = Number(event.target.value);
+        component = Math.min(Math.max(component, parameter.lower[position]), parameter.upper[position]);
+        if (parameter.element_type === "int") {
+            component = Math.round(component);
+        }
+
+        let newValue = [...value];
+        newValue.splice(position, 1, component);
+        setFormValues({
+            ...formValues,
+            [index]: newValue
+        });
+        onStlChange(index, newValue);
+    };
+
+    return (
+        <Grid container spacing={2} alignItems="center" className="Parameter-grid">
+            <Grid item>
+                <Typography>
+                    {name}
+                </Typography>
+            </Grid>
+            {value.map((component, position) => (
+                <Grid item key={position}>
+                    <Input
+                        value={component}
+                        size="small"
+                        onChange={handleInputChange(position)}
+                        inputProps={{
+                            step: step,
+                            min: parameter.lower[position],
+                            max: parameter.upper[position],
+                            type: 'number',
+                        }}
+                        style={{width: 60}}
+                    />
+                </Grid>
+            ))}
+        </Grid>
+    );
+}
+
 export function RenderParam(parameter, formValues, setFormValues, onStlChange) {
     if (parameter.IntRange) {
         return IntRange(parameter.IntRange, formValues, setFormValues, onStlChange)
@@ -358,5 +419,7 @@ export function RenderParam(parameter, formValues, setFormValues, onStlChange) {
         return StringList(parameter.StringList, formValues, setFormValues, onStlChange)
     } else if (parameter.Bool) {
         return BoolCheck(parameter.Bool, formValues, setFormValues, onStlChange)
+    } else if (parameter.Vector) {
+        return VectorInputs(parameter.Vector, formValues, setFormValues, onStlChange)
     }
 }
@@ -1,6 +1,6 @@
 import React, {useEffect, useState} from 'react';
 import ReactDOM from 'react-dom';
-import {BrowserRouter, Route, Routes, useParams} from 'react-router-dom';
+import {BrowserRouter, Route, Routes, useParams, useSearchParams} from 'react-router-dom';
 import './index.css';
 import GalleryView from './GalleryView';
 import {createTheme, CssBaseline, ThemeProvider} from "@mui/material";
@@ -29,8 +29,17 @@ const dark = {
 
 const RenderGalleryView = () => {
     const [models, setModels] = useState();
+    const [query, setQuery] = useState({search: "", tags: [], sort: "name", page: 1});
     useEffect(() => {
-        const request = new Request("/api/models", {
+        let params = new URLSearchParams({sort: query.sort, page: query.page});
+        if (query.search) {
+            params.append("search", query.search);
+        }
+        for (let i = 0; i < query.tags.length; i++) {
+            params.append("tag", query.tags[i]);
+        }
+
+        const request = new Request("/api/models?" + params.toString(), {
             method: 'GET',
             headers: new Headers({
                 'Content-Type': 'application/json'
@@ -42,32 +51,46 @@ const RenderGalleryView = () => {
         }
 
         getModels();
-    }, []);
+    }, [query]);
 
-    return models && <GalleryView models={models}/>
+    return models && <GalleryView models={models} query={query} setQuery={setQuery}/>
 
 }
 
 const RenderModelView = () => {
     const {id} = useParams();
+    const [searchParams] = useSearchParams();
     const [model, setModel] = useState();
+    const [config, setConfig] = useState();
 
     useEffect(() => {
-        const request = new Request("/api/models/" + id, {
-            method: 'GET',
-            headers: new Headers({
-                'Content-Type': 'application/json'
-            })
-        });
-
         const getModel = async () => {
+            // A saved config opens the version of the model it was saved with, or the current one
+            let modelId = id;
+            const configId = searchParams.get("config");
+            if (configId) {
+                const response = await fetch("/api/configs/" + configId);
+                if (response.ok) {
+                    const saved = await response.json();
+                    setConfig(saved);
+                    modelId = saved.model_id;
+                }
+            }
+
+            const request = new Request("/api/models/" + modelId, {
+                method: 'GET',
+                headers: new Headers({
+                    'Content-Type': 'application/json'
+                })
+            });
             setModel(await (await fetch(request)).json());
         }
 
         getModel();
-    }, [])
+    }, [id])
 
-    return model && <ModelView model={model}/>
+    // Switching to another version of the model starts over with that version's parameters
+    return model && <ModelView model={model} config={config} key={model.model_id}/>
 }
 
 ReactDOM.render(